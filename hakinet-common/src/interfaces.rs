@@ -0,0 +1,231 @@
+//! Network interface enumeration shared by the capture tool and the scanner
+
+use anyhow::{Result, anyhow};
+use pnet::datalink::{self, NetworkInterface};
+use pnet::ipnetwork::IpNetwork;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+/// A network interface with its addresses and capabilities
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InterfaceInfo {
+    pub name: String,
+    pub description: Option<String>,
+    pub flags: InterfaceFlags,
+    pub addresses: Vec<InterfaceAddress>,
+    pub mac: Option<String>,
+    pub mtu: Option<u32>,
+    /// Link types a capture on this interface can use; only known to tools
+    /// that open it with libpcap
+    pub link_types: Vec<InterfaceLinkType>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InterfaceFlags {
+    pub up: bool,
+    pub running: bool,
+    pub loopback: bool,
+    pub wireless: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InterfaceAddress {
+    pub addr: IpAddr,
+    pub netmask: Option<IpAddr>,
+    pub broadcast: Option<IpAddr>,
+    pub prefix: Option<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InterfaceLinkType {
+    pub id: i32,
+    pub name: String,
+    pub description: Option<String>,
+}
+
+impl InterfaceInfo {
+    /// An interface known only by name, to be filled in by the caller
+    pub fn named(name: &str) -> Self {
+        InterfaceInfo {
+            name: name.to_string(),
+            description: None,
+            flags: InterfaceFlags::default(),
+            addresses: Vec::new(),
+            mac: None,
+            mtu: None,
+            link_types: Vec::new(),
+        }
+    }
+
+    /// The address to send from when reaching `destination` through this
+    /// interface: one on the same subnet if there is one, otherwise the first
+    /// of the same family
+    pub fn source_addr_for(&self, destination: IpAddr) -> Option<IpAddr> {
+        let same_family = || {
+            self.addresses
+                .iter()
+                .filter(move |address| address.addr.is_ipv4() == destination.is_ipv4())
+        };
+        same_family()
+            .find(|address| {
+                address
+                    .network()
+                    .is_some_and(|net| net.contains(destination))
+            })
+            .or_else(|| same_family().find(|address| !is_link_local(address.addr)))
+            .or_else(|| same_family().next())
+            .map(|address| address.addr)
+    }
+
+    /// The first IPv4 subnet on this interface, e.g. for host discovery
+    pub fn ipv4_network(&self) -> Option<IpNetwork> {
+        self.addresses
+            .iter()
+            .filter(|address| address.addr.is_ipv4())
+            .find_map(InterfaceAddress::network)
+            .map(|net| IpNetwork::new(net.network(), net.prefix()).unwrap_or(net))
+    }
+}
+
+impl InterfaceAddress {
+    pub fn network(&self) -> Option<IpNetwork> {
+        IpNetwork::new(self.addr, self.prefix?).ok()
+    }
+}
+
+impl From<NetworkInterface> for InterfaceInfo {
+    fn from(interface: NetworkInterface) -> Self {
+        let addresses = interface
+            .ips
+            .iter()
+            .map(|ip| InterfaceAddress {
+                addr: ip.ip(),
+                netmask: Some(ip.mask()),
+                broadcast: match ip {
+                    IpNetwork::V4(net) if interface.is_broadcast() && net.prefix() < 31 => {
+                        Some(IpAddr::V4(net.broadcast()))
+                    }
+                    _ => None,
+                },
+                prefix: Some(ip.prefix()),
+            })
+            .collect();
+
+        InterfaceInfo {
+            description: Some(interface.description.clone()).filter(|d| !d.is_empty()),
+            flags: InterfaceFlags {
+                up: interface.is_up(),
+                running: is_running(&interface),
+                loopback: interface.is_loopback(),
+                wireless: sysfs(&interface.name, "wireless").is_some(),
+            },
+            addresses,
+            mac: interface
+                .mac
+                .filter(|mac| !mac.is_zero())
+                .map(|mac| mac.to_string()),
+            mtu: sysfs(&interface.name, "mtu").and_then(|mtu| mtu.trim().parse().ok()),
+            link_types: Vec::new(),
+            name: interface.name,
+        }
+    }
+}
+
+/// Enumerate the system's network interfaces
+pub fn list_interfaces() -> Vec<InterfaceInfo> {
+    datalink::interfaces()
+        .into_iter()
+        .map(InterfaceInfo::from)
+        .collect()
+}
+
+/// Look up an interface by name
+pub fn find_interface(name: &str) -> Result<InterfaceInfo> {
+    list_interfaces()
+        .into_iter()
+        .find(|interface| interface.name == name)
+        .ok_or_else(|| anyhow!("No such interface: {}", name))
+}
+
+#[cfg(unix)]
+fn is_running(interface: &NetworkInterface) -> bool {
+    interface.is_running()
+}
+
+#[cfg(not(unix))]
+fn is_running(interface: &NetworkInterface) -> bool {
+    interface.is_up()
+}
+
+/// Read an attribute of the interface from /sys/class/net. Only Linux has it;
+/// elsewhere MTU and the wireless flag are left unknown.
+fn sysfs(name: &str, attribute: &str) -> Option<String> {
+    let path = std::path::Path::new("/sys/class/net")
+        .join(name)
+        .join(attribute);
+    if path.is_dir() {
+        return Some(String::new());
+    }
+    std::fs::read_to_string(path).ok()
+}
+
+fn is_link_local(addr: IpAddr) -> bool {
+    match addr {
+        IpAddr::V4(v4) => v4.is_link_local(),
+        IpAddr::V6(v6) => v6.is_unicast_link_local(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(addr: &str, prefix: u8) -> InterfaceAddress {
+        InterfaceAddress {
+            addr: addr.parse().unwrap(),
+            netmask: None,
+            broadcast: None,
+            prefix: Some(prefix),
+        }
+    }
+
+    #[test]
+    fn test_source_addr_for() {
+        let mut interface = InterfaceInfo::named("eth0");
+        interface.addresses = vec![
+            address("fe80::1", 64),
+            address("10.0.0.5", 24),
+            address("192.168.1.20", 24),
+            address("2001:db8::5", 64),
+        ];
+
+        let source = |destination: &str| interface.source_addr_for(destination.parse().unwrap());
+        assert_eq!(source("192.168.1.1"), Some("192.168.1.20".parse().unwrap()));
+        assert_eq!(source("8.8.8.8"), Some("10.0.0.5".parse().unwrap()));
+        assert_eq!(
+            source("2606:4700::1111"),
+            Some("2001:db8::5".parse().unwrap())
+        );
+        assert_eq!(
+            interface.ipv4_network(),
+            Some("10.0.0.0/24".parse().unwrap())
+        );
+
+        assert_eq!(
+            InterfaceInfo::named("lo").source_addr_for("127.0.0.1".parse().unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_list_interfaces() {
+        // Every system has at least a loopback interface
+        let interfaces = list_interfaces();
+        assert!(!interfaces.is_empty());
+        assert!(find_interface("no-such-interface0").is_err());
+
+        let json = serde_json::to_string(&interfaces[0]).unwrap();
+        let parsed: InterfaceInfo = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, interfaces[0]);
+    }
+}
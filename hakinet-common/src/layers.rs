@@ -0,0 +1,1018 @@
+//! Typed protocol layers produced by packet dissection
+
+use pnet::packet::ip::IpNextHeaderProtocol;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// One decoded protocol layer, outermost first in `PacketInfo::layers`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "layer", rename_all = "snake_case")]
+pub enum Layer {
+    Ethernet(EthernetLayer),
+    LinuxSll(LinuxSllLayer),
+    Loopback(LoopbackLayer),
+    Vlan(VlanLayer),
+    Arp(ArpLayer),
+    Ipv4(Ipv4Layer),
+    Ipv6(Ipv6Layer),
+    Tcp(TcpLayer),
+    Udp(UdpLayer),
+    Icmp(IcmpLayer),
+    Icmpv6(Icmpv6Layer),
+    Dns(DnsLayer),
+    Http(HttpLayer),
+    Tls(TlsLayer),
+}
+
+impl Layer {
+    /// Short protocol name as shown in the packet list
+    pub fn name(&self) -> &'static str {
+        match self {
+            Layer::Ethernet(_) => "Ethernet",
+            Layer::LinuxSll(sll) if sll.version == 2 => "Linux SLL2",
+            Layer::LinuxSll(_) => "Linux SLL",
+            Layer::Loopback(_) => "Loopback",
+            Layer::Vlan(vlan) if vlan.is_service_tag() => "802.1ad",
+            Layer::Vlan(_) => "802.1Q",
+            Layer::Arp(_) => "ARP",
+            Layer::Ipv4(_) => "IPv4",
+            Layer::Ipv6(_) => "IPv6",
+            Layer::Tcp(_) => "TCP",
+            Layer::Udp(_) => "UDP",
+            Layer::Icmp(_) => "ICMP",
+            Layer::Icmpv6(_) => "ICMPv6",
+            Layer::Dns(_) => "DNS",
+            Layer::Http(_) => "HTTP",
+            Layer::Tls(_) => "TLS",
+        }
+    }
+
+    /// One-line description of the layer for the info column
+    pub fn info(&self) -> Option<String> {
+        match self {
+            Layer::Ethernet(eth) => Some(format!("EtherType: 0x{:04x}", eth.ethertype)),
+            Layer::LinuxSll(sll) => Some(sll.summary()),
+            Layer::Loopback(lo) => Some(format!("Family: {}", lo.family_name())),
+            Layer::Vlan(vlan) => Some(format!("VLAN {}", vlan.id)),
+            Layer::Arp(arp) => Some(arp.summary()),
+            Layer::Ipv4(ip) => Some(format!("Protocol: {}", protocol_name(ip.protocol))),
+            Layer::Ipv6(ip) => Some(format!("Next header: {}", protocol_name(ip.upper_protocol))),
+            Layer::Tcp(tcp) => Some(tcp.summary()),
+            Layer::Udp(udp) => Some(format!("Len={}", udp.payload_len)),
+            Layer::Icmp(icmp) => Some(icmp.summary()),
+            Layer::Icmpv6(icmp) => Some(icmp.summary()),
+            Layer::Dns(dns) => Some(dns.summary()),
+            Layer::Http(http) => Some(http.summary()),
+            Layer::Tls(tls) => Some(tls.summary()),
+        }
+    }
+}
+
+fn protocol_name(protocol: u8) -> String {
+    IpNextHeaderProtocol::new(protocol).to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EthernetLayer {
+    pub src: String,
+    pub dst: String,
+    pub ethertype: u16,
+}
+
+/// Linux cooked capture header, written for the `any` device and
+/// interfaces without a link-layer header
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LinuxSllLayer {
+    /// 1 for LINKTYPE_LINUX_SLL, 2 for LINKTYPE_LINUX_SLL2
+    pub version: u8,
+    /// Direction of the packet relative to the capturing host
+    pub packet_type: u16,
+    /// ARPHRD_ type of the interface
+    pub arphrd_type: u16,
+    /// Link-layer source address, when the interface has one
+    pub address: Option<String>,
+    /// EtherType of the payload
+    pub protocol: u16,
+    /// Interface index, only present in SLL2
+    pub interface_index: Option<u32>,
+}
+
+impl LinuxSllLayer {
+    pub fn packet_type_name(&self) -> &'static str {
+        match self.packet_type {
+            0 => "Unicast to us",
+            1 => "Broadcast",
+            2 => "Multicast",
+            3 => "Unicast to another host",
+            4 => "Sent by us",
+            _ => "Unknown",
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        let mut summary = self.packet_type_name().to_string();
+        if let Some(address) = &self.address {
+            summary.push_str(&format!(" from {}", address));
+        }
+        if let Some(index) = self.interface_index {
+            summary.push_str(&format!(" on ifindex {}", index));
+        }
+        summary
+    }
+}
+
+/// BSD loopback (DLT_NULL / DLT_LOOP) address family header
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoopbackLayer {
+    pub family: u32,
+}
+
+impl LoopbackLayer {
+    pub fn family_name(&self) -> &'static str {
+        match self.family {
+            2 => "IPv4",
+            // AF_INET6 differs between the BSDs, Darwin and Linux
+            10 | 24 | 28 | 30 => "IPv6",
+            _ => "Unknown",
+        }
+    }
+}
+
+/// IEEE 802.1Q customer tag or 802.1ad (QinQ) service tag
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VlanLayer {
+    /// Tag protocol identifier the tag was found under
+    pub tpid: u16,
+    pub id: u16,
+    pub priority: u8,
+    pub drop_eligible: bool,
+    /// EtherType of the encapsulated payload
+    pub ethertype: u16,
+}
+
+impl VlanLayer {
+    /// Outer QinQ tags use 0x88a8, or 0x9100 on older switches
+    pub fn is_service_tag(&self) -> bool {
+        matches!(self.tpid, 0x88a8 | 0x9100)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArpLayer {
+    pub hardware_type: u16,
+    pub protocol_type: u16,
+    /// 1 = request, 2 = reply
+    pub operation: u16,
+    pub sender_mac: String,
+    pub sender_ip: Ipv4Addr,
+    pub target_mac: String,
+    pub target_ip: Ipv4Addr,
+}
+
+impl ArpLayer {
+    /// tcpdump-style description, e.g. `Who has 10.0.0.1? Tell 10.0.0.2`
+    pub fn summary(&self) -> String {
+        match self.operation {
+            1 if self.sender_ip.is_unspecified() => {
+                format!("Who has {}? (ARP probe)", self.target_ip)
+            }
+            1 if self.sender_ip == self.target_ip => {
+                format!("Gratuitous ARP for {}", self.sender_ip)
+            }
+            1 => format!("Who has {}? Tell {}", self.target_ip, self.sender_ip),
+            2 => format!("{} is at {}", self.sender_ip, self.sender_mac),
+            op => format!("Opcode: {}", op),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ipv4Layer {
+    pub src: Ipv4Addr,
+    pub dst: Ipv4Addr,
+    pub header_length: u8,
+    pub dscp: u8,
+    pub ecn: u8,
+    pub total_length: u16,
+    pub identification: u16,
+    pub dont_fragment: bool,
+    pub more_fragments: bool,
+    pub fragment_offset: u16,
+    pub ttl: u8,
+    pub protocol: u8,
+    pub checksum: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ipv6Layer {
+    pub src: Ipv6Addr,
+    pub dst: Ipv6Addr,
+    pub traffic_class: u8,
+    pub flow_label: u32,
+    pub payload_length: u16,
+    pub next_header: u8,
+    pub hop_limit: u8,
+    /// Extension headers between the fixed header and the upper layer
+    pub extensions: Vec<Ipv6Extension>,
+    /// Protocol carried after the last extension header
+    pub upper_protocol: u8,
+}
+
+/// IPv6 extension header, lengths are in bytes including the header itself
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Ipv6Extension {
+    HopByHop {
+        length: u16,
+    },
+    Routing {
+        length: u16,
+        routing_type: u8,
+        segments_left: u8,
+    },
+    Fragment {
+        offset: u16,
+        more_fragments: bool,
+        identification: u32,
+    },
+    DestinationOptions {
+        length: u16,
+    },
+}
+
+/// TCP control bits
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TcpFlags {
+    pub fin: bool,
+    pub syn: bool,
+    pub rst: bool,
+    pub psh: bool,
+    pub ack: bool,
+    pub urg: bool,
+    pub ece: bool,
+    pub cwr: bool,
+}
+
+impl TcpFlags {
+    pub fn from_bits(bits: u8) -> Self {
+        TcpFlags {
+            fin: bits & 0x01 != 0,
+            syn: bits & 0x02 != 0,
+            rst: bits & 0x04 != 0,
+            psh: bits & 0x08 != 0,
+            ack: bits & 0x10 != 0,
+            urg: bits & 0x20 != 0,
+            ece: bits & 0x40 != 0,
+            cwr: bits & 0x80 != 0,
+        }
+    }
+
+    /// Flag names in Wireshark order, e.g. `SYN, ACK`
+    pub fn names(&self) -> String {
+        let flags = [
+            (self.fin, "FIN"),
+            (self.syn, "SYN"),
+            (self.rst, "RST"),
+            (self.psh, "PSH"),
+            (self.ack, "ACK"),
+            (self.urg, "URG"),
+            (self.ece, "ECE"),
+            (self.cwr, "CWR"),
+        ];
+        flags
+            .iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, name)| *name)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TcpOption {
+    Nop,
+    Mss { value: u16 },
+    WindowScale { shift: u8 },
+    SackPermitted,
+    Sack { blocks: Vec<(u32, u32)> },
+    Timestamps { value: u32, echo_reply: u32 },
+    Unknown { code: u8, length: u8 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TcpLayer {
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub seq: u32,
+    pub ack: u32,
+    pub header_length: u8,
+    pub flags: TcpFlags,
+    pub window: u16,
+    pub checksum: u16,
+    pub urgent_pointer: u16,
+    pub options: Vec<TcpOption>,
+    pub payload_len: usize,
+}
+
+impl TcpLayer {
+    fn summary(&self) -> String {
+        let mut summary = format!("[{}] Seq={}", self.flags.names(), self.seq);
+        if self.flags.ack {
+            summary.push_str(&format!(" Ack={}", self.ack));
+        }
+        summary.push_str(&format!(" Win={} Len={}", self.window, self.payload_len));
+        summary
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UdpLayer {
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub length: u16,
+    pub checksum: u16,
+    pub payload_len: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IcmpLayer {
+    pub icmp_type: u8,
+    pub code: u8,
+    pub checksum: u16,
+    /// Identifier and sequence number of echo messages
+    pub identifier: Option<u16>,
+    pub sequence: Option<u16>,
+}
+
+impl IcmpLayer {
+    pub fn type_name(&self) -> Option<&'static str> {
+        match self.icmp_type {
+            0 => Some("Echo reply"),
+            3 => Some("Destination unreachable"),
+            4 => Some("Source quench"),
+            5 => Some("Redirect"),
+            8 => Some("Echo request"),
+            9 => Some("Router advertisement"),
+            10 => Some("Router solicitation"),
+            11 => Some("Time exceeded"),
+            12 => Some("Parameter problem"),
+            13 => Some("Timestamp request"),
+            14 => Some("Timestamp reply"),
+            _ => None,
+        }
+    }
+
+    fn summary(&self) -> String {
+        let mut summary = match self.type_name() {
+            Some(name) => name.to_string(),
+            None => format!("Type {}", self.icmp_type),
+        };
+        if let (Some(id), Some(seq)) = (self.identifier, self.sequence) {
+            summary.push_str(&format!(" id={} seq={}", id, seq));
+        } else if self.code != 0 {
+            summary.push_str(&format!(" (code {})", self.code));
+        }
+        summary
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Icmpv6Layer {
+    pub icmp_type: u8,
+    pub code: u8,
+    pub checksum: u16,
+    /// Identifier and sequence number of echo messages
+    pub identifier: Option<u16>,
+    pub sequence: Option<u16>,
+    /// Target address of neighbor discovery and redirect messages
+    pub target: Option<Ipv6Addr>,
+    /// Source or target link-layer address option
+    pub link_layer_address: Option<String>,
+    /// Router lifetime in seconds of router advertisements
+    pub router_lifetime: Option<u16>,
+    /// Prefix information options of router advertisements
+    pub prefixes: Vec<String>,
+    /// Message flags, e.g. `router`, `solicited`, `override`, `managed`
+    pub flags: Vec<String>,
+}
+
+impl Icmpv6Layer {
+    pub fn type_name(&self) -> Option<&'static str> {
+        match self.icmp_type {
+            1 => Some("Destination unreachable"),
+            2 => Some("Packet too big"),
+            3 => Some("Time exceeded"),
+            4 => Some("Parameter problem"),
+            128 => Some("Echo request"),
+            129 => Some("Echo reply"),
+            130 => Some("Multicast listener query"),
+            131 => Some("Multicast listener report"),
+            132 => Some("Multicast listener done"),
+            133 => Some("Router solicitation"),
+            134 => Some("Router advertisement"),
+            135 => Some("Neighbor solicitation"),
+            136 => Some("Neighbor advertisement"),
+            137 => Some("Redirect"),
+            143 => Some("Multicast listener report v2"),
+            _ => None,
+        }
+    }
+
+    fn summary(&self) -> String {
+        let mut summary = match self.type_name() {
+            Some(name) => name.to_string(),
+            None => format!("Type {}", self.icmp_type),
+        };
+        if let (Some(id), Some(seq)) = (self.identifier, self.sequence) {
+            summary.push_str(&format!(" id={} seq={}", id, seq));
+        }
+        if let Some(target) = self.target {
+            let verb = if self.icmp_type == 136 { "is" } else { "for" };
+            summary.push_str(&format!(" {} {}", verb, target));
+        }
+        if let Some(lladdr) = &self.link_layer_address {
+            let verb = if self.icmp_type == 136 { "at" } else { "from" };
+            summary.push_str(&format!(" {} {}", verb, lladdr));
+        }
+        if !self.prefixes.is_empty() {
+            summary.push_str(&format!(" prefix {}", self.prefixes.join(", ")));
+        }
+        if !self.flags.is_empty() {
+            summary.push_str(&format!(" [{}]", self.flags.join(", ")));
+        }
+        summary
+    }
+}
+
+/// DNS message carried over UDP or TCP
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DnsLayer {
+    pub id: u16,
+    pub response: bool,
+    pub opcode: u8,
+    pub flags: DnsFlags,
+    /// Response code, extended to 12 bits when an OPT record is present
+    pub rcode: u16,
+    pub questions: Vec<DnsQuestion>,
+    pub answers: Vec<DnsRecord>,
+    pub authority_count: u16,
+    pub additional_count: u16,
+    pub edns: Option<Edns>,
+}
+
+impl DnsLayer {
+    pub fn rcode_name(&self) -> String {
+        match self.rcode {
+            0 => "NOERROR".to_string(),
+            1 => "FORMERR".to_string(),
+            2 => "SERVFAIL".to_string(),
+            3 => "NXDOMAIN".to_string(),
+            4 => "NOTIMP".to_string(),
+            5 => "REFUSED".to_string(),
+            9 => "NOTAUTH".to_string(),
+            16 => "BADVERS".to_string(),
+            other => format!("RCODE{}", other),
+        }
+    }
+
+    /// Console summary, e.g. `query A example.com` or `response A example.com 1.2.3.4`
+    pub fn summary(&self) -> String {
+        let mut summary = if self.response {
+            "response".to_string()
+        } else {
+            "query".to_string()
+        };
+        if self.response && self.rcode != 0 {
+            summary.push_str(&format!(" {}", self.rcode_name()));
+        }
+        for question in &self.questions {
+            summary.push_str(&format!(
+                " {} {}",
+                dns_type_name(question.qtype),
+                question.name
+            ));
+        }
+        for answer in &self.answers {
+            summary.push_str(&format!(" {}", answer.data));
+        }
+        summary
+    }
+}
+
+/// Header bits other than QR, opcode and rcode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DnsFlags {
+    pub authoritative: bool,
+    pub truncated: bool,
+    pub recursion_desired: bool,
+    pub recursion_available: bool,
+    pub authentic_data: bool,
+    pub checking_disabled: bool,
+}
+
+impl DnsFlags {
+    pub fn from_bits(bits: u16) -> Self {
+        DnsFlags {
+            authoritative: bits & 0x0400 != 0,
+            truncated: bits & 0x0200 != 0,
+            recursion_desired: bits & 0x0100 != 0,
+            recursion_available: bits & 0x0080 != 0,
+            authentic_data: bits & 0x0020 != 0,
+            checking_disabled: bits & 0x0010 != 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DnsQuestion {
+    pub name: String,
+    pub qtype: u16,
+    pub qclass: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DnsRecord {
+    pub name: String,
+    pub rtype: u16,
+    pub class: u16,
+    pub ttl: u32,
+    pub data: DnsRecordData,
+}
+
+/// Decoded resource record data
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DnsRecordData {
+    A {
+        address: Ipv4Addr,
+    },
+    Aaaa {
+        address: Ipv6Addr,
+    },
+    Cname {
+        name: String,
+    },
+    Ns {
+        name: String,
+    },
+    Ptr {
+        name: String,
+    },
+    Mx {
+        preference: u16,
+        exchange: String,
+    },
+    Txt {
+        strings: Vec<String>,
+    },
+    Srv {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: String,
+    },
+    /// Any other type, kept as its length only
+    Other {
+        length: u16,
+    },
+}
+
+impl std::fmt::Display for DnsRecordData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DnsRecordData::A { address } => write!(f, "{}", address),
+            DnsRecordData::Aaaa { address } => write!(f, "{}", address),
+            DnsRecordData::Cname { name } => write!(f, "CNAME {}", name),
+            DnsRecordData::Ns { name } => write!(f, "NS {}", name),
+            DnsRecordData::Ptr { name } => write!(f, "PTR {}", name),
+            DnsRecordData::Mx {
+                preference,
+                exchange,
+            } => write!(f, "MX {} {}", preference, exchange),
+            DnsRecordData::Txt { strings } => write!(f, "TXT {:?}", strings.join("")),
+            DnsRecordData::Srv {
+                priority,
+                weight,
+                port,
+                target,
+            } => write!(f, "SRV {} {} {} {}", priority, weight, port, target),
+            DnsRecordData::Other { length } => write!(f, "<{} bytes>", length),
+        }
+    }
+}
+
+/// EDNS(0) parameters from the OPT pseudo-record
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Edns {
+    pub udp_payload_size: u16,
+    pub version: u8,
+    pub dnssec_ok: bool,
+}
+
+/// Mnemonic for a DNS record type, e.g. `AAAA` or `TYPE65`
+pub fn dns_type_name(rtype: u16) -> String {
+    match rtype {
+        1 => "A",
+        2 => "NS",
+        5 => "CNAME",
+        6 => "SOA",
+        12 => "PTR",
+        15 => "MX",
+        16 => "TXT",
+        28 => "AAAA",
+        33 => "SRV",
+        41 => "OPT",
+        43 => "DS",
+        46 => "RRSIG",
+        48 => "DNSKEY",
+        64 => "SVCB",
+        65 => "HTTPS",
+        255 => "ANY",
+        other => return format!("TYPE{}", other),
+    }
+    .to_string()
+}
+
+/// HTTP/1.x request or response head, decoded from the reassembled stream
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HttpLayer {
+    /// Protocol version from the start line, e.g. `HTTP/1.1`
+    pub version: String,
+    pub method: Option<String>,
+    pub uri: Option<String>,
+    pub status: Option<u16>,
+    pub reason: Option<String>,
+    pub host: Option<String>,
+    pub content_type: Option<String>,
+    pub content_length: Option<u64>,
+    pub chunked: bool,
+    pub headers: Vec<HttpHeader>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HttpHeader {
+    pub name: String,
+    pub value: String,
+}
+
+impl HttpLayer {
+    pub fn is_request(&self) -> bool {
+        self.method.is_some()
+    }
+
+    /// Value of the first header called `name`, ignoring case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|h| h.name.eq_ignore_ascii_case(name))
+            .map(|h| h.value.as_str())
+    }
+
+    /// Wireshark-style info, e.g. `GET /index.html HTTP/1.1 (example.com)`
+    /// or `HTTP/1.1 200 OK (text/html, 1256 bytes)`
+    pub fn summary(&self) -> String {
+        let mut summary = match (&self.method, self.status) {
+            (Some(method), _) => format!(
+                "{} {} {}",
+                method,
+                self.uri.as_deref().unwrap_or("/"),
+                self.version
+            ),
+            (None, Some(status)) => format!(
+                "{} {} {}",
+                self.version,
+                status,
+                self.reason.as_deref().unwrap_or("")
+            )
+            .trim_end()
+            .to_string(),
+            (None, None) => self.version.clone(),
+        };
+
+        let mut details = Vec::new();
+        if self.is_request() {
+            details.extend(self.host.clone());
+        }
+        details.extend(self.content_type.clone());
+        if let Some(length) = self.content_length {
+            details.push(format!("{} bytes", length));
+        } else if self.chunked {
+            details.push("chunked".to_string());
+        }
+        if !details.is_empty() {
+            summary.push_str(&format!(" ({})", details.join(", ")));
+        }
+        summary
+    }
+}
+
+/// TLS handshake hello, decoded from the reassembled stream
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TlsLayer {
+    /// Version from the record header
+    pub record_version: u16,
+    pub handshake: TlsHandshake,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TlsHandshake {
+    ClientHello(TlsClientHello),
+    ServerHello(TlsServerHello),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TlsClientHello {
+    /// Legacy version field of the hello
+    pub version: u16,
+    /// Versions offered in the supported_versions extension
+    pub supported_versions: Vec<u16>,
+    pub cipher_suites: Vec<u16>,
+    /// Extension types in the order sent
+    pub extensions: Vec<u16>,
+    pub server_name: Option<String>,
+    pub alpn: Vec<String>,
+    pub supported_groups: Vec<u16>,
+    pub ec_point_formats: Vec<u8>,
+    pub signature_algorithms: Vec<u16>,
+    /// JA3 string and its MD5 hash
+    pub ja3: String,
+    pub ja3_hash: String,
+    pub ja4: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TlsServerHello {
+    /// Legacy version field of the hello
+    pub version: u16,
+    /// Version chosen through the supported_versions extension (TLS 1.3)
+    pub selected_version: Option<u16>,
+    pub cipher_suite: u16,
+    pub extensions: Vec<u16>,
+    pub alpn: Option<String>,
+    /// JA3S string and its MD5 hash
+    pub ja3s: String,
+    pub ja3s_hash: String,
+}
+
+impl TlsLayer {
+    /// Console summary, e.g. `Client Hello SNI=example.com ALPN=h2 JA4=t13d...`
+    pub fn summary(&self) -> String {
+        match &self.handshake {
+            TlsHandshake::ClientHello(hello) => {
+                let mut summary = "Client Hello".to_string();
+                if let Some(name) = &hello.server_name {
+                    summary.push_str(&format!(" SNI={}", name));
+                }
+                if !hello.alpn.is_empty() {
+                    summary.push_str(&format!(" ALPN={}", hello.alpn.join(",")));
+                }
+                summary.push_str(&format!(" JA4={}", hello.ja4));
+                summary
+            }
+            TlsHandshake::ServerHello(hello) => {
+                let version = hello.selected_version.unwrap_or(hello.version);
+                let mut summary = format!(
+                    "Server Hello {} {}",
+                    tls_version_name(version),
+                    tls_cipher_suite_name(hello.cipher_suite)
+                );
+                if let Some(alpn) = &hello.alpn {
+                    summary.push_str(&format!(" ALPN={}", alpn));
+                }
+                summary.push_str(&format!(" JA3S={}", hello.ja3s_hash));
+                summary
+            }
+        }
+    }
+}
+
+/// Name of a TLS protocol version, e.g. `TLS 1.3`
+pub fn tls_version_name(version: u16) -> String {
+    match version {
+        0x0300 => "SSL 3.0".to_string(),
+        0x0301 => "TLS 1.0".to_string(),
+        0x0302 => "TLS 1.1".to_string(),
+        0x0303 => "TLS 1.2".to_string(),
+        0x0304 => "TLS 1.3".to_string(),
+        other => format!("0x{:04x}", other),
+    }
+}
+
+/// IANA name of the common cipher suites, hex for the rest
+pub fn tls_cipher_suite_name(suite: u16) -> String {
+    match suite {
+        0x1301 => "TLS_AES_128_GCM_SHA256",
+        0x1302 => "TLS_AES_256_GCM_SHA384",
+        0x1303 => "TLS_CHACHA20_POLY1305_SHA256",
+        0xc02b => "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256",
+        0xc02c => "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384",
+        0xc02f => "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256",
+        0xc030 => "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384",
+        0xcca8 => "TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256",
+        0xcca9 => "TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256",
+        0x009c => "TLS_RSA_WITH_AES_128_GCM_SHA256",
+        0x009d => "TLS_RSA_WITH_AES_256_GCM_SHA384",
+        0x002f => "TLS_RSA_WITH_AES_128_CBC_SHA",
+        0x0035 => "TLS_RSA_WITH_AES_256_CBC_SHA",
+        other => return format!("0x{:04x}", other),
+    }
+    .to_string()
+}
+
+/// Flat view of a layer stack: addresses, ports and the top protocol
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LayerSummary {
+    pub protocol: Option<String>,
+    pub src_addr: Option<String>,
+    pub dst_addr: Option<String>,
+    pub src_port: Option<u16>,
+    pub dst_port: Option<u16>,
+    /// VLAN IDs, outermost tag first
+    pub vlan_ids: Vec<u16>,
+    pub info: Option<String>,
+}
+
+impl LayerSummary {
+    pub fn from_layers(layers: &[Layer]) -> Self {
+        let mut summary = LayerSummary::default();
+
+        for layer in layers {
+            match layer {
+                Layer::Ipv4(ip) => summary.set_addrs(ip.src.into(), ip.dst.into()),
+                Layer::Ipv6(ip) => summary.set_addrs(ip.src.into(), ip.dst.into()),
+                Layer::Vlan(vlan) => summary.vlan_ids.push(vlan.id),
+                Layer::Arp(arp) => summary.set_addrs(arp.sender_ip.into(), arp.target_ip.into()),
+                Layer::Tcp(tcp) => summary.set_ports(tcp.src_port, tcp.dst_port),
+                Layer::Udp(udp) => summary.set_ports(udp.src_port, udp.dst_port),
+                _ => {}
+            }
+        }
+
+        if let Some(top) = layers.last() {
+            summary.protocol = Some(top.name().to_string());
+            summary.info = top.info();
+        }
+
+        summary
+    }
+
+    fn set_addrs(&mut self, src: IpAddr, dst: IpAddr) {
+        // Tunnelled packets report their innermost addresses
+        self.src_addr = Some(src.to_string());
+        self.dst_addr = Some(dst.to_string());
+    }
+
+    fn set_ports(&mut self, src: u16, dst: u16) {
+        self.src_port = Some(src);
+        self.dst_port = Some(dst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tcp_layer(flags: u8) -> TcpLayer {
+        TcpLayer {
+            src_port: 51000,
+            dst_port: 443,
+            seq: 1000,
+            ack: 2000,
+            header_length: 20,
+            flags: TcpFlags::from_bits(flags),
+            window: 64240,
+            checksum: 0,
+            urgent_pointer: 0,
+            options: vec![TcpOption::Mss { value: 1460 }],
+            payload_len: 0,
+        }
+    }
+
+    #[test]
+    fn test_tcp_flags() {
+        assert_eq!(TcpFlags::from_bits(0x12).names(), "SYN, ACK");
+        assert_eq!(TcpFlags::from_bits(0x11).names(), "FIN, ACK");
+        assert_eq!(TcpFlags::from_bits(0).names(), "");
+    }
+
+    #[test]
+    fn test_summary_from_layers() {
+        let layers = vec![
+            Layer::Ipv4(Ipv4Layer {
+                src: "10.0.0.1".parse().unwrap(),
+                dst: "10.0.0.2".parse().unwrap(),
+                header_length: 20,
+                dscp: 0,
+                ecn: 0,
+                total_length: 40,
+                identification: 1,
+                dont_fragment: true,
+                more_fragments: false,
+                fragment_offset: 0,
+                ttl: 64,
+                protocol: 6,
+                checksum: 0,
+            }),
+            Layer::Tcp(tcp_layer(0x02)),
+        ];
+
+        let summary = LayerSummary::from_layers(&layers);
+        assert_eq!(summary.protocol.as_deref(), Some("TCP"));
+        assert_eq!(summary.src_addr.as_deref(), Some("10.0.0.1"));
+        assert_eq!(summary.dst_port, Some(443));
+        assert_eq!(
+            summary.info.as_deref(),
+            Some("[SYN] Seq=1000 Win=64240 Len=0")
+        );
+    }
+
+    #[test]
+    fn test_layer_serialization() {
+        let json = serde_json::to_value(Layer::Tcp(tcp_layer(0x12))).unwrap();
+        assert_eq!(json["layer"], "tcp");
+        assert_eq!(json["flags"]["syn"], true);
+        assert_eq!(json["options"][0]["kind"], "mss");
+        assert_eq!(json["options"][0]["value"], 1460);
+    }
+
+    #[test]
+    fn test_arp_summary() {
+        let mut arp = ArpLayer {
+            hardware_type: 1,
+            protocol_type: 0x0800,
+            operation: 1,
+            sender_mac: "02:00:00:00:00:01".to_string(),
+            sender_ip: Ipv4Addr::new(10, 0, 0, 2),
+            target_mac: "00:00:00:00:00:00".to_string(),
+            target_ip: Ipv4Addr::new(10, 0, 0, 1),
+        };
+        assert_eq!(arp.summary(), "Who has 10.0.0.1? Tell 10.0.0.2");
+
+        arp.operation = 2;
+        assert_eq!(arp.summary(), "10.0.0.2 is at 02:00:00:00:00:01");
+
+        arp.operation = 1;
+        arp.target_ip = arp.sender_ip;
+        assert_eq!(arp.summary(), "Gratuitous ARP for 10.0.0.2");
+    }
+
+    #[test]
+    fn test_dns_summary() {
+        let mut dns = DnsLayer {
+            id: 0xbeef,
+            response: false,
+            opcode: 0,
+            flags: DnsFlags::from_bits(0x0100),
+            rcode: 0,
+            questions: vec![DnsQuestion {
+                name: "example.com".to_string(),
+                qtype: 1,
+                qclass: 1,
+            }],
+            answers: Vec::new(),
+            authority_count: 0,
+            additional_count: 0,
+            edns: None,
+        };
+        assert!(dns.flags.recursion_desired);
+        assert_eq!(dns.summary(), "query A example.com");
+
+        dns.response = true;
+        dns.answers.push(DnsRecord {
+            name: "example.com".to_string(),
+            rtype: 1,
+            class: 1,
+            ttl: 300,
+            data: DnsRecordData::A {
+                address: Ipv4Addr::new(1, 2, 3, 4),
+            },
+        });
+        assert_eq!(dns.summary(), "response A example.com 1.2.3.4");
+
+        dns.answers.clear();
+        dns.rcode = 3;
+        assert_eq!(dns.summary(), "response NXDOMAIN A example.com");
+    }
+
+    #[test]
+    fn test_icmpv6_summary() {
+        let advert = Icmpv6Layer {
+            icmp_type: 136,
+            code: 0,
+            checksum: 0,
+            identifier: None,
+            sequence: None,
+            target: Some("fe80::1".parse().unwrap()),
+            link_layer_address: Some("02:00:00:00:00:01".to_string()),
+            router_lifetime: None,
+            prefixes: Vec::new(),
+            flags: vec!["router".to_string(), "solicited".to_string()],
+        };
+        assert_eq!(
+            advert.summary(),
+            "Neighbor advertisement is fe80::1 at 02:00:00:00:00:01 [router, solicited]"
+        );
+    }
+}
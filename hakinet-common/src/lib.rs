@@ -1,13 +1,19 @@
 //! Common utilities and types for the Hakinet network tools suite
 
+pub mod interfaces;
+pub mod layers;
 pub mod network;
 pub mod output;
+pub mod signal;
 pub mod types;
 pub mod utils;
 
 pub use types::*;
+pub use interfaces::*;
+pub use layers::*;
 pub use network::*;
 pub use output::*;
+pub use signal::*;
 pub use utils::*;
 
 use colored::*;
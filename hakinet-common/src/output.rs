@@ -48,7 +48,7 @@ impl OutputWriter {
                 writer.write_all(json.as_bytes()).await?;
                 self.packet_count += 1;
 
-                if self.packet_count % 10 == 0 {
+                if self.packet_count.is_multiple_of(10) {
                     writer.flush().await?;
                 }
             }
@@ -110,6 +110,9 @@ impl ScanOutputWriter {
         let mut output = String::new();
         
         output.push_str(&format!("\n{}\n", "🎯 Scan Results Summary".bright_green().bold()));
+        if results.interrupted {
+            output.push_str(&format!("{}\n", "⚠️  Scan interrupted, results are partial".bright_yellow()));
+        }
         output.push_str(&format!("Duration: {} seconds\n", results.duration()));
         output.push_str(&format!("Total hosts: {}\n", results.total_hosts));
         output.push_str(&format!("Hosts up: {}\n", results.hosts_up));
@@ -176,12 +179,13 @@ impl ScanOutputWriter {
         let mut xml = String::new();
         xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
         xml.push_str("<scan_results>\n");
-        xml.push_str(&format!("  <summary>\n"));
+        xml.push_str("  <summary>\n");
         xml.push_str(&format!("    <duration>{}</duration>\n", results.duration()));
         xml.push_str(&format!("    <total_hosts>{}</total_hosts>\n", results.total_hosts));
         xml.push_str(&format!("    <hosts_up>{}</hosts_up>\n", results.hosts_up));
         xml.push_str(&format!("    <total_ports_scanned>{}</total_ports_scanned>\n", results.total_ports_scanned));
         xml.push_str(&format!("    <open_ports_found>{}</open_ports_found>\n", results.open_ports_found));
+        xml.push_str(&format!("    <interrupted>{}</interrupted>\n", results.interrupted));
         xml.push_str("  </summary>\n");
         
         xml.push_str("  <hosts>\n");
@@ -272,8 +276,7 @@ pub fn print_port_result(host: &str, port: &PortInfo) {
     };
 
     println!(
-        "{} {}:{}/{} {}{}",
-        "🎯".to_string(),
+        "🎯 {}:{}/{} {}{}",
         host.bright_cyan(),
         port.port.to_string().bright_white(),
         port.protocol.bright_magenta(),
@@ -0,0 +1,156 @@
+//! Ctrl+C / SIGTERM handling shared by the Hakinet tools
+
+use log::warn;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// A termination request received from the user or the system
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// SIGINT, usually Ctrl+C
+    Interrupt,
+    /// SIGTERM, e.g. from `kill` or a service manager
+    Terminate,
+}
+
+impl Signal {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Signal::Interrupt => "SIGINT",
+            Signal::Terminate => "SIGTERM",
+        }
+    }
+
+    /// Exit status following the shell convention of 128 + signal number
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Signal::Interrupt => 130,
+            Signal::Terminate => 143,
+        }
+    }
+}
+
+/// Cloneable flag telling long-running loops to wind down
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    sender: Arc<watch::Sender<Option<Signal>>>,
+}
+
+impl Shutdown {
+    /// A flag that is only set by `trigger`
+    pub fn new() -> Self {
+        Shutdown {
+            sender: Arc::new(watch::channel(None).0),
+        }
+    }
+
+    /// Listen for SIGINT and SIGTERM. The first signal sets the flag so the
+    /// tool can save what it has; a second one exits straight away.
+    pub fn install() -> Self {
+        let shutdown = Shutdown::new();
+        let listener = shutdown.clone();
+        tokio::spawn(async move {
+            loop {
+                let Some(signal) = next_signal().await else {
+                    return;
+                };
+                if let Some(first) = listener.signal() {
+                    std::process::exit(first.exit_code());
+                }
+                warn!(
+                    "Received {}, finishing up (send again to quit immediately)",
+                    signal.name()
+                );
+                listener.trigger(signal);
+            }
+        });
+        shutdown
+    }
+
+    pub fn trigger(&self, signal: Signal) {
+        self.sender.send_if_modified(|current| {
+            if current.is_some() {
+                return false;
+            }
+            *current = Some(signal);
+            true
+        });
+    }
+
+    /// The first signal received, if any
+    pub fn signal(&self) -> Option<Signal> {
+        *self.sender.borrow()
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.signal().is_some()
+    }
+
+    /// Resolve once a signal has been received
+    pub async fn wait(&self) -> Signal {
+        let mut receiver = self.sender.subscribe();
+        // The sender lives as long as `self`, so waiting cannot fail
+        let signal = receiver
+            .wait_for(Option::is_some)
+            .await
+            .ok()
+            .and_then(|s| *s);
+        signal.unwrap_or(Signal::Interrupt)
+    }
+
+    /// Exit with the signal's status if one was received
+    pub fn exit_if_triggered(&self) {
+        if let Some(signal) = self.signal() {
+            std::process::exit(signal.exit_code());
+        }
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(unix)]
+async fn next_signal() -> Option<Signal> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            warn!("Cannot listen for SIGTERM: {}", e);
+            tokio::signal::ctrl_c().await.ok()?;
+            return Some(Signal::Interrupt);
+        }
+    };
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result.ok().map(|_| Signal::Interrupt),
+        _ = terminate.recv() => Some(Signal::Terminate),
+    }
+}
+
+#[cfg(not(unix))]
+async fn next_signal() -> Option<Signal> {
+    tokio::signal::ctrl_c().await.ok()?;
+    Some(Signal::Interrupt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_first_signal_wins() {
+        let shutdown = Shutdown::new();
+        let waiter = shutdown.clone();
+        let handle = tokio::spawn(async move { waiter.wait().await });
+
+        assert!(!shutdown.is_triggered());
+        shutdown.trigger(Signal::Terminate);
+        shutdown.trigger(Signal::Interrupt);
+
+        assert_eq!(handle.await.unwrap(), Signal::Terminate);
+        assert_eq!(shutdown.signal().map(|s| s.exit_code()), Some(143));
+    }
+}
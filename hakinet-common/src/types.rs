@@ -1,30 +1,209 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
 use std::net::IpAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::layers::{Layer, LayerSummary};
+
+/// Packet timestamp with nanosecond resolution.
+///
+/// Serialized as a JSON number of epoch seconds (`1700000000.0001`). A
+/// double only keeps about a quarter microsecond at current epoch values, so
+/// packet records also carry the exact decimal string as `timestamp_exact`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp {
+    pub secs: u64,
+    pub nanos: u32,
+}
+
+impl Timestamp {
+    pub fn new(secs: u64, nanos: u32) -> Self {
+        Timestamp {
+            secs: secs + (nanos / 1_000_000_000) as u64,
+            nanos: nanos % 1_000_000_000,
+        }
+    }
+
+    pub fn now() -> Self {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .into()
+    }
+
+    pub fn as_duration(&self) -> Duration {
+        Duration::new(self.secs, self.nanos)
+    }
+
+    pub fn as_secs_f64(&self) -> f64 {
+        self.as_duration().as_secs_f64()
+    }
+
+    /// Time elapsed since `earlier`, zero if `earlier` is later
+    pub fn since(&self, earlier: Timestamp) -> Duration {
+        self.as_duration().saturating_sub(earlier.as_duration())
+    }
+}
+
+impl From<Duration> for Timestamp {
+    fn from(duration: Duration) -> Self {
+        Timestamp::new(duration.as_secs(), duration.subsec_nanos())
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{:09}", self.secs, self.nanos)
+    }
+}
+
+impl std::str::FromStr for Timestamp {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (secs, frac) = s.split_once('.').unwrap_or((s, ""));
+        if frac.len() > 9 || !frac.chars().all(|c| c.is_ascii_digit()) {
+            return Err(anyhow::anyhow!("Invalid timestamp: {}", s));
+        }
+        let nanos = if frac.is_empty() {
+            0
+        } else {
+            format!("{:0<9}", frac).parse::<u32>()?
+        };
+        Ok(Timestamp::new(secs.parse()?, nanos))
+    }
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.as_secs_f64())
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Older output files stored whole seconds as a number
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            // Decimal strings as written by the exact fields
+            Text(String),
+            Secs(u64),
+            Float(f64),
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Text(text) => text.parse().map_err(serde::de::Error::custom),
+            Repr::Secs(secs) => Ok(Timestamp::new(secs, 0)),
+            Repr::Float(secs) => Duration::try_from_secs_f64(secs.max(0.0))
+                .map(Timestamp::from)
+                .map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// `timestamp` as a number plus `timestamp_exact` as a lossless string,
+/// preferring the exact form when reading a record back
+mod exact_timestamp {
+    use super::Timestamp;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize)]
+    struct Fields {
+        timestamp: Timestamp,
+        timestamp_exact: String,
+    }
+
+    #[derive(Deserialize)]
+    struct Lenient {
+        timestamp: Timestamp,
+        #[serde(default)]
+        timestamp_exact: Option<String>,
+    }
+
+    pub fn serialize<S: Serializer>(
+        timestamp: &Timestamp,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        Fields {
+            timestamp: *timestamp,
+            timestamp_exact: timestamp.to_string(),
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Timestamp, D::Error> {
+        let fields = Lenient::deserialize(deserializer)?;
+        match fields.timestamp_exact {
+            Some(exact) => exact.parse().map_err(serde::de::Error::custom),
+            None => Ok(fields.timestamp),
+        }
+    }
+}
 
 /// Packet information structure for capture and analysis
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PacketInfo {
-    pub timestamp: u64,
+    #[serde(flatten, with = "exact_timestamp")]
+    pub timestamp: Timestamp,
+    /// Interface the packet was captured on, when capturing from several
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interface: Option<String>,
+    /// Seconds since the first packet of the capture
+    #[serde(default)]
+    pub time_relative: f64,
+    /// Seconds since the previous packet
+    #[serde(default)]
+    pub time_delta: f64,
     pub length: usize,
     pub protocol: String,
     pub src_addr: Option<String>,
     pub dst_addr: Option<String>,
     pub src_port: Option<u16>,
     pub dst_port: Option<u16>,
+    /// VLAN IDs from 802.1Q/802.1ad tags, outermost first
+    #[serde(default)]
+    pub vlan_ids: Vec<u16>,
     pub info: Option<String>,
+    /// Decoded protocol layers, outermost first
+    #[serde(default)]
+    pub layers: Vec<Layer>,
 }
 
 impl PacketInfo {
     pub fn new() -> Self {
         PacketInfo {
-            timestamp: 0,
+            timestamp: Timestamp::default(),
+            interface: None,
+            time_relative: 0.0,
+            time_delta: 0.0,
             length: 0,
             protocol: "Unknown".to_string(),
             src_addr: None,
             dst_addr: None,
             src_port: None,
             dst_port: None,
+            vlan_ids: Vec::new(),
             info: None,
+            layers: Vec::new(),
+        }
+    }
+
+    /// Build a packet from its decoded layers, deriving the flat summary fields
+    pub fn from_layers(timestamp: Timestamp, length: usize, layers: Vec<Layer>) -> Self {
+        let summary = LayerSummary::from_layers(&layers);
+        PacketInfo {
+            timestamp,
+            length,
+            protocol: summary.protocol.unwrap_or_else(|| "Unknown".to_string()),
+            src_addr: summary.src_addr,
+            dst_addr: summary.dst_addr,
+            src_port: summary.src_port,
+            dst_port: summary.dst_port,
+            vlan_ids: summary.vlan_ids,
+            info: summary.info,
+            layers,
+            ..PacketInfo::new()
         }
     }
 }
@@ -190,6 +369,9 @@ pub struct ScanResults {
     pub hosts_up: usize,
     pub total_ports_scanned: usize,
     pub open_ports_found: usize,
+    /// The scan was stopped by a signal before covering every target
+    #[serde(default)]
+    pub interrupted: bool,
 }
 
 impl ScanResults {
@@ -205,6 +387,7 @@ impl ScanResults {
             hosts_up: 0,
             total_ports_scanned: 0,
             open_ports_found: 0,
+            interrupted: false,
         }
     }
 
@@ -234,4 +417,55 @@ impl Default for ScanResults {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestamp_round_trip() {
+        let ts = Timestamp::new(1_700_000_000, 100_000);
+        assert_eq!(ts.to_string(), "1700000000.000100000");
+        assert_eq!("1700000000.0001".parse::<Timestamp>().unwrap(), ts);
+
+        let json = serde_json::to_string(&ts).unwrap();
+        assert_eq!(json, "1700000000.0001");
+        assert_eq!(
+            serde_json::from_str::<Timestamp>("\"1700000000.000100000\"").unwrap(),
+            ts
+        );
+        assert_eq!(
+            serde_json::from_str::<Timestamp>("1700000000").unwrap(),
+            Timestamp::new(1_700_000_000, 0)
+        );
+        assert!(serde_json::from_str::<Timestamp>("1e30").is_err());
+    }
+
+    #[test]
+    fn test_packet_timestamp_json() {
+        let mut packet = PacketInfo::new();
+        packet.timestamp = Timestamp::new(1_700_000_000, 250_300_123);
+
+        let json = serde_json::to_value(&packet).unwrap();
+        assert!(json["timestamp"].is_f64());
+        assert_eq!(json["timestamp_exact"], "1700000000.250300123");
+        let parsed: PacketInfo = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(parsed.timestamp, packet.timestamp);
+
+        // Records written before the exact field existed
+        let mut old = json;
+        old.as_object_mut().unwrap().remove("timestamp_exact");
+        old["timestamp"] = 1_700_000_000u64.into();
+        let parsed: PacketInfo = serde_json::from_value(old).unwrap();
+        assert_eq!(parsed.timestamp, Timestamp::new(1_700_000_000, 0));
+    }
+
+    #[test]
+    fn test_timestamp_since() {
+        let earlier = Timestamp::new(10, 900_000_000);
+        let later = Timestamp::new(11, 100_000_000);
+        assert_eq!(later.since(earlier), Duration::from_millis(200));
+        assert_eq!(earlier.since(later), Duration::ZERO);
+    }
+}
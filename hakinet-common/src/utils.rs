@@ -56,7 +56,7 @@ where
 }
 
 /// Shuffle a vector using Fisher-Yates algorithm
-pub fn shuffle<T>(vec: &mut Vec<T>) {
+pub fn shuffle<T>(vec: &mut [T]) {
     use rand::seq::SliceRandom;
     let mut rng = rand::rng();
     vec.shuffle(&mut rng);
@@ -1,6 +1,7 @@
 use anyhow::Result;
 use hakinet_common::{
     network::{parse_targets, is_private_ip},
+    signal::Shutdown,
     types::{HostInfo, ScanResults},
     utils::current_timestamp_micros,
 };
@@ -16,6 +17,7 @@ use tokio::time::timeout;
 pub struct HostDiscoverer {
     max_parallel: usize,
     timeout: Duration,
+    shutdown: Shutdown,
 }
 
 impl HostDiscoverer {
@@ -23,6 +25,7 @@ impl HostDiscoverer {
         HostDiscoverer {
             max_parallel: 50,
             timeout: Duration::from_secs(2),
+            shutdown: Shutdown::new(),
         }
     }
 
@@ -36,6 +39,12 @@ impl HostDiscoverer {
         self
     }
 
+    /// Stop probing once a signal arrives and return the hosts seen so far
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
     pub async fn ping_discovery(&self, network: &str) -> Result<ScanResults> {
         let targets = parse_targets(network).await?;
         let mut results = ScanResults::new();
@@ -44,6 +53,9 @@ impl HostDiscoverer {
         let mut handles = Vec::new();
 
         for target in targets {
+            if self.shutdown.is_triggered() {
+                break;
+            }
             let permit = semaphore.clone().acquire_owned().await.unwrap();
             let timeout_duration = self.timeout;
             let addr = target.addr;
@@ -81,6 +93,7 @@ impl HostDiscoverer {
             }
         }
 
+        results.interrupted = self.shutdown.is_triggered();
         results.finalize();
         Ok(results)
     }
@@ -94,6 +107,9 @@ impl HostDiscoverer {
         let mut handles = Vec::new();
 
         for target in targets {
+            if self.shutdown.is_triggered() {
+                break;
+            }
             let permit = semaphore.clone().acquire_owned().await.unwrap();
             let timeout_duration = self.timeout;
             let addr = target.addr;
@@ -138,6 +154,7 @@ impl HostDiscoverer {
             }
         }
 
+        results.interrupted = self.shutdown.is_triggered();
         results.finalize();
         Ok(results)
     }
@@ -149,6 +166,9 @@ impl HostDiscoverer {
 
         // Check if targets are in private IP ranges
         for target in targets {
+            if self.shutdown.is_triggered() {
+                break;
+            }
             if !is_private_ip(&target.addr) {
                 warn!("ARP discovery only works on local networks. Skipping {}", target.addr);
                 continue;
@@ -176,6 +196,7 @@ impl HostDiscoverer {
             results.add_host(host_info);
         }
 
+        results.interrupted = self.shutdown.is_triggered();
         results.finalize();
         Ok(results)
     }
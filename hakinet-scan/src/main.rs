@@ -1,12 +1,13 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use clap::{Parser, Subcommand, ValueEnum};
 use colored::*;
 use log::info;
-use hakinet_common::{print_cat_banner, print_cat_working, print_cat_done, print_cat_error};
+use hakinet_common::{find_interface, print_cat_banner, print_cat_working, print_cat_done, print_cat_error, Shutdown};
 
 mod scanner;
 mod discovery;
 mod service;
+mod syn;
 
 use scanner::PortScanner;
 
@@ -63,15 +64,23 @@ enum Commands {
         #[arg(long)]
         randomize: bool,
 
+        /// Send probes from this interface's addresses
+        #[arg(short = 'e', long)]
+        interface: Option<String>,
+
         /// Enable verbose output
         #[arg(short, long)]
         verbose: bool,
     },
     /// Discover hosts on network
     Discovery {
-        /// Target network (CIDR notation)
-        #[arg(value_name = "NETWORK")]
-        network: String,
+        /// Target network (CIDR notation), defaults to the subnet of --interface
+        #[arg(value_name = "NETWORK", required_unless_present = "interface")]
+        network: Option<String>,
+
+        /// Interface whose IPv4 subnet to sweep when no network is given
+        #[arg(short = 'e', long)]
+        interface: Option<String>,
 
         /// Discovery method
         #[arg(short, long, default_value = "ping")]
@@ -148,11 +157,21 @@ impl From<OutputFormat> for hakinet_common::output::OutputFormat {
 async fn main() -> Result<()> {
     let cli = Cli::parse();
 
+    let verbose = match &cli.command {
+        Commands::Scan { verbose, .. } | Commands::Discovery { verbose, .. } => *verbose,
+    };
+
     // Initialize logger
     env_logger::Builder::from_default_env()
-        .filter_level(log::LevelFilter::Info)
+        .filter_level(if verbose {
+            log::LevelFilter::Debug
+        } else {
+            log::LevelFilter::Info
+        })
         .init();
 
+    let shutdown = Shutdown::install();
+
     print_cat_banner("Hakinet-Scan", "Your cute network scanning cat");
 
     match cli.command {
@@ -167,18 +186,14 @@ async fn main() -> Result<()> {
             service_detection,
             os_detection,
             randomize,
-            verbose,
+            interface,
+            ..
         } => {
-            if verbose {
-                env_logger::Builder::from_default_env()
-                    .filter_level(log::LevelFilter::Debug)
-                    .init();
-            }
-
             if targets.is_empty() {
                 print_cat_error("No targets specified!");
                 std::process::exit(1);
             }
+            let interface = interface.map(|name| find_interface(&name)).transpose()?;
 
             info!("Starting port scan on targets: {:?}", targets);
             print_cat_working("Scanning ports like a ninja cat...");
@@ -188,7 +203,9 @@ async fn main() -> Result<()> {
                 .with_timeout(std::time::Duration::from_secs(timeout))
                 .with_randomize(randomize)
                 .with_service_detection(service_detection)
-                .with_os_detection(os_detection);
+                .with_os_detection(os_detection)
+                .with_interface(interface)
+                .with_shutdown(shutdown.clone());
 
             let results = match scan_type {
                 ScanType::Syn => scanner.syn_scan(targets, ports).await?,
@@ -200,7 +217,11 @@ async fn main() -> Result<()> {
             let output_writer = hakinet_common::output::ScanOutputWriter::new(output.into(), file);
             output_writer.write_results(&results).await?;
 
-            print_cat_done("Port scanning complete!");
+            if results.interrupted {
+                print_cat_error("Port scanning interrupted!");
+            } else {
+                print_cat_done("Port scanning complete!");
+            }
             println!(
                 "{}",
                 format!(
@@ -218,20 +239,24 @@ async fn main() -> Result<()> {
             timeout,
             output,
             file,
-            verbose,
+            interface,
+            ..
         } => {
-            if verbose {
-                env_logger::Builder::from_default_env()
-                    .filter_level(log::LevelFilter::Debug)
-                    .init();
-            }
-
+            let network = match (network, interface) {
+                (Some(network), _) => network,
+                (None, Some(name)) => find_interface(&name)?
+                    .ipv4_network()
+                    .ok_or_else(|| anyhow!("Interface {} has no IPv4 subnet", name))?
+                    .to_string(),
+                (None, None) => unreachable!("clap requires a network or an interface"),
+            };
             info!("Starting host discovery on network: {}", network);
             print_cat_working("Discovering hosts like a detective cat...");
 
             let discoverer = discovery::HostDiscoverer::new()
                 .with_max_parallel(max_parallel)
-                .with_timeout(std::time::Duration::from_secs(timeout));
+                .with_timeout(std::time::Duration::from_secs(timeout))
+                .with_shutdown(shutdown.clone());
 
             let results = match method {
                 DiscoveryMethod::Ping => discoverer.ping_discovery(&network).await?,
@@ -242,7 +267,11 @@ async fn main() -> Result<()> {
             let output_writer = hakinet_common::output::ScanOutputWriter::new(output.into(), file);
             output_writer.write_results(&results).await?;
 
-            print_cat_done("Host discovery complete!");
+            if results.interrupted {
+                print_cat_error("Host discovery interrupted!");
+            } else {
+                print_cat_done("Host discovery complete!");
+            }
             println!(
                 "{}",
                 format!("Discovered {} live hosts!", results.hosts_up).bright_green()
@@ -251,5 +280,6 @@ async fn main() -> Result<()> {
     }
 
     println!("{}", "Thanks for using Hakinet-Scan! 🐾".bright_magenta());
+    shutdown.exit_if_triggered();
     Ok(())
 }
\ No newline at end of file
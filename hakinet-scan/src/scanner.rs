@@ -3,21 +3,28 @@ use anyhow::{anyhow, Result};
 use hakinet_common::{
     network::{parse_targets, PortRange, Protocol},
     output::print_scan_progress,
+    signal::Shutdown,
+    interfaces::InterfaceInfo,
     types::{HostInfo, PortInfo, PortState, ScanConfig, ScanResults},
-    utils::{current_timestamp_micros, shuffle},
+    utils::{current_timestamp_micros, is_privileged, shuffle},
 };
+use log::{debug, warn};
 
 use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::net::{TcpSocket, UdpSocket};
+use tokio::net::{TcpSocket, TcpStream, UdpSocket};
 use tokio::sync::Semaphore;
 use tokio::time::timeout;
 
+use crate::syn::SynProber;
+
 pub struct PortScanner {
     config: ScanConfig,
     service_detection: bool,
     os_detection: bool,
+    interface: Option<InterfaceInfo>,
+    shutdown: Shutdown,
 }
 
 impl PortScanner {
@@ -26,6 +33,8 @@ impl PortScanner {
             config: ScanConfig::default(),
             service_detection: false,
             os_detection: false,
+            interface: None,
+            shutdown: Shutdown::new(),
         }
     }
 
@@ -54,7 +63,33 @@ impl PortScanner {
         self
     }
 
+    /// Send probes from this interface's addresses instead of letting the
+    /// routing table pick the source
+    pub fn with_interface(mut self, interface: Option<InterfaceInfo>) -> Self {
+        self.interface = interface;
+        self
+    }
+
+    /// Stop probing once a signal arrives and return what was found so far
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
     pub async fn syn_scan(&self, targets: Vec<String>, ports: String) -> Result<ScanResults> {
+        if !is_privileged() {
+            warn!("SYN scan requires root privileges, falling back to TCP connect scan");
+            return self.connect_scan(targets, ports).await;
+        }
+
+        let prober = match SynProber::new(self.interface.clone()) {
+            Ok(prober) => Arc::new(prober),
+            Err(e) => {
+                warn!("Cannot open raw sockets ({}), falling back to TCP connect scan", e);
+                return self.connect_scan(targets, ports).await;
+            }
+        };
+
         let hosts = self.parse_all_targets(targets).await?;
         let port_ranges = self.parse_ports(&ports)?;
         
@@ -62,6 +97,9 @@ impl PortScanner {
         let semaphore = Arc::new(Semaphore::new(self.config.max_parallel));
         
         for host in hosts {
+            if self.shutdown.is_triggered() {
+                break;
+            }
             let mut host_info = HostInfo::new(host.addr).with_hostname(
                 host.hostname.unwrap_or_else(|| host.addr.to_string())
             );
@@ -80,46 +118,63 @@ impl PortScanner {
                 }
 
                 let total_ports = scan_ports.len();
-                let mut completed = 0;
+                let mut port_results = Vec::new();
 
-                for port in scan_ports {
+                for (i, port) in scan_ports.iter().enumerate() {
+                    if self.shutdown.is_triggered() {
+                        break;
+                    }
                     let permit = semaphore.clone().acquire_owned().await.unwrap();
+                    let prober = prober.clone();
                     let host_addr = host.addr;
+                    let port = *port;
                     let timeout_duration = self.config.timeout;
+                    let retries = self.config.retries;
                     let service_detection = self.service_detection;
 
-                    tokio::spawn(async move {
+                    let handle = tokio::spawn(async move {
                         let _permit = permit;
                         let start_time = current_timestamp_micros();
                         
-                        let port_info = match syn_scan_port(host_addr, port, timeout_duration).await {
+                        match prober.probe(host_addr, port, timeout_duration, retries).await {
                             Ok(state) => {
                                 let response_time = current_timestamp_micros() - start_time;
                                 let mut port_info = PortInfo::new(port, "tcp".to_string(), state)
                                     .with_response_time(response_time);
                                 
-                                if service_detection && state == PortState::Open {
-                                    if let Some(service) = detect_service(host_addr, port).await {
-                                        port_info = port_info.with_service(service);
-                                    }
+                                if service_detection
+                                    && state == PortState::Open
+                                    && let Some(service) = detect_service(host_addr, port).await
+                                {
+                                    port_info = port_info.with_service(service);
                                 }
                                 
                                 port_info
                             },
-                            Err(_) => PortInfo::new(port, "tcp".to_string(), PortState::Filtered),
-                        };
-
-                        port_info
+                            Err(e) => {
+                                debug!("SYN probe to {}:{} failed: {}", host_addr, port, e);
+                                PortInfo::new(port, "tcp".to_string(), PortState::Unknown)
+                            }
+                        }
                     });
 
-                    completed += 1;
-                    print_scan_progress(completed, total_ports, &host.addr.to_string());
+                    port_results.push(handle);
+                    print_scan_progress(i + 1, total_ports, &host.addr.to_string());
+                }
+
+                // Collect results
+                for handle in port_results {
+                    if let Ok(port_info) = handle.await {
+                        host_info = host_info.add_port(port_info);
+                    }
                 }
             }
             
             results.add_host(host_info);
         }
 
+        println!(); // New line after progress
+        results.interrupted = self.shutdown.is_triggered();
         results.finalize();
         Ok(results)
     }
@@ -132,6 +187,9 @@ impl PortScanner {
         let semaphore = Arc::new(Semaphore::new(self.config.max_parallel));
         
         for host in hosts {
+            if self.shutdown.is_triggered() {
+                break;
+            }
             let mut host_info = HostInfo::new(host.addr).with_hostname(
                 host.hostname.unwrap_or_else(|| host.addr.to_string())
             );
@@ -152,18 +210,22 @@ impl PortScanner {
                 let mut port_results = Vec::new();
 
                 for (i, port) in scan_ports.iter().enumerate() {
+                    if self.shutdown.is_triggered() {
+                        break;
+                    }
                     let permit = semaphore.clone().acquire_owned().await.unwrap();
                     let host_addr = host.addr;
                     let port = *port;
                     let timeout_duration = self.config.timeout;
                     let service_detection = self.service_detection;
+                    let source = self.source_addr_for(host_addr);
 
                     let handle = tokio::spawn(async move {
                         let _permit = permit;
                         let start_time = current_timestamp_micros();
                         
                         let socket_addr = SocketAddr::new(host_addr, port);
-                        let state = match timeout(timeout_duration, tokio::net::TcpStream::connect(socket_addr)).await {
+                        let state = match timeout(timeout_duration, connect_from(source, socket_addr)).await {
                             Ok(Ok(_)) => PortState::Open,
                             Ok(Err(_)) => PortState::Closed,
                             Err(_) => PortState::Filtered,
@@ -173,10 +235,11 @@ impl PortScanner {
                         let mut port_info = PortInfo::new(port, "tcp".to_string(), state)
                             .with_response_time(response_time);
                         
-                        if service_detection && state == PortState::Open {
-                            if let Some(service) = detect_service(host_addr, port).await {
-                                port_info = port_info.with_service(service);
-                            }
+                        if service_detection
+                            && state == PortState::Open
+                            && let Some(service) = detect_service(host_addr, port).await
+                        {
+                            port_info = port_info.with_service(service);
                         }
                         
                         port_info
@@ -198,6 +261,7 @@ impl PortScanner {
         }
 
         println!(); // New line after progress
+        results.interrupted = self.shutdown.is_triggered();
         results.finalize();
         Ok(results)
     }
@@ -209,6 +273,9 @@ impl PortScanner {
         let mut results = ScanResults::new();
         
         for host in hosts {
+            if self.shutdown.is_triggered() {
+                break;
+            }
             let mut host_info = HostInfo::new(host.addr).with_hostname(
                 host.hostname.unwrap_or_else(|| host.addr.to_string())
             );
@@ -228,6 +295,9 @@ impl PortScanner {
                 let total_ports = scan_ports.len();
 
                 for (i, port) in scan_ports.iter().enumerate() {
+                    if self.shutdown.is_triggered() {
+                        break;
+                    }
                     let port_info = self.udp_scan_port(host.addr, *port).await;
                     host_info = host_info.add_port(port_info);
                     print_scan_progress(i + 1, total_ports, &host.addr.to_string());
@@ -238,6 +308,7 @@ impl PortScanner {
         }
 
         println!(); // New line after progress
+        results.interrupted = self.shutdown.is_triggered();
         results.finalize();
         Ok(results)
     }
@@ -245,6 +316,9 @@ impl PortScanner {
     pub async fn comprehensive_scan(&self, targets: Vec<String>, ports: String) -> Result<ScanResults> {
         // First do TCP scan
         let tcp_results = self.connect_scan(targets.clone(), ports.clone()).await?;
+        if tcp_results.interrupted {
+            return Ok(tcp_results);
+        }
         
         // Then do UDP scan on common UDP ports
         let udp_ports = "53,67,68,69,123,161,162,500,514,520,1900,4500";
@@ -252,6 +326,7 @@ impl PortScanner {
         
         // Merge results
         let mut combined_results = tcp_results;
+        combined_results.interrupted = udp_results.interrupted;
         
         for udp_host in udp_results.hosts {
             if let Some(tcp_host) = combined_results.hosts.iter_mut()
@@ -289,10 +364,11 @@ impl PortScanner {
     async fn ping_host(&self, addr: &IpAddr) -> bool {
         // Simple TCP connect to common ports to check if host is up
         let common_ports = [80, 443, 22, 21, 25, 53];
+        let source = self.source_addr_for(*addr);
         
         for port in common_ports {
             let socket_addr = SocketAddr::new(*addr, port);
-            if timeout(Duration::from_millis(500), tokio::net::TcpStream::connect(socket_addr)).await.is_ok() {
+            if timeout(Duration::from_millis(500), connect_from(source, socket_addr)).await.is_ok() {
                 return true;
             }
         }
@@ -300,11 +376,24 @@ impl PortScanner {
         false
     }
 
+    /// Source address to bind probes to when an interface was chosen
+    fn source_addr_for(&self, destination: IpAddr) -> Option<IpAddr> {
+        self.interface
+            .as_ref()
+            .and_then(|interface| interface.source_addr_for(destination))
+    }
+
     async fn udp_scan_port(&self, addr: IpAddr, port: u16) -> PortInfo {
         let socket_addr = SocketAddr::new(addr, port);
         let start_time = current_timestamp_micros();
         
-        match UdpSocket::bind("0.0.0.0:0").await {
+        let bind_addr = match self.source_addr_for(addr) {
+            Some(source) => SocketAddr::new(source, 0),
+            None if addr.is_ipv6() => SocketAddr::from(([0u16; 8], 0)),
+            None => SocketAddr::from(([0u8; 4], 0)),
+        };
+
+        match UdpSocket::bind(bind_addr).await {
             Ok(socket) => {
                 match timeout(self.config.timeout, socket.connect(socket_addr)).await {
                     Ok(Ok(_)) => {
@@ -336,15 +425,18 @@ impl Default for PortScanner {
     }
 }
 
-async fn syn_scan_port(addr: IpAddr, port: u16, timeout_duration: Duration) -> Result<PortState> {
-    let socket = TcpSocket::new_v4()?;
-    let socket_addr = SocketAddr::new(addr, port);
-    
-    match timeout(timeout_duration, socket.connect(socket_addr)).await {
-        Ok(Ok(_)) => Ok(PortState::Open),
-        Ok(Err(_)) => Ok(PortState::Closed),
-        Err(_) => Ok(PortState::Filtered),
-    }
+/// Open a TCP connection, bound to `source` if one is given
+async fn connect_from(source: Option<IpAddr>, destination: SocketAddr) -> std::io::Result<TcpStream> {
+    let Some(source) = source else {
+        return TcpStream::connect(destination).await;
+    };
+    let socket = if source.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    socket.bind(SocketAddr::new(source, 0))?;
+    socket.connect(destination).await
 }
 
 async fn detect_service(addr: IpAddr, port: u16) -> Option<String> {
@@ -0,0 +1,350 @@
+use anyhow::{Result, anyhow};
+use hakinet_common::interfaces::InterfaceInfo;
+use hakinet_common::types::PortState;
+use log::{debug, warn};
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::tcp::{self, MutableTcpPacket, TcpFlags, TcpOption, TcpPacket};
+use pnet::transport::{
+    TransportChannelType, TransportProtocol, TransportReceiver, TransportSender, tcp_packet_iter,
+    transport_channel,
+};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::time::timeout;
+
+/// TCP header with a single MSS option, which is what most stacks expect on a SYN
+const SYN_SEGMENT_LEN: usize = 24;
+
+/// How long listener threads block on the raw socket before checking for shutdown
+const LISTEN_POLL: Duration = Duration::from_millis(200);
+
+type PendingProbes = Mutex<HashMap<(IpAddr, u16), oneshot::Sender<PortState>>>;
+
+/// Half-open TCP SYN prober backed by raw sockets.
+///
+/// Each probe sends a bare SYN and waits for the target's answer: a SYN/ACK
+/// means open (the half-open connection is torn down with a RST), a RST means
+/// closed, and silence until the timeout means filtered.
+pub struct SynProber {
+    shared: Arc<Shared>,
+    listeners: Vec<JoinHandle<()>>,
+}
+
+struct Shared {
+    v4: Mutex<TransportSender>,
+    v6: Option<Mutex<TransportSender>>,
+    pending: PendingProbes,
+    source_port: u16,
+    sequence: u32,
+    interface: Option<InterfaceInfo>,
+    running: AtomicBool,
+}
+
+impl SynProber {
+    /// Open the raw sockets and start listening for replies. With an
+    /// interface, probes are sent from its addresses.
+    ///
+    /// Fails when the process lacks the privileges needed for raw sockets.
+    pub fn new(interface: Option<InterfaceInfo>) -> Result<Self> {
+        use rand::Rng;
+
+        let (v4_tx, v4_rx) = open_channel(TransportProtocol::Ipv4(IpNextHeaderProtocols::Tcp))?;
+        let v6 = match open_channel(TransportProtocol::Ipv6(IpNextHeaderProtocols::Tcp)) {
+            Ok(channel) => Some(channel),
+            Err(e) => {
+                debug!(
+                    "IPv6 raw socket unavailable, SYN scan limited to IPv4: {}",
+                    e
+                );
+                None
+            }
+        };
+
+        let mut rng = rand::rng();
+        let (v6_tx, v6_rx) = match v6 {
+            Some((tx, rx)) => (Some(Mutex::new(tx)), Some(rx)),
+            None => (None, None),
+        };
+
+        let shared = Arc::new(Shared {
+            v4: Mutex::new(v4_tx),
+            v6: v6_tx,
+            pending: Mutex::new(HashMap::new()),
+            source_port: rng.random_range(40000..60000),
+            sequence: rng.random(),
+            interface,
+            running: AtomicBool::new(true),
+        });
+
+        let mut listeners = vec![spawn_listener(shared.clone(), v4_rx)];
+        if let Some(rx) = v6_rx {
+            listeners.push(spawn_listener(shared.clone(), rx));
+        }
+
+        Ok(SynProber { shared, listeners })
+    }
+
+    /// Probe a single port, retrying unanswered SYNs up to `retries` times.
+    pub async fn probe(
+        &self,
+        addr: IpAddr,
+        port: u16,
+        timeout_duration: Duration,
+        retries: usize,
+    ) -> Result<PortState> {
+        let source = self.shared.source_addr_for(addr)?;
+
+        for attempt in 0..=retries {
+            let (tx, rx) = oneshot::channel();
+            self.shared.pending.lock().unwrap().insert((addr, port), tx);
+            self.shared
+                .send_segment(source, addr, port, TcpFlags::SYN, self.shared.sequence)?;
+
+            match timeout(timeout_duration, rx).await {
+                Ok(Ok(state)) => return Ok(state),
+                _ => {
+                    self.shared.pending.lock().unwrap().remove(&(addr, port));
+                    debug!("No reply from {}:{} (attempt {})", addr, port, attempt + 1);
+                }
+            }
+        }
+
+        Ok(PortState::Filtered)
+    }
+}
+
+impl Drop for SynProber {
+    fn drop(&mut self) {
+        self.shared.running.store(false, Ordering::Relaxed);
+        for listener in self.listeners.drain(..) {
+            let _ = listener.join();
+        }
+    }
+}
+
+impl Shared {
+    fn send_segment(
+        &self,
+        source: IpAddr,
+        destination: IpAddr,
+        destination_port: u16,
+        flags: u8,
+        sequence: u32,
+    ) -> Result<()> {
+        let segment = build_segment(
+            source,
+            destination,
+            self.source_port,
+            destination_port,
+            sequence,
+            flags,
+        )?;
+        let packet = TcpPacket::new(&segment).ok_or_else(|| anyhow!("Invalid TCP segment"))?;
+
+        let sender = match destination {
+            IpAddr::V4(_) => &self.v4,
+            IpAddr::V6(_) => self
+                .v6
+                .as_ref()
+                .ok_or_else(|| anyhow!("IPv6 raw socket unavailable"))?,
+        };
+        sender.lock().unwrap().send_to(packet, destination)?;
+        Ok(())
+    }
+
+    fn source_addr_for(&self, destination: IpAddr) -> Result<IpAddr> {
+        match &self.interface {
+            Some(interface) => interface.source_addr_for(destination).ok_or_else(|| {
+                anyhow!(
+                    "Interface {} has no address to reach {}",
+                    interface.name,
+                    destination
+                )
+            }),
+            None => route_source_addr(destination),
+        }
+    }
+
+    fn handle_reply(&self, reply: &TcpPacket, addr: IpAddr) {
+        if reply.get_destination() != self.source_port {
+            return;
+        }
+
+        let expected_ack = self.sequence.wrapping_add(1);
+        if reply.get_acknowledgement() != expected_ack {
+            return;
+        }
+
+        let Some(state) = classify_reply(reply.get_flags()) else {
+            return;
+        };
+
+        let port = reply.get_source();
+        if state == PortState::Open {
+            // Tear down the half-open connection so the target frees its slot
+            match self.source_addr_for(addr) {
+                Ok(source) => {
+                    if let Err(e) =
+                        self.send_segment(source, addr, port, TcpFlags::RST, expected_ack)
+                    {
+                        debug!("Failed to send RST to {}:{}: {}", addr, port, e);
+                    }
+                }
+                Err(e) => debug!("No route back to {}: {}", addr, e),
+            }
+        }
+
+        if let Some(waiter) = self.pending.lock().unwrap().remove(&(addr, port)) {
+            let _ = waiter.send(state);
+        }
+    }
+}
+
+fn open_channel(protocol: TransportProtocol) -> Result<(TransportSender, TransportReceiver)> {
+    Ok(transport_channel(
+        4096,
+        TransportChannelType::Layer4(protocol),
+    )?)
+}
+
+fn spawn_listener(shared: Arc<Shared>, mut receiver: TransportReceiver) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut replies = tcp_packet_iter(&mut receiver);
+        while shared.running.load(Ordering::Relaxed) {
+            match replies.next_with_timeout(LISTEN_POLL) {
+                Ok(Some((reply, addr))) => shared.handle_reply(&reply, addr),
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!("Raw socket receive error: {}", e);
+                    std::thread::sleep(LISTEN_POLL);
+                }
+            }
+        }
+    })
+}
+
+/// Map the flags of a reply to our SYN onto a port state
+fn classify_reply(flags: u8) -> Option<PortState> {
+    if flags & TcpFlags::RST != 0 {
+        Some(PortState::Closed)
+    } else if flags & (TcpFlags::SYN | TcpFlags::ACK) == TcpFlags::SYN | TcpFlags::ACK {
+        Some(PortState::Open)
+    } else {
+        None
+    }
+}
+
+/// Build a TCP segment with a valid checksum for the given address pair
+fn build_segment(
+    source: IpAddr,
+    destination: IpAddr,
+    source_port: u16,
+    destination_port: u16,
+    sequence: u32,
+    flags: u8,
+) -> Result<Vec<u8>> {
+    let syn = flags & TcpFlags::SYN != 0;
+    let len = if syn {
+        SYN_SEGMENT_LEN
+    } else {
+        TcpPacket::minimum_packet_size()
+    };
+    let mut buffer = vec![0u8; len];
+
+    let mut segment =
+        MutableTcpPacket::new(&mut buffer).ok_or_else(|| anyhow!("TCP buffer too small"))?;
+    segment.set_source(source_port);
+    segment.set_destination(destination_port);
+    segment.set_sequence(sequence);
+    segment.set_acknowledgement(0);
+    segment.set_flags(flags);
+    segment.set_data_offset((len / 4) as u8);
+    if syn {
+        segment.set_window(1024);
+        segment.set_options(&[TcpOption::mss(1460)]);
+    }
+
+    let checksum = match (source, destination) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            tcp::ipv4_checksum(&segment.to_immutable(), &src, &dst)
+        }
+        (IpAddr::V6(src), IpAddr::V6(dst)) => {
+            tcp::ipv6_checksum(&segment.to_immutable(), &src, &dst)
+        }
+        _ => {
+            return Err(anyhow!(
+                "Address family mismatch between {} and {}",
+                source,
+                destination
+            ));
+        }
+    };
+    segment.set_checksum(checksum);
+
+    Ok(buffer)
+}
+
+/// Find the local address the kernel would use to reach `destination`
+fn route_source_addr(destination: IpAddr) -> Result<IpAddr> {
+    let bind_addr: SocketAddr = match destination {
+        IpAddr::V4(_) => "0.0.0.0:0".parse()?,
+        IpAddr::V6(_) => "[::]:0".parse()?,
+    };
+    // Connecting a UDP socket only performs a route lookup, nothing is sent
+    let socket = UdpSocket::bind(bind_addr)?;
+    socket.connect(SocketAddr::new(destination, 9))?;
+    Ok(socket.local_addr()?.ip())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_syn_segment() {
+        let src: IpAddr = "192.168.1.10".parse().unwrap();
+        let dst: IpAddr = "192.168.1.1".parse().unwrap();
+        let buffer = build_segment(src, dst, 45000, 443, 0x1234_5678, TcpFlags::SYN).unwrap();
+        let segment = TcpPacket::new(&buffer).unwrap();
+
+        assert_eq!(buffer.len(), SYN_SEGMENT_LEN);
+        assert_eq!(segment.get_source(), 45000);
+        assert_eq!(segment.get_destination(), 443);
+        assert_eq!(segment.get_sequence(), 0x1234_5678);
+        assert_eq!(segment.get_flags(), TcpFlags::SYN);
+        assert_eq!(segment.get_data_offset(), 6);
+
+        let (IpAddr::V4(src), IpAddr::V4(dst)) = (src, dst) else {
+            unreachable!()
+        };
+        assert_eq!(
+            segment.get_checksum(),
+            tcp::ipv4_checksum(&segment, &src, &dst)
+        );
+    }
+
+    #[test]
+    fn test_classify_reply() {
+        assert_eq!(
+            classify_reply(TcpFlags::SYN | TcpFlags::ACK),
+            Some(PortState::Open)
+        );
+        assert_eq!(
+            classify_reply(TcpFlags::RST | TcpFlags::ACK),
+            Some(PortState::Closed)
+        );
+        assert_eq!(classify_reply(TcpFlags::ACK), None);
+    }
+
+    #[test]
+    fn test_mixed_families_rejected() {
+        let src: IpAddr = "10.0.0.1".parse().unwrap();
+        let dst: IpAddr = "::1".parse().unwrap();
+        assert!(build_segment(src, dst, 1, 2, 0, TcpFlags::SYN).is_err());
+    }
+}
@@ -1,73 +1,411 @@
 use anyhow::{anyhow, Result};
+use clap::ValueEnum;
 use colored::*;
 use log::{debug, info, warn};
-use pcap::{Active, Capture, Device};
-use pnet::packet::ethernet::{EtherTypes, EthernetPacket};
-use pnet::packet::icmp::IcmpPacket;
-use pnet::packet::ipv4::Ipv4Packet;
-use pnet::packet::ipv6::Ipv6Packet;
-use pnet::packet::tcp::TcpPacket;
-use pnet::packet::udp::UdpPacket;
-use pnet::packet::Packet;
-use std::time::{SystemTime, UNIX_EPOCH};
-
-use crate::output::OutputWriter;
-use hakinet_common::PacketInfo;
+use pcap::{Activated, Capture, Device, Linktype, Precision};
+use std::time::Duration;
+
+use crate::display::{self, HexDump};
+use crate::dissect::Dissector;
+use crate::engine::CaptureEngine;
+use crate::filter::{bpf, PacketFilter};
+use crate::flow::{FlowFormat, FlowTable};
+use crate::options::{self, CaptureOptions};
+use crate::output::{OutputFormat, OutputWriter};
+use crate::rotate::RotationPolicy;
+use crate::savefile::{InterfaceMeta, SaveFormat, SavefileWriter};
+use crate::stats::{CaptureStats, StatusLine};
+use crate::summary::SummaryBuilder;
+use hakinet_common::{
+    InterfaceAddress, InterfaceFlags, InterfaceInfo, InterfaceLinkType, PacketInfo, Shutdown,
+    Timestamp,
+};
+
+/// Percentage of dropped packets above which a live capture warns
+pub const DEFAULT_DROP_THRESHOLD: f64 = 1.0;
+
+/// A captured frame detached from the libpcap buffer
+#[derive(Debug, Clone)]
+pub struct RawPacket {
+    /// Capture time from the pcap header
+    pub timestamp: Timestamp,
+    /// Original length of the frame on the wire
+    pub len: u32,
+    /// Captured bytes (possibly truncated to the snaplen)
+    pub data: Vec<u8>,
+    /// Index of the source interface, 0 when there is only one
+    pub interface: usize,
+}
 
-pub struct PacketCapture {
-    capture: Capture<Active>,
-    interface_name: String,
+/// How packet times are shown on the console, modelled on tcpdump's -t options
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeFormat {
+    /// Time of day with fractional seconds
+    Absolute,
+    /// Date and time of day
+    Date,
+    /// Seconds since the Unix epoch
+    Epoch,
+    /// Seconds since the previous packet (tcpdump -ttt)
+    Delta,
+    /// Seconds since the first packet (tcpdump -ttttt)
+    Relative,
 }
 
-impl PacketCapture {
-    pub fn new(interface_name: &str) -> Result<Self> {
-        let device = if interface_name == "any" {
-            Device::lookup()?.ok_or_else(|| anyhow!("No default device found"))?
-        } else {
-            Device::list()?
-                .into_iter()
-                .find(|d| d.name == interface_name)
-                .ok_or_else(|| anyhow!("Interface '{}' not found", interface_name))?
-        };
+/// Where and how to save raw frames alongside the JSON output
+struct PcapOutput {
+    path: String,
+    format: SaveFormat,
+    comment: Option<String>,
+}
+
+/// Where to export the conversation table, if anywhere
+struct FlowReport {
+    path: Option<String>,
+    format: FlowFormat,
+}
 
+/// One interface or file feeding the capture
+struct Source {
+    name: String,
+    description: Option<String>,
+    linktype: Linktype,
+    precision: Precision,
+    /// `None` while the capture thread owns the handle
+    capture: Option<Capture<dyn Activated>>,
+}
+
+impl Source {
+    fn open(device: Device, options: &CaptureOptions) -> Result<Self> {
         info!(
             "Opening device: {} ({})",
             device.name,
             device.desc.as_deref().unwrap_or("No description")
         );
 
-        let capture = Capture::from_device(device)?
-            .promisc(true)
-            .snaplen(65535)
-            .timeout(1000)
-            .open()?;
+        let name = device.name.clone();
+        let description = device.desc.clone();
+        let (capture, precision) = options.open(device)?;
+
+        Ok(Source {
+            name,
+            description,
+            linktype: capture.get_datalink(),
+            precision,
+            capture: Some(capture.into()),
+        })
+    }
+
+    fn handle(&mut self) -> Result<&mut Capture<dyn Activated>> {
+        self.capture
+            .as_mut()
+            .ok_or_else(|| anyhow!("The capture is already running"))
+    }
+}
+
+pub struct PacketCapture {
+    sources: Vec<Source>,
+    snaplen: u32,
+    /// How long a live packet waits for earlier ones from other interfaces
+    merge_window: Option<Duration>,
+    offline: bool,
+    filter: Option<String>,
+    display_filter: Option<PacketFilter>,
+    output_format: OutputFormat,
+    pcap_output: Option<PcapOutput>,
+    rotation: Option<RotationPolicy>,
+    time_format: TimeFormat,
+    flow_report: Option<FlowReport>,
+    summary_output: Option<String>,
+    quiet: bool,
+    layer_detail: u8,
+    hex_dump: Option<HexDump>,
+    status_interval: Option<Duration>,
+    drop_threshold: f64,
+    shutdown: Shutdown,
+}
+
+impl PacketCapture {
+    pub fn new(interface_name: &str) -> Result<Self> {
+        Self::with_options(interface_name, &CaptureOptions::default())
+    }
+
+    /// Open a live capture with explicit snaplen, buffer, timestamp and
+    /// similar settings
+    pub fn with_options(interface_name: &str, options: &CaptureOptions) -> Result<Self> {
+        Self::with_interfaces(&[interface_name.to_string()], options)
+    }
+
+    /// Capture from several interfaces at once, merging their packets in
+    /// timestamp order. Without names the default device is opened; `any`
+    /// is the Linux pseudo-device that sees every interface.
+    pub fn with_interfaces(interface_names: &[String], options: &CaptureOptions) -> Result<Self> {
+        options.validate()?;
+        let sources = find_devices(interface_names)?
+            .into_iter()
+            .map(|device| Source::open(device, options))
+            .collect::<Result<Vec<_>>>()?;
 
         Ok(PacketCapture {
-            capture,
-            interface_name: interface_name.to_string(),
+            sources,
+            snaplen: options.snaplen as u32,
+            // libpcap may hold packets back for up to one read timeout
+            merge_window: Some(Duration::from_millis(options.timeout_ms as u64)),
+            offline: false,
+            filter: None,
+            display_filter: None,
+            output_format: OutputFormat::Json,
+            pcap_output: None,
+            rotation: None,
+            time_format: TimeFormat::Absolute,
+            flow_report: None,
+            summary_output: None,
+            quiet: false,
+            layer_detail: 0,
+            hex_dump: None,
+            status_interval: None,
+            drop_threshold: DEFAULT_DROP_THRESHOLD,
+            shutdown: Shutdown::new(),
+        })
+    }
+
+    /// Open a saved pcap or pcapng file as the packet source
+    pub fn from_file(path: &str) -> Result<Self> {
+        // libpcap scales microsecond files up, so nanosecond files keep full precision
+        let capture = Capture::from_file_with_precision(path, Precision::Nano)
+            .map_err(|e| anyhow!("Cannot open capture file '{}': {}", path, e))?;
+
+        info!("Opened capture file: {}", path);
+
+        Ok(PacketCapture {
+            sources: vec![Source {
+                name: path.to_string(),
+                description: None,
+                linktype: capture.get_datalink(),
+                precision: Precision::Nano,
+                capture: Some(capture.into()),
+            }],
+            // Largest snaplen libpcap will write, used when re-saving frames
+            snaplen: options::MAX_SNAPLEN as u32,
+            merge_window: None,
+            offline: true,
+            filter: None,
+            display_filter: None,
+            output_format: OutputFormat::Json,
+            pcap_output: None,
+            rotation: None,
+            time_format: TimeFormat::Absolute,
+            flow_report: None,
+            summary_output: None,
+            quiet: false,
+            layer_detail: 0,
+            hex_dump: None,
+            status_interval: None,
+            drop_threshold: DEFAULT_DROP_THRESHOLD,
+            shutdown: Shutdown::new(),
         })
     }
 
     pub fn set_filter(&mut self, filter: &str) -> Result<()> {
-        self.capture.filter(filter, true)?;
+        for source in &mut self.sources {
+            // Compile on a dead handle first for an error that names the link type
+            bpf::compile(filter, source.linktype, true)?;
+            source.handle()?.filter(filter, true)?;
+        }
+        self.filter = Some(filter.to_string());
         Ok(())
     }
 
+    /// Only show, count and save packets matching a display filter
+    pub fn set_display_filter(&mut self, filter: &str) -> Result<()> {
+        self.display_filter = Some(PacketFilter::new(filter)?);
+        Ok(())
+    }
+
+    /// Layout of the file given to `start_capture`
+    pub fn set_output_format(&mut self, format: OutputFormat) {
+        self.output_format = format;
+    }
+
+    /// Save the raw frames to a pcap or pcapng file while capturing
+    pub fn set_pcap_output(&mut self, path: &str, format: SaveFormat, comment: Option<String>) {
+        self.pcap_output = Some(PcapOutput {
+            path: path.to_string(),
+            format,
+            comment,
+        });
+    }
+
+    /// Rotate the pcap and JSON outputs through a series of files
+    pub fn set_rotation(&mut self, policy: RotationPolicy) {
+        self.rotation = Some(policy);
+    }
+
+    pub fn set_time_format(&mut self, time_format: TimeFormat) {
+        self.time_format = time_format;
+    }
+
+    /// Track conversations and print them when the capture ends
+    pub fn set_flows(&mut self, path: Option<String>, format: FlowFormat) {
+        self.flow_report = Some(FlowReport { path, format });
+    }
+
+    /// Also write the end-of-capture summary to a JSON file
+    pub fn set_summary_output(&mut self, path: &str) {
+        self.summary_output = Some(path.to_string());
+    }
+
+    /// Don't print a line per packet
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.quiet = quiet;
+    }
+
+    /// Follow each packet line with its decoded layers (`layer_detail` 1 for
+    /// the main fields, 2 for everything) and/or a hex dump
+    pub fn set_packet_detail(&mut self, layer_detail: u8, hex_dump: Option<HexDump>) {
+        self.layer_detail = layer_detail;
+        self.hex_dump = hex_dump;
+    }
+
+    /// Show throughput and drop counters of a live capture on stderr every
+    /// `interval`, warning when more than `drop_threshold` percent are lost
+    pub fn set_status(&mut self, interval: Option<Duration>, drop_threshold: f64) {
+        self.status_interval = interval;
+        self.drop_threshold = drop_threshold;
+    }
+
+    /// Stop the capture cleanly, saving everything, when a signal arrives
+    pub fn set_shutdown(&mut self, shutdown: Shutdown) {
+        self.shutdown = shutdown;
+    }
+
+    fn interfaces_meta(&self) -> Vec<InterfaceMeta> {
+        self.sources
+            .iter()
+            .map(|source| InterfaceMeta {
+                name: source.name.clone(),
+                description: source.description.clone(),
+                linktype: source.linktype.0,
+                snaplen: self.snaplen,
+                precision: source.precision,
+                filter: self.filter.clone(),
+            })
+            .collect()
+    }
+
+    /// Names of the interfaces or the file being read
+    pub fn source_name(&self) -> String {
+        let names: Vec<&str> = self
+            .sources
+            .iter()
+            .map(|source| source.name.as_str())
+            .collect();
+        names.join(", ")
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// Timestamp resolution common to all sources
+    pub fn precision(&self) -> Precision {
+        if self
+            .sources
+            .iter()
+            .any(|source| source.precision == Precision::Micro)
+        {
+            Precision::Micro
+        } else {
+            Precision::Nano
+        }
+    }
+
+    /// Hand the sources to the capture threads. Together with `decoder`
+    /// this lets front ends other than `start_capture` consume the packets.
+    pub fn spawn_engine(&mut self) -> Result<CaptureEngine> {
+        let captures = self
+            .sources
+            .iter_mut()
+            .map(|source| {
+                let capture = source
+                    .capture
+                    .take()
+                    .ok_or_else(|| anyhow!("The capture is already running"))?;
+                Ok((capture, source.precision))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        CaptureEngine::spawn(captures, !self.offline, self.merge_window)
+    }
+
+    /// Take the handles back from a stopped engine
+    fn restore_handles(&mut self, captures: Vec<Capture<dyn Activated>>) {
+        for (source, capture) in self.sources.iter_mut().zip(captures) {
+            source.capture = Some(capture);
+        }
+    }
+
+    /// A decoder for the link types of the sources, tagging packets with
+    /// their interface when there are several
+    pub fn decoder(&self) -> PacketDecoder {
+        match self.sources.as_slice() {
+            [source] => PacketDecoder::new(source.linktype),
+            sources => PacketDecoder::with_interfaces(
+                sources
+                    .iter()
+                    .map(|source| (source.name.clone(), source.linktype))
+                    .collect(),
+            ),
+        }
+    }
+
     pub async fn start_capture(&mut self, count: usize, output_file: Option<String>) -> Result<()> {
-        let mut output_writer = OutputWriter::new(output_file)?;
+        let mut output_writer =
+            OutputWriter::new(output_file, self.output_format, self.rotation.clone())?;
+        let mut savefile_writer = match &self.pcap_output {
+            Some(pcap_output) => Some(
+                SavefileWriter::create(
+                    &pcap_output.path,
+                    pcap_output.format,
+                    &self.interfaces_meta(),
+                    pcap_output.comment.as_deref(),
+                    self.rotation.clone(),
+                )
+                .await?,
+            ),
+            None => None,
+        };
         let mut packet_count = 0;
         let unlimited = count == 0;
+        let mut decoder = self.decoder();
+        let mut flows = self.flow_report.as_ref().map(|_| FlowTable::new());
+        let mut summary = SummaryBuilder::new();
 
-        println!(
-            "{}",
-            format!("🔍 Capturing packets on interface: {}", self.interface_name).bright_green()
-        );
+        if self.offline {
+            println!(
+                "{}",
+                format!("📂 Reading packets from file: {}", self.source_name()).bright_green()
+            );
+        } else {
+            println!(
+                "{}",
+                format!(
+                    "🔍 Capturing packets on {}: {}",
+                    if self.sources.len() > 1 {
+                        "interfaces"
+                    } else {
+                        "interface"
+                    },
+                    self.source_name()
+                )
+                .bright_green()
+            );
+        }
         if !unlimited {
             println!(
                 "{}",
                 format!("📊 Will capture {} packets", count).bright_blue()
             );
-        } else {
+        } else if !self.offline {
             println!(
                 "{}",
                 "📊 Capturing unlimited packets (Ctrl+C to stop)".bright_blue()
@@ -75,144 +413,154 @@ impl PacketCapture {
         }
         println!();
 
+        let mut status = match self.status_interval {
+            Some(interval) if !self.offline => Some(StatusLine::new(interval, self.drop_threshold)),
+            _ => None,
+        };
+        let mut ticker = tokio::time::interval(
+            status
+                .as_ref()
+                .map_or(Duration::from_secs(1), StatusLine::interval),
+        );
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        ticker.tick().await;
+        // Every frame read, before the display filter, for the status line
+        let mut frames: u64 = 0;
+        let mut frame_bytes: u64 = 0;
+
+        let mut engine = self.spawn_engine()?;
         loop {
             if !unlimited && packet_count >= count {
                 break;
             }
 
-            match self.capture.next_packet() {
-                Ok(packet) => {
-                    packet_count += 1;
-
-                    let timestamp = SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs();
+            let raw_packet = tokio::select! {
+                packet = engine.next_packet() => match packet {
+                    Some(packet) => packet,
+                    // End of the capture file
+                    None => break,
+                },
+                _ = ticker.tick(), if status.is_some() => {
+                    if let Some(status) = status.as_mut() {
+                        status.tick(frames, frame_bytes, engine.stats(), engine.queued());
+                    }
+                    continue;
+                }
+                _ = self.shutdown.wait() => break,
+            };
+            frames += 1;
+            frame_bytes += raw_packet.len as u64;
 
-                    let packet_data = packet.data.to_vec();
-                    let packet_info = self.parse_packet(&packet_data, timestamp);
+            let packet_info = decoder.decode(&raw_packet);
 
-                    // Print packet info to console
-                    self.print_packet_info(&packet_info, packet_count);
+            if let Some(filter) = &self.display_filter
+                && !filter.matches(&packet_info)
+            {
+                continue;
+            }
+            packet_count += 1;
 
-                    // Write to output file if specified
-                    output_writer.write_packet(&packet_info).await?;
+            summary.update(&packet_info);
+            if let Some(flows) = flows.as_mut() {
+                flows.update(&packet_info);
+            }
 
-                    // Small delay to make output readable
-                    tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+            // Print packet info to console
+            if !self.quiet {
+                if let Some(status) = status.as_mut() {
+                    status.clear();
                 }
-                Err(pcap::Error::TimeoutExpired) => {
-                    // Timeout is normal, continue
-                    continue;
+                self.print_packet_info(&packet_info, packet_count);
+                if self.layer_detail > 0 {
+                    display::print_layer_tree(&packet_info, self.layer_detail);
                 }
-                Err(e) => {
-                    warn!("Error capturing packet: {}", e);
-                    continue;
+                if let Some(mode) = self.hex_dump {
+                    display::print_hex_dump(&raw_packet.data, &packet_info.layers, mode);
                 }
             }
-        }
 
-        output_writer.close().await?;
+            // Write to output file if specified
+            output_writer.write_packet(&packet_info).await?;
 
-        println!();
-        println!(
-            "{}",
-            format!("🎉 Captured {} packets successfully!", packet_count)
-                .bright_green()
-                .bold()
-        );
-
-        Ok(())
-    }
+            if let Some(writer) = savefile_writer.as_mut() {
+                writer.write_packet(&raw_packet).await?;
+            }
+        }
 
-    fn parse_packet(&self, data: &[u8], timestamp: u64) -> PacketInfo {
-        let mut packet_info = PacketInfo {
-            timestamp,
-            length: data.len(),
-            protocol: "Unknown".to_string(),
-            src_addr: None,
-            dst_addr: None,
-            src_port: None,
-            dst_port: None,
-            info: None,
+        let mut captures = engine.stop().await?;
+        if let Some(status) = status.as_mut() {
+            status.clear();
+        }
+        let stats = if self.offline {
+            None
+        } else {
+            let stats: Vec<CaptureStats> = captures
+                .iter_mut()
+                .filter_map(|capture| {
+                    capture
+                        .stats()
+                        .map(CaptureStats::from)
+                        .map_err(|e| debug!("Capture statistics unavailable: {}", e))
+                        .ok()
+                })
+                .collect();
+            (!stats.is_empty()).then(|| stats.into_iter().sum())
         };
+        self.restore_handles(captures);
 
-        if let Some(ethernet) = EthernetPacket::new(data) {
-            debug!(
-                "Ethernet packet: {} -> {}",
-                ethernet.get_source(),
-                ethernet.get_destination()
+        output_writer.close().await?;
+        if let Some(writer) = savefile_writer.as_mut() {
+            writer.close().await?;
+        }
+
+        println!();
+        if let Some(signal) = self.shutdown.signal() {
+            println!(
+                "{}",
+                format!("🛑 Stopped by {} after {} packets", signal.name(), packet_count)
+                    .bright_yellow()
+                    .bold()
             );
+        } else {
+            println!(
+                "{}",
+                format!("🎉 Captured {} packets successfully!", packet_count)
+                    .bright_green()
+                    .bold()
+            );
+        }
 
-            match ethernet.get_ethertype() {
-                EtherTypes::Ipv4 => {
-                    if let Some(ipv4) = Ipv4Packet::new(ethernet.payload()) {
-                        packet_info.src_addr = Some(ipv4.get_source().to_string());
-                        packet_info.dst_addr = Some(ipv4.get_destination().to_string());
-
-                        match ipv4.get_next_level_protocol() {
-                            pnet::packet::ip::IpNextHeaderProtocols::Tcp => {
-                                packet_info.protocol = "TCP".to_string();
-                                if let Some(tcp) = TcpPacket::new(ipv4.payload()) {
-                                    packet_info.src_port = Some(tcp.get_source());
-                                    packet_info.dst_port = Some(tcp.get_destination());
-                                    packet_info.info =
-                                        Some(format!("Flags: {:?}", tcp.get_flags()));
-                                }
-                            }
-                            pnet::packet::ip::IpNextHeaderProtocols::Udp => {
-                                packet_info.protocol = "UDP".to_string();
-                                if let Some(udp) = UdpPacket::new(ipv4.payload()) {
-                                    packet_info.src_port = Some(udp.get_source());
-                                    packet_info.dst_port = Some(udp.get_destination());
-                                }
-                            }
-                            pnet::packet::ip::IpNextHeaderProtocols::Icmp => {
-                                packet_info.protocol = "ICMP".to_string();
-                                if let Some(icmp) = IcmpPacket::new(ipv4.payload()) {
-                                    packet_info.info = Some(format!(
-                                        "Type: {:?}, Code: {:?}",
-                                        icmp.get_icmp_type(),
-                                        icmp.get_icmp_code()
-                                    ));
-                                }
-                            }
-                            _ => {
-                                packet_info.protocol =
-                                    format!("IPv4 ({})", ipv4.get_next_level_protocol());
-                            }
-                        }
-                    }
-                }
-                EtherTypes::Ipv6 => {
-                    if let Some(ipv6) = Ipv6Packet::new(ethernet.payload()) {
-                        packet_info.protocol = "IPv6".to_string();
-                        packet_info.src_addr = Some(ipv6.get_source().to_string());
-                        packet_info.dst_addr = Some(ipv6.get_destination().to_string());
-                    }
-                }
-                EtherTypes::Arp => {
-                    packet_info.protocol = "ARP".to_string();
-                }
-                _ => {
-                    packet_info.protocol = format!("Ethernet ({})", ethernet.get_ethertype());
-                }
+        let mut summary = summary.finish();
+        summary.capture_stats = stats;
+        summary.print();
+        if let Some(stats) = stats {
+            stats.warn_if_dropping(self.drop_threshold, "during the capture");
+        }
+        if let Some(path) = &self.summary_output {
+            summary.save(path).await?;
+        }
+
+        if let (Some(flows), Some(report)) = (&flows, &self.flow_report) {
+            flows.print_table();
+            if let Some(path) = &report.path {
+                flows.export(path, report.format).await?;
             }
         }
 
-        packet_info
+        Ok(())
     }
 
     fn print_packet_info(&self, packet: &PacketInfo, count: usize) {
-        let timestamp_str = chrono::DateTime::from_timestamp(packet.timestamp as i64, 0)
-            .map(|dt| dt.format("%H:%M:%S").to_string())
-            .unwrap_or_else(|| "Unknown".to_string());
+        let timestamp_str = format_time(packet, self.time_format, self.precision());
 
         let protocol_colored = match packet.protocol.as_str() {
             "TCP" => packet.protocol.bright_red(),
             "UDP" => packet.protocol.bright_blue(),
             "ICMP" => packet.protocol.bright_yellow(),
             "ARP" => packet.protocol.bright_green(),
+            "DNS" => packet.protocol.bright_cyan(),
+            "HTTP" => packet.protocol.green(),
+            "TLS" => packet.protocol.magenta(),
             _ => packet.protocol.normal(),
         };
 
@@ -228,10 +576,18 @@ impl PacketCapture {
 
         print!("{} ", format!("[{}]", count).bright_cyan());
         print!("{} ", timestamp_str.bright_black());
+        if let Some(interface) = &packet.interface {
+            print!("{} ", format!("{:<8}", interface).blue());
+        }
         print!("{:>8} ", protocol_colored);
         print!("{:>6} bytes ", packet.length.to_string().bright_magenta());
         print!("{}", src_dst.bright_white());
 
+        if !packet.vlan_ids.is_empty() {
+            let ids: Vec<String> = packet.vlan_ids.iter().map(|id| id.to_string()).collect();
+            print!(" {}", format!("[VLAN {}]", ids.join("/")).bright_yellow());
+        }
+
         if let Some(info) = &packet.info {
             print!(" {}", info.bright_black());
         }
@@ -240,38 +596,392 @@ impl PacketCapture {
     }
 }
 
-pub fn list_interfaces() -> Result<()> {
-    let devices = Device::list()?;
+/// Render a packet time for the console in the requested style
+pub fn format_time(packet: &PacketInfo, time_format: TimeFormat, precision: Precision) -> String {
+    let digits = match precision {
+        Precision::Nano => 9,
+        Precision::Micro => 6,
+    };
+    let fraction = |nanos: u32| format!("{:09}", nanos)[..digits].to_string();
+    let seconds = |secs: f64| format!("{:.*}", digits, secs);
+
+    match time_format {
+        TimeFormat::Absolute | TimeFormat::Date => {
+            let pattern = if time_format == TimeFormat::Date {
+                "%Y-%m-%d %H:%M:%S"
+            } else {
+                "%H:%M:%S"
+            };
+            chrono::DateTime::from_timestamp(packet.timestamp.secs as i64, 0)
+                .map(|dt| {
+                    format!(
+                        "{}.{}",
+                        dt.format(pattern),
+                        fraction(packet.timestamp.nanos)
+                    )
+                })
+                .unwrap_or_else(|| "Unknown".to_string())
+        }
+        TimeFormat::Epoch => format!(
+            "{}.{}",
+            packet.timestamp.secs,
+            fraction(packet.timestamp.nanos)
+        ),
+        TimeFormat::Delta => seconds(packet.time_delta),
+        TimeFormat::Relative => seconds(packet.time_relative),
+    }
+}
+
+/// Turns captured frames into packet summaries, keeping the stream state
+/// and the timing of earlier packets
+pub struct PacketDecoder {
+    /// One per source, as link types and TCP streams are per interface
+    dissectors: Vec<Dissector>,
+    /// Names to tag packets with when there are several sources
+    interfaces: Vec<String>,
+    first_timestamp: Option<Timestamp>,
+    previous_timestamp: Option<Timestamp>,
+}
+
+impl PacketDecoder {
+    pub fn new(linktype: Linktype) -> Self {
+        PacketDecoder {
+            dissectors: vec![Dissector::new(linktype.0)],
+            interfaces: Vec::new(),
+            first_timestamp: None,
+            previous_timestamp: None,
+        }
+    }
+
+    /// Decode frames from several interfaces, indexed as in
+    /// `RawPacket::interface`
+    pub fn with_interfaces(interfaces: Vec<(String, Linktype)>) -> Self {
+        let (interfaces, linktypes): (Vec<String>, Vec<Linktype>) = interfaces.into_iter().unzip();
+        PacketDecoder {
+            dissectors: linktypes
+                .into_iter()
+                .map(|linktype| Dissector::new(linktype.0))
+                .collect(),
+            interfaces,
+            first_timestamp: None,
+            previous_timestamp: None,
+        }
+    }
+
+    pub fn decode(&mut self, raw_packet: &RawPacket) -> PacketInfo {
+        let timestamp = raw_packet.timestamp;
+        let index = raw_packet.interface.min(self.dissectors.len() - 1);
+        let mut packet_info =
+            parse_packet(&mut self.dissectors[index], &raw_packet.data, timestamp);
+        packet_info.interface = self.interfaces.get(raw_packet.interface).cloned();
+        // Count the frame as it was on the wire, even when the snaplen cut it short
+        packet_info.length = raw_packet.len as usize;
+        packet_info.time_relative = timestamp
+            .since(*self.first_timestamp.get_or_insert(timestamp))
+            .as_secs_f64();
+        packet_info.time_delta = self
+            .previous_timestamp
+            .map(|previous| timestamp.since(previous).as_secs_f64())
+            .unwrap_or(0.0);
+        self.previous_timestamp = Some(timestamp);
+        packet_info
+    }
+}
+
+/// Look up the devices to capture from, the default one when no names are
+/// given
+fn find_devices(names: &[String]) -> Result<Vec<Device>> {
+    if names.is_empty() {
+        let device = Device::lookup()?.ok_or_else(|| anyhow!("No default device found"))?;
+        return Ok(vec![device]);
+    }
+
+    let available = Device::list()?;
+    let mut devices = Vec::with_capacity(names.len());
+    for (i, name) in names.iter().enumerate() {
+        if names[..i].contains(name) {
+            return Err(anyhow!("Interface '{}' is given more than once", name));
+        }
+        let device = available
+            .iter()
+            .find(|device| &device.name == name)
+            .cloned()
+            .ok_or_else(|| {
+                if name == "any" {
+                    anyhow!("The 'any' pseudo-device is only available on Linux")
+                } else {
+                    anyhow!("Interface '{}' not found", name)
+                }
+            })?;
+        devices.push(device);
+    }
+    if devices.len() > 1 && names.iter().any(|name| name == "any") {
+        warn!("'any' already sees every interface, so some packets will be captured twice");
+    }
+    Ok(devices)
+}
+
+/// Decode a captured frame into a packet summary, feeding TCP payloads
+/// through the dissector's stream reassembly
+pub fn parse_packet(dissector: &mut Dissector, data: &[u8], timestamp: Timestamp) -> PacketInfo {
+    PacketInfo::from_layers(timestamp, data.len(), dissector.dissect(data, timestamp))
+}
+
+/// Enumerate capture interfaces: the system's interfaces plus libpcap-only
+/// pseudo-devices such as "any", with the link types each can capture with
+pub fn interfaces() -> Result<Vec<InterfaceInfo>> {
+    let mut system = hakinet_common::list_interfaces();
+    let mut interfaces = Vec::new();
 
-    if devices.is_empty() {
+    for device in Device::list()? {
+        let mut interface = match system.iter().position(|i| i.name == device.name) {
+            Some(index) => system.remove(index),
+            None => interface_from_device(&device),
+        };
+        if interface.description.is_none() {
+            interface.description = device.desc.clone();
+        }
+        interface.flags.wireless |= device.flags.is_wireless();
+        interface.link_types = link_types(device);
+        interfaces.push(interface);
+    }
+    // Interfaces libpcap can't open, e.g. ones that are down on some platforms
+    interfaces.extend(system);
+
+    Ok(interfaces)
+}
+
+fn interface_from_device(device: &Device) -> InterfaceInfo {
+    let mut interface = InterfaceInfo::named(&device.name);
+    interface.flags = InterfaceFlags {
+        up: device.flags.is_up(),
+        running: device.flags.is_running(),
+        loopback: device.flags.is_loopback(),
+        wireless: device.flags.is_wireless(),
+    };
+    interface.addresses = device
+        .addresses
+        .iter()
+        .map(|address| InterfaceAddress {
+            addr: address.addr,
+            netmask: address.netmask,
+            broadcast: address.broadcast_addr,
+            prefix: address
+                .netmask
+                .and_then(|mask| pnet::ipnetwork::ip_mask_to_prefix(mask).ok()),
+        })
+        .collect();
+    interface
+}
+
+/// Link types libpcap offers on a device. Opening it needs capture
+/// privileges; without them the list stays empty.
+fn link_types(device: Device) -> Vec<InterfaceLinkType> {
+    let name = device.name.clone();
+    let datalinks = Capture::from_device(device)
+        .and_then(|capture| capture.open())
+        .and_then(|capture| capture.list_datalinks());
+    match datalinks {
+        Ok(datalinks) => datalinks
+            .into_iter()
+            .map(|linktype| InterfaceLinkType {
+                id: linktype.0,
+                name: linktype
+                    .get_name()
+                    .unwrap_or_else(|_| linktype.0.to_string()),
+                description: linktype.get_description().ok(),
+            })
+            .collect(),
+        Err(e) => {
+            debug!("Cannot list link types of {}: {}", name, e);
+            Vec::new()
+        }
+    }
+}
+
+/// Print the interface list, as JSON for scripts or as a colored list
+pub fn list_interfaces(json: bool) -> Result<()> {
+    let interfaces = interfaces()?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&interfaces)?);
+        return Ok(());
+    }
+
+    if interfaces.is_empty() {
         println!("{}", "No network interfaces found!".bright_red());
         return Ok(());
     }
 
-    for (i, device) in devices.iter().enumerate() {
-        let status = if device.flags.is_up() {
+    for (i, interface) in interfaces.iter().enumerate() {
+        let flags = &interface.flags;
+        let mut status = vec![if flags.up {
             "UP".bright_green()
         } else {
             "DOWN".bright_red()
-        };
+        }];
+        if flags.running {
+            status.push("RUNNING".bright_green());
+        }
+        if flags.loopback {
+            status.push("LOOPBACK".bright_yellow());
+        }
+        if flags.wireless {
+            status.push("WIRELESS".bright_magenta());
+        }
+        let status: Vec<String> = status.iter().map(ToString::to_string).collect();
 
         println!(
-            "{} {}: {} [{}]",
-            "🔌".to_string(),
+            "🔌 {}: {} [{}]",
             (i + 1).to_string().bright_cyan(),
-            device.name.bright_white().bold(),
-            status
+            interface.name.bright_white().bold(),
+            status.join(", ")
         );
 
-        if let Some(desc) = &device.desc {
+        if let Some(desc) = &interface.description {
             println!("   📝 {}", desc.bright_black());
         }
 
-        for addr in &device.addresses {
-            println!("   🌐 {}", addr.addr.to_string().bright_blue());
+        let mut link = Vec::new();
+        if let Some(mac) = &interface.mac {
+            link.push(format!("MAC {}", mac));
+        }
+        if let Some(mtu) = interface.mtu {
+            link.push(format!("MTU {}", mtu));
+        }
+        if !link.is_empty() {
+            println!("   🏷️  {}", link.join("  "));
+        }
+
+        for address in &interface.addresses {
+            let mut line = address.addr.to_string().bright_blue().to_string();
+            if let Some(prefix) = address.prefix {
+                line.push_str(&format!("/{}", prefix));
+            }
+            if let Some(broadcast) = address.broadcast {
+                line.push_str(&format!(" brd {}", broadcast).bright_black().to_string());
+            }
+            println!("   🌐 {}", line);
+        }
+
+        if !interface.link_types.is_empty() {
+            let names: Vec<&str> = interface
+                .link_types
+                .iter()
+                .map(|linktype| linktype.name.as_str())
+                .collect();
+            println!("   🔗 {}", names.join(", ").bright_black());
         }
         println!();
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::read_packet;
+    use hakinet_common::{Layer, TcpOption};
+
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/sample.pcap");
+
+    fn read_fixture() -> Vec<RawPacket> {
+        let mut capture = PacketCapture::from_file(FIXTURE).unwrap();
+        let source = &mut capture.sources[0];
+        let handle = source.capture.as_mut().unwrap();
+        let mut packets = Vec::new();
+        loop {
+            match read_packet(handle, source.precision) {
+                Ok(packet) => packets.push(packet),
+                Err(pcap::Error::NoMorePackets) => break,
+                Err(e) => panic!("unexpected error reading fixture: {}", e),
+            }
+        }
+        packets
+    }
+
+    #[test]
+    fn test_read_fixture_file() {
+        let packets = read_fixture();
+        assert_eq!(packets.len(), 3);
+        assert_eq!(packets[0].timestamp, Timestamp::new(1_700_000_000, 100_000));
+        assert_eq!(packets[1].timestamp, Timestamp::new(1_700_000_000, 250_300_000));
+        assert_eq!(packets[0].len as usize, packets[0].data.len());
+    }
+
+    #[test]
+    fn test_parse_fixture_packets() {
+        let packets = read_fixture();
+        let mut dissector = Dissector::new(crate::dissect::LINKTYPE_ETHERNET);
+        let infos: Vec<PacketInfo> = packets
+            .iter()
+            .map(|p| parse_packet(&mut dissector, &p.data, p.timestamp))
+            .collect();
+
+        assert_eq!(infos[0].protocol, "TCP");
+        assert_eq!(infos[0].src_addr.as_deref(), Some("192.168.1.10"));
+        assert_eq!(infos[0].dst_port, Some(80));
+        assert_eq!(infos[0].info.as_deref(), Some("[SYN] Seq=1000 Win=64240 Len=0"));
+        assert_eq!(infos[0].layers.len(), 3);
+        let Layer::Tcp(tcp) = &infos[0].layers[2] else {
+            panic!("expected TCP layer");
+        };
+        assert_eq!(tcp.options, vec![TcpOption::Mss { value: 1460 }]);
+
+        assert_eq!(infos[1].protocol, "DNS");
+        assert_eq!(infos[1].dst_addr.as_deref(), Some("8.8.8.8"));
+        assert_eq!(infos[1].dst_port, Some(53));
+        assert_eq!(infos[1].info.as_deref(), Some("query A example.com"));
+
+        assert_eq!(infos[2].protocol, "ARP");
+        assert_eq!(
+            infos[2].info.as_deref(),
+            Some("Who has 192.168.1.1? Tell 192.168.1.10")
+        );
+    }
+
+    #[test]
+    fn test_decoder_tags_interfaces() {
+        let mut packets = read_fixture();
+        packets[1].interface = 1;
+
+        let mut decoder = PacketDecoder::new(Linktype::ETHERNET);
+        assert_eq!(decoder.decode(&packets[1]).interface, None);
+
+        let mut decoder = PacketDecoder::with_interfaces(vec![
+            ("eth0".to_string(), Linktype::ETHERNET),
+            ("eth1".to_string(), Linktype::ETHERNET),
+        ]);
+        let infos: Vec<PacketInfo> = packets.iter().map(|p| decoder.decode(p)).collect();
+        assert_eq!(infos[0].interface.as_deref(), Some("eth0"));
+        assert_eq!(infos[1].interface.as_deref(), Some("eth1"));
+        assert_eq!(infos[1].protocol, "DNS");
+        assert_eq!(infos[1].time_relative, 0.2502);
+    }
+
+    #[test]
+    fn test_format_time() {
+        let mut packet = PacketInfo::new();
+        packet.timestamp = Timestamp::new(1_700_000_000, 250_300_123);
+        packet.time_delta = 0.0123;
+        packet.time_relative = 1.5;
+
+        assert_eq!(
+            format_time(&packet, TimeFormat::Absolute, Precision::Micro),
+            "22:13:20.250300"
+        );
+        assert_eq!(
+            format_time(&packet, TimeFormat::Epoch, Precision::Nano),
+            "1700000000.250300123"
+        );
+        assert_eq!(
+            format_time(&packet, TimeFormat::Delta, Precision::Micro),
+            "0.012300"
+        );
+        assert_eq!(
+            format_time(&packet, TimeFormat::Relative, Precision::Micro),
+            "1.500000"
+        );
+    }
+}
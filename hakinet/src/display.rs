@@ -0,0 +1,341 @@
+//! Detailed console views of a packet: a tree of the decoded layers and a
+//! tcpdump -X style hex/ASCII dump colored by layer
+
+use colored::*;
+use hakinet_common::{Layer, PacketInfo};
+use serde_json::Value;
+
+/// Which bytes the hex dump covers, as tcpdump's -X and -XX do
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HexDump {
+    /// From the network layer on (-X)
+    Payload,
+    /// The whole frame including the link-layer header (-XX)
+    Frame,
+}
+
+impl HexDump {
+    /// Map a repeated -X flag to a dump mode
+    pub fn from_count(count: u8) -> Option<Self> {
+        match count {
+            0 => None,
+            1 => Some(HexDump::Payload),
+            _ => Some(HexDump::Frame),
+        }
+    }
+}
+
+/// One line of the layer tree: a layer heading at depth 0, fields below it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeLine {
+    pub depth: usize,
+    pub name: String,
+    /// Empty for nested structures whose entries follow on deeper lines
+    pub value: String,
+}
+
+/// Each decoded layer with its fields. At level 1 nested structures are
+/// summarized; from level 2 on they are expanded in full.
+pub fn layer_tree(packet: &PacketInfo, level: u8) -> Vec<TreeLine> {
+    let mut lines = Vec::new();
+    for layer in &packet.layers {
+        lines.push(TreeLine {
+            depth: 0,
+            name: layer.name().to_string(),
+            value: layer.info().unwrap_or_default(),
+        });
+
+        let Ok(Value::Object(fields)) = serde_json::to_value(layer) else {
+            continue;
+        };
+        for (name, value) in fields.iter().filter(|(name, _)| name.as_str() != "layer") {
+            push_field(&mut lines, name, value, 1, level);
+        }
+    }
+    lines
+}
+
+/// Print the layer tree under a packet line
+pub fn print_layer_tree(packet: &PacketInfo, level: u8) {
+    for line in layer_tree(packet, level) {
+        let indent = " ".repeat(4 + line.depth * 2);
+        if line.depth == 0 {
+            println!(
+                "{}{} {}",
+                indent,
+                line.name.bright_cyan().bold(),
+                line.value.bright_black()
+            );
+        } else if line.value.is_empty() {
+            println!("{}{}:", indent, line.name.bright_blue());
+        } else {
+            println!("{}{}: {}", indent, line.name.bright_blue(), line.value);
+        }
+    }
+}
+
+fn push_field(lines: &mut Vec<TreeLine>, name: &str, value: &Value, depth: usize, level: u8) {
+    let mut push = |value: String| {
+        lines.push(TreeLine {
+            depth,
+            name: name.to_string(),
+            value,
+        })
+    };
+    match value {
+        Value::Null => {}
+        Value::Object(map) if map.values().all(Value::is_boolean) => {
+            // Flag sets read better as the names of the flags that are set
+            let set: Vec<&str> = map
+                .iter()
+                .filter(|(_, v)| v.as_bool() == Some(true))
+                .map(|(k, _)| k.as_str())
+                .collect();
+            push(if set.is_empty() {
+                "none".to_string()
+            } else {
+                set.join(", ")
+            });
+        }
+        Value::Array(items) if items.iter().all(is_scalar) => {
+            let items: Vec<String> = items.iter().map(scalar).collect();
+            push(format!("[{}]", items.join(", ")));
+        }
+        Value::Object(_) | Value::Array(_) if level < 2 => {
+            let count = match value {
+                Value::Array(items) => items.len(),
+                Value::Object(map) => map.len(),
+                _ => 0,
+            };
+            push(format!("[{} entries]", count));
+        }
+        Value::Object(map) => {
+            push(String::new());
+            for (key, value) in map {
+                push_field(lines, key, value, depth + 1, level);
+            }
+        }
+        Value::Array(items) => {
+            push(String::new());
+            for (i, item) in items.iter().enumerate() {
+                push_field(lines, &format!("[{}]", i), item, depth + 1, level);
+            }
+        }
+        value => push(scalar(value)),
+    }
+}
+
+fn is_scalar(value: &Value) -> bool {
+    !matches!(value, Value::Object(_) | Value::Array(_))
+}
+
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Where each header ends, by layer group
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Regions {
+    link: usize,
+    network: usize,
+    transport: usize,
+}
+
+/// Work out header boundaries from the decoded layers. Anything after the
+/// last header whose size is known counts as payload.
+fn regions(layers: &[Layer], frame_len: usize) -> Regions {
+    let mut regions = Regions::default();
+    let mut offset = 0;
+    for layer in layers {
+        let (size, group) = match layer {
+            Layer::Ethernet(_) => (14, 0),
+            Layer::Vlan(_) => (4, 0),
+            Layer::LinuxSll(sll) if sll.version == 2 => (20, 0),
+            Layer::LinuxSll(_) => (16, 0),
+            Layer::Loopback(_) => (4, 0),
+            Layer::Arp(_) => (28, 1),
+            Layer::Ipv4(ip) => (ip.header_length as usize, 1),
+            Layer::Ipv6(ip) => {
+                let extensions: usize = ip
+                    .extensions
+                    .iter()
+                    .map(|extension| match extension {
+                        hakinet_common::Ipv6Extension::HopByHop { length }
+                        | hakinet_common::Ipv6Extension::Routing { length, .. }
+                        | hakinet_common::Ipv6Extension::DestinationOptions { length } => {
+                            *length as usize
+                        }
+                        hakinet_common::Ipv6Extension::Fragment { .. } => 8,
+                    })
+                    .sum();
+                (40 + extensions, 1)
+            }
+            Layer::Tcp(tcp) => (tcp.header_length as usize, 2),
+            Layer::Udp(_) => (8, 2),
+            Layer::Icmp(_) | Layer::Icmpv6(_) => (8, 2),
+            Layer::Dns(_) | Layer::Http(_) | Layer::Tls(_) => break,
+        };
+        offset = (offset + size).min(frame_len);
+        match group {
+            0 => regions.link = offset,
+            1 => regions.network = offset,
+            _ => regions.transport = offset,
+        }
+    }
+    regions.network = regions.network.max(regions.link);
+    regions.transport = regions.transport.max(regions.network);
+    regions
+}
+
+/// Which header a byte belongs to, for coloring
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    Link,
+    Network,
+    Transport,
+    Payload,
+}
+
+/// Up to 16 bytes of a hex dump
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HexRow {
+    /// Offset of the first byte from the start of the dump
+    pub offset: usize,
+    pub bytes: Vec<(u8, Region)>,
+}
+
+impl HexRow {
+    /// Spaces after the hex column of a short row so the ASCII column lines up
+    pub fn padding(&self) -> usize {
+        let len = self.bytes.len();
+        40 - (len * 2 + len.div_ceil(2))
+    }
+}
+
+/// How a byte shows in the ASCII column
+pub fn printable(byte: u8) -> char {
+    if byte.is_ascii_graphic() || byte == b' ' {
+        byte as char
+    } else {
+        '.'
+    }
+}
+
+/// Split the dumped part of `data` into rows of 16 bytes
+pub fn hex_rows(data: &[u8], layers: &[Layer], mode: HexDump) -> Vec<HexRow> {
+    let regions = regions(layers, data.len());
+    let start = match mode {
+        HexDump::Payload => regions.link,
+        HexDump::Frame => 0,
+    };
+    let region = |index: usize| {
+        if index < regions.link {
+            Region::Link
+        } else if index < regions.network {
+            Region::Network
+        } else if index < regions.transport {
+            Region::Transport
+        } else {
+            Region::Payload
+        }
+    };
+
+    data[start..]
+        .chunks(16)
+        .enumerate()
+        .map(|(line, chunk)| HexRow {
+            offset: line * 16,
+            bytes: chunk
+                .iter()
+                .enumerate()
+                .map(|(i, byte)| (*byte, region(start + line * 16 + i)))
+                .collect(),
+        })
+        .collect()
+}
+
+/// Print `data` as offset, hex and ASCII columns, 16 bytes per line
+pub fn print_hex_dump(data: &[u8], layers: &[Layer], mode: HexDump) {
+    let paint = |region: Region, text: String| -> ColoredString {
+        match region {
+            Region::Link => text.bright_blue(),
+            Region::Network => text.bright_green(),
+            Region::Transport => text.bright_yellow(),
+            Region::Payload => text.normal(),
+        }
+    };
+
+    for row in hex_rows(data, layers, mode) {
+        let mut hex = String::new();
+        let mut ascii = String::new();
+        for (i, (byte, region)) in row.bytes.iter().enumerate() {
+            if i % 2 == 0 {
+                hex.push(' ');
+            }
+            hex.push_str(&paint(*region, format!("{:02x}", byte)).to_string());
+            ascii.push_str(&paint(*region, printable(*byte).to_string()).to_string());
+        }
+        println!(
+            "\t{}: {}{}  {}",
+            format!("0x{:04x}", row.offset).bright_black(),
+            hex,
+            " ".repeat(row.padding()),
+            ascii
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::parse_packet;
+    use crate::dissect::{Dissector, LINKTYPE_ETHERNET};
+    use crate::testutil::Frame;
+    use hakinet_common::Timestamp;
+
+    #[test]
+    fn test_regions() {
+        // Ethernet + 802.1Q + IPv4 with options + UDP + 4 bytes of payload
+        let frame = Frame::new("10.0.0.1:40000", "10.0.0.2:9000")
+            .vlan(10)
+            .ip_options(&[1, 1, 1, 1])
+            .udp(b"ping");
+        let packet = parse_packet(
+            &mut Dissector::new(LINKTYPE_ETHERNET),
+            &frame,
+            Timestamp::new(1, 0),
+        );
+
+        assert_eq!(
+            regions(&packet.layers, frame.len()),
+            Regions {
+                link: 18,
+                network: 42,
+                transport: 50,
+            }
+        );
+        // Truncated by the snaplen
+        assert_eq!(regions(&packet.layers, 30).transport, 30);
+
+        // -X starts at the IP header, -XX at the start of the frame
+        let rows = hex_rows(&frame, &packet.layers, HexDump::Payload);
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].bytes[0], (0x46, Region::Network));
+        assert_eq!(rows[2].bytes.len(), 4);
+        assert_eq!(rows[2].bytes[0], (b'p', Region::Payload));
+        assert_eq!(rows[2].padding(), 30);
+        let rows = hex_rows(&frame, &packet.layers, HexDump::Frame);
+        assert_eq!(rows[0].bytes[0].1, Region::Link);
+        assert_eq!(rows[3].offset, 48);
+    }
+
+    #[test]
+    fn test_hex_dump_mode() {
+        assert_eq!(HexDump::from_count(0), None);
+        assert_eq!(HexDump::from_count(1), Some(HexDump::Payload));
+        assert_eq!(HexDump::from_count(2), Some(HexDump::Frame));
+    }
+}
@@ -0,0 +1,949 @@
+//! Protocol dissectors turning raw frames into typed layers
+
+use hakinet_common::{
+    ArpLayer, EthernetLayer, IcmpLayer, Icmpv6Layer, Ipv4Layer, Ipv6Extension, Ipv6Layer, Layer,
+    LinuxSllLayer, LoopbackLayer, TcpFlags, TcpLayer, TcpOption, Timestamp, UdpLayer, VlanLayer,
+};
+use log::debug;
+use pnet::packet::Packet;
+use pnet::packet::arp::ArpPacket;
+use pnet::packet::ethernet::{EtherType, EtherTypes, EthernetPacket};
+use pnet::packet::icmp::IcmpPacket;
+use pnet::packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
+use pnet::packet::ipv4::Ipv4Packet;
+use pnet::packet::ipv6::Ipv6Packet;
+use pnet::packet::tcp::TcpPacket;
+use pnet::packet::udp::UdpPacket;
+use std::net::Ipv6Addr;
+
+use crate::reassembly::{Stream, StreamKey, TcpReassembler};
+
+mod dns;
+mod http;
+mod tls;
+
+// libpcap LINKTYPE_ values for the link layers we understand
+pub const LINKTYPE_NULL: i32 = 0;
+pub const LINKTYPE_ETHERNET: i32 = 1;
+pub const LINKTYPE_RAW: i32 = 101;
+pub const LINKTYPE_LOOP: i32 = 108;
+pub const LINKTYPE_LINUX_SLL: i32 = 113;
+pub const LINKTYPE_IPV4: i32 = 228;
+pub const LINKTYPE_IPV6: i32 = 229;
+pub const LINKTYPE_LINUX_SLL2: i32 = 276;
+
+// Some platforms still report the DLT_ value for raw IP instead of LINKTYPE_RAW
+const DLT_RAW_BSD: i32 = 12;
+const DLT_RAW_OPENBSD: i32 = 14;
+
+const ETHERTYPE_QINQ: u16 = 0x88a8;
+const ETHERTYPE_QINQ_LEGACY: u16 = 0x9100;
+
+/// VLAN tags decoded per frame; real networks stack two or three at most
+const MAX_VLAN_TAGS: usize = 8;
+
+/// Decode a captured frame into its protocol layers, outermost first.
+///
+/// `linktype` is the pcap datalink type of the source the frame came from.
+/// Decoding stops quietly at the first layer that is truncated or unknown,
+/// so the result always describes as much of the frame as could be parsed.
+/// Only per-packet decoding happens here; use [`Dissector`] to also decode
+/// protocols that need TCP stream reassembly.
+pub fn dissect(linktype: i32, data: &[u8]) -> Vec<Layer> {
+    dissect_frame(linktype, data).layers
+}
+
+/// Layers decoded from one frame, plus the TCP payload for reassembly
+#[derive(Debug, Default)]
+struct Dissection {
+    layers: Vec<Layer>,
+    tcp_payload: Option<Vec<u8>>,
+}
+
+fn dissect_frame(linktype: i32, data: &[u8]) -> Dissection {
+    let mut out = Dissection::default();
+    match linktype {
+        LINKTYPE_ETHERNET => ethernet(data, &mut out),
+        LINKTYPE_LINUX_SLL => linux_sll(data, &mut out),
+        LINKTYPE_LINUX_SLL2 => linux_sll2(data, &mut out),
+        LINKTYPE_RAW | DLT_RAW_BSD | DLT_RAW_OPENBSD => raw_ip(data, &mut out),
+        LINKTYPE_IPV4 => ipv4(data, &mut out),
+        LINKTYPE_IPV6 => ipv6(data, &mut out),
+        LINKTYPE_NULL | LINKTYPE_LOOP => loopback(linktype, data, &mut out),
+        _ => debug!("Unsupported datalink type: {}", linktype),
+    }
+    out
+}
+
+/// Application protocol spoken on one direction of a TCP stream
+#[derive(Debug, Default)]
+enum StreamProtocol {
+    /// Not enough bytes seen yet to tell
+    #[default]
+    Unknown,
+    Http(http::HttpStream),
+    Tls(tls::TlsStream),
+    /// Nothing we decode; bytes are discarded as they arrive
+    Opaque,
+}
+
+/// Stateful dissector for one capture: per-packet decoding plus TCP stream
+/// reassembly for protocols whose messages span segments
+pub struct Dissector {
+    linktype: i32,
+    streams: TcpReassembler<StreamProtocol>,
+}
+
+impl Dissector {
+    pub fn new(linktype: i32) -> Self {
+        Dissector {
+            linktype,
+            streams: TcpReassembler::new(),
+        }
+    }
+
+    pub fn dissect(&mut self, data: &[u8], timestamp: Timestamp) -> Vec<Layer> {
+        let mut out = dissect_frame(self.linktype, data);
+
+        if let Some(payload) = &out.tcp_payload
+            && let Some((key, tcp)) = stream_key(&out.layers)
+            && self
+                .streams
+                .push(key, tcp.seq, tcp.flags, payload, timestamp)
+                .is_some()
+            && let Some((stream, reverse)) = self.streams.with_reverse(&key)
+        {
+            let peer = reverse.map(|reverse| &mut reverse.state);
+            stream_layers(stream, peer, &mut out.layers);
+        }
+
+        out.layers
+    }
+}
+
+/// Identify the TCP stream direction a decoded packet belongs to
+fn stream_key(layers: &[Layer]) -> Option<(StreamKey, TcpLayer)> {
+    let mut addrs = None;
+    for layer in layers {
+        match layer {
+            Layer::Ipv4(ip) => addrs = Some((ip.src.into(), ip.dst.into())),
+            Layer::Ipv6(ip) => addrs = Some((ip.src.into(), ip.dst.into())),
+            Layer::Tcp(tcp) => {
+                let (src, dst) = addrs?;
+                let key = StreamKey {
+                    src,
+                    src_port: tcp.src_port,
+                    dst,
+                    dst_port: tcp.dst_port,
+                };
+                return Some((key, tcp.clone()));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Run the stream dissectors over newly reassembled bytes. `peer` is the
+/// state of the opposite direction of the connection, if it has been seen.
+fn stream_layers(
+    stream: &mut Stream<StreamProtocol>,
+    peer: Option<&mut StreamProtocol>,
+    layers: &mut Vec<Layer>,
+) {
+    if stream.take_gap() {
+        // Lost bytes: the parser position is meaningless, start over
+        stream.state = StreamProtocol::Unknown;
+    }
+
+    let (data, state) = stream.data_and_state();
+    if let StreamProtocol::Unknown = state {
+        *state = match (http::detect(data), tls::detect(data)) {
+            (Some(true), _) => StreamProtocol::Http(http::HttpStream::default()),
+            (_, Some(true)) => StreamProtocol::Tls(tls::TlsStream::default()),
+            (Some(false), Some(false)) => StreamProtocol::Opaque,
+            _ => return,
+        };
+    }
+
+    let consumed = match state {
+        StreamProtocol::Http(http) => {
+            let mut messages = Vec::new();
+            let requests = match peer {
+                Some(StreamProtocol::Http(requests)) => Some(requests),
+                _ => None,
+            };
+            let consumed = http::parse(data, http, requests, &mut messages);
+            layers.extend(messages.into_iter().map(Layer::Http));
+            consumed
+        }
+        StreamProtocol::Tls(tls) => {
+            let mut messages = Vec::new();
+            let consumed = tls::parse(data, tls, &mut messages);
+            layers.extend(messages.into_iter().map(Layer::Tls));
+            consumed
+        }
+        StreamProtocol::Opaque => data.len(),
+        StreamProtocol::Unknown => 0,
+    };
+    stream.consume(consumed);
+}
+
+fn ethernet(data: &[u8], out: &mut Dissection) {
+    let Some(frame) = EthernetPacket::new(data) else {
+        return;
+    };
+
+    debug!(
+        "Ethernet packet: {} -> {}",
+        frame.get_source(),
+        frame.get_destination()
+    );
+
+    out.layers.push(Layer::Ethernet(EthernetLayer {
+        src: frame.get_source().to_string(),
+        dst: frame.get_destination().to_string(),
+        ethertype: frame.get_ethertype().0,
+    }));
+
+    ethertype(frame.get_ethertype(), frame.payload(), out);
+}
+
+fn ethertype(mut ethertype: EtherType, mut payload: &[u8], out: &mut Dissection) {
+    // Stacked tags are walked in a loop and only so far, as a crafted frame
+    // can hold tens of thousands of them
+    let mut tags = 0;
+    while let EtherTypes::Vlan | EtherType(ETHERTYPE_QINQ | ETHERTYPE_QINQ_LEGACY) = ethertype {
+        if tags == MAX_VLAN_TAGS {
+            debug!("Not decoding past {} VLAN tags", MAX_VLAN_TAGS);
+            return;
+        }
+        let Some(inner) = vlan(ethertype.0, payload, out) else {
+            return;
+        };
+        ethertype = inner;
+        payload = &payload[4..];
+        tags += 1;
+    }
+
+    match ethertype {
+        EtherTypes::Ipv4 => ipv4(payload, out),
+        EtherTypes::Ipv6 => ipv6(payload, out),
+        EtherTypes::Arp => arp(payload, out),
+        _ => {}
+    }
+}
+
+/// Linux cooked capture v1: 16-byte header ending in the EtherType
+fn linux_sll(data: &[u8], out: &mut Dissection) {
+    if data.len() < 16 {
+        return;
+    }
+
+    let protocol = u16::from_be_bytes([data[14], data[15]]);
+    out.layers.push(Layer::LinuxSll(LinuxSllLayer {
+        version: 1,
+        packet_type: u16::from_be_bytes([data[0], data[1]]),
+        arphrd_type: u16::from_be_bytes([data[2], data[3]]),
+        address: sll_address(
+            &data[6..14],
+            u16::from_be_bytes([data[4], data[5]]) as usize,
+        ),
+        protocol,
+        interface_index: None,
+    }));
+
+    ethertype(EtherType::new(protocol), &data[16..], out);
+}
+
+/// Linux cooked capture v2: 20-byte header starting with the EtherType
+fn linux_sll2(data: &[u8], out: &mut Dissection) {
+    if data.len() < 20 {
+        return;
+    }
+
+    let protocol = u16::from_be_bytes([data[0], data[1]]);
+    out.layers.push(Layer::LinuxSll(LinuxSllLayer {
+        version: 2,
+        packet_type: data[10] as u16,
+        arphrd_type: u16::from_be_bytes([data[8], data[9]]),
+        address: sll_address(&data[12..20], data[11] as usize),
+        protocol,
+        interface_index: Some(be_u32(&data[4..8])),
+    }));
+
+    ethertype(EtherType::new(protocol), &data[20..], out);
+}
+
+fn sll_address(field: &[u8], len: usize) -> Option<String> {
+    if len == 0 {
+        return None;
+    }
+    Some(
+        field[..len.min(field.len())]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(":"),
+    )
+}
+
+/// Raw IP with no link-layer header; the version nibble picks the protocol
+fn raw_ip(data: &[u8], out: &mut Dissection) {
+    match data.first().map(|b| b >> 4) {
+        Some(4) => ipv4(data, out),
+        Some(6) => ipv6(data, out),
+        _ => {}
+    }
+}
+
+/// BSD loopback: a 4-byte address family, in host byte order of the
+/// capturing machine for DLT_NULL and network byte order for DLT_LOOP
+fn loopback(linktype: i32, data: &[u8], out: &mut Dissection) {
+    if data.len() < 4 {
+        return;
+    }
+
+    let bytes = [data[0], data[1], data[2], data[3]];
+    let family = if linktype == LINKTYPE_LOOP {
+        u32::from_be_bytes(bytes)
+    } else {
+        // Families are small, so whichever order gives a small value is right
+        let le = u32::from_le_bytes(bytes);
+        if le > 0xffff {
+            u32::from_be_bytes(bytes)
+        } else {
+            le
+        }
+    };
+
+    let layer = LoopbackLayer { family };
+    let family_name = layer.family_name();
+    out.layers.push(Layer::Loopback(layer));
+
+    match family_name {
+        "IPv4" => ipv4(&data[4..], out),
+        "IPv6" => ipv6(&data[4..], out),
+        _ => {}
+    }
+}
+
+/// Decode one 802.1Q/802.1ad tag, returning the EtherType it wraps
+fn vlan(tpid: u16, data: &[u8], out: &mut Dissection) -> Option<EtherType> {
+    if data.len() < 4 {
+        return None;
+    }
+
+    let tci = u16::from_be_bytes([data[0], data[1]]);
+    let inner = u16::from_be_bytes([data[2], data[3]]);
+
+    out.layers.push(Layer::Vlan(VlanLayer {
+        tpid,
+        id: tci & 0x0fff,
+        priority: (tci >> 13) as u8,
+        drop_eligible: tci & 0x1000 != 0,
+        ethertype: inner,
+    }));
+
+    Some(EtherType::new(inner))
+}
+
+fn arp(data: &[u8], out: &mut Dissection) {
+    let Some(arp) = ArpPacket::new(data) else {
+        return;
+    };
+
+    out.layers.push(Layer::Arp(ArpLayer {
+        hardware_type: arp.get_hardware_type().0,
+        protocol_type: arp.get_protocol_type().0,
+        operation: arp.get_operation().0,
+        sender_mac: arp.get_sender_hw_addr().to_string(),
+        sender_ip: arp.get_sender_proto_addr(),
+        target_mac: arp.get_target_hw_addr().to_string(),
+        target_ip: arp.get_target_proto_addr(),
+    }));
+}
+
+fn ipv4(data: &[u8], out: &mut Dissection) {
+    let Some(ip) = Ipv4Packet::new(data) else {
+        return;
+    };
+
+    let flags = ip.get_flags();
+    out.layers.push(Layer::Ipv4(Ipv4Layer {
+        src: ip.get_source(),
+        dst: ip.get_destination(),
+        header_length: ip.get_header_length() * 4,
+        dscp: ip.get_dscp(),
+        ecn: ip.get_ecn(),
+        total_length: ip.get_total_length(),
+        identification: ip.get_identification(),
+        dont_fragment: flags & 0b010 != 0,
+        more_fragments: flags & 0b001 != 0,
+        fragment_offset: ip.get_fragment_offset(),
+        ttl: ip.get_ttl(),
+        protocol: ip.get_next_level_protocol().0,
+        checksum: ip.get_checksum(),
+    }));
+
+    // Only the first fragment carries the transport header
+    if ip.get_fragment_offset() == 0 {
+        transport(ip.get_next_level_protocol(), ip.payload(), out);
+    }
+}
+
+fn ipv6(data: &[u8], out: &mut Dissection) {
+    let Some(ip) = Ipv6Packet::new(data) else {
+        return;
+    };
+
+    let (extensions, upper_protocol, offset, fragmented) =
+        ipv6_extensions(ip.get_next_header(), ip.payload());
+
+    out.layers.push(Layer::Ipv6(Ipv6Layer {
+        src: ip.get_source(),
+        dst: ip.get_destination(),
+        traffic_class: ip.get_traffic_class(),
+        flow_label: ip.get_flow_label(),
+        payload_length: ip.get_payload_length(),
+        next_header: ip.get_next_header().0,
+        hop_limit: ip.get_hop_limit(),
+        extensions,
+        upper_protocol: upper_protocol.0,
+    }));
+
+    // Non-initial fragments have no upper-layer header to decode
+    if !fragmented {
+        transport(upper_protocol, &ip.payload()[offset..], out);
+    }
+}
+
+/// Walk the IPv6 extension header chain.
+///
+/// Returns the headers found, the upper-layer protocol, the offset of the
+/// upper-layer data in `payload` and whether this is a non-initial fragment.
+fn ipv6_extensions(
+    mut next_header: IpNextHeaderProtocol,
+    payload: &[u8],
+) -> (Vec<Ipv6Extension>, IpNextHeaderProtocol, usize, bool) {
+    let mut extensions = Vec::new();
+    let mut offset = 0;
+
+    loop {
+        let header = &payload[offset..];
+        if header.len() < 8 {
+            break;
+        }
+        let following = IpNextHeaderProtocol::new(header[0]);
+        let length = (header[1] as usize + 1) * 8;
+
+        let extension = match next_header {
+            IpNextHeaderProtocols::Hopopt => Ipv6Extension::HopByHop {
+                length: length as u16,
+            },
+            IpNextHeaderProtocols::Ipv6Route => Ipv6Extension::Routing {
+                length: length as u16,
+                routing_type: header[2],
+                segments_left: header[3],
+            },
+            IpNextHeaderProtocols::Ipv6Opts => Ipv6Extension::DestinationOptions {
+                length: length as u16,
+            },
+            IpNextHeaderProtocols::Ipv6Frag => {
+                // Fragment headers have a fixed size and no length field
+                let offset_flags = u16::from_be_bytes([header[2], header[3]]);
+                let fragment_offset = offset_flags >> 3;
+                extensions.push(Ipv6Extension::Fragment {
+                    offset: fragment_offset,
+                    more_fragments: offset_flags & 1 != 0,
+                    identification: be_u32(&header[4..8]),
+                });
+                next_header = following;
+                offset += 8;
+                if fragment_offset != 0 {
+                    return (extensions, next_header, offset, true);
+                }
+                continue;
+            }
+            _ => break,
+        };
+
+        if length > header.len() {
+            break;
+        }
+        extensions.push(extension);
+        next_header = following;
+        offset += length;
+    }
+
+    (extensions, next_header, offset, false)
+}
+
+fn transport(protocol: IpNextHeaderProtocol, payload: &[u8], out: &mut Dissection) {
+    match protocol {
+        IpNextHeaderProtocols::Tcp => tcp(payload, out),
+        IpNextHeaderProtocols::Udp => udp(payload, out),
+        IpNextHeaderProtocols::Icmp => icmp(payload, out),
+        IpNextHeaderProtocols::Icmpv6 => icmpv6(payload, out),
+        _ => {}
+    }
+}
+
+fn tcp(data: &[u8], out: &mut Dissection) {
+    let Some(tcp) = TcpPacket::new(data) else {
+        return;
+    };
+
+    let header_length = (tcp.get_data_offset() as usize * 4).clamp(20, data.len());
+    out.layers.push(Layer::Tcp(TcpLayer {
+        src_port: tcp.get_source(),
+        dst_port: tcp.get_destination(),
+        seq: tcp.get_sequence(),
+        ack: tcp.get_acknowledgement(),
+        header_length: header_length as u8,
+        flags: TcpFlags::from_bits(tcp.get_flags()),
+        window: tcp.get_window(),
+        checksum: tcp.get_checksum(),
+        urgent_pointer: tcp.get_urgent_ptr(),
+        options: tcp_options(&data[20..header_length]),
+        payload_len: data.len() - header_length,
+    }));
+
+    let payload = &data[header_length..];
+    out.tcp_payload = Some(payload.to_vec());
+    if is_dns(tcp.get_source(), tcp.get_destination())
+        && let Some(dns) = dns::parse_tcp(payload)
+    {
+        out.layers.push(Layer::Dns(dns));
+    }
+}
+
+/// Walk the TCP option list, stopping at the end-of-list marker
+fn tcp_options(mut data: &[u8]) -> Vec<TcpOption> {
+    let mut options = Vec::new();
+
+    while let Some(&kind) = data.first() {
+        match kind {
+            0 => break,
+            1 => {
+                options.push(TcpOption::Nop);
+                data = &data[1..];
+                continue;
+            }
+            _ => {}
+        }
+
+        let Some(&length) = data.get(1) else {
+            break;
+        };
+        if length < 2 || length as usize > data.len() {
+            break;
+        }
+        let value = &data[2..length as usize];
+
+        let option = match (kind, value.len()) {
+            (2, 2) => TcpOption::Mss {
+                value: u16::from_be_bytes([value[0], value[1]]),
+            },
+            (3, 1) => TcpOption::WindowScale { shift: value[0] },
+            (4, 0) => TcpOption::SackPermitted,
+            (5, n) if n % 8 == 0 => TcpOption::Sack {
+                blocks: value
+                    .chunks_exact(8)
+                    .map(|block| (be_u32(&block[..4]), be_u32(&block[4..])))
+                    .collect(),
+            },
+            (8, 8) => TcpOption::Timestamps {
+                value: be_u32(&value[..4]),
+                echo_reply: be_u32(&value[4..]),
+            },
+            _ => TcpOption::Unknown { code: kind, length },
+        };
+        options.push(option);
+        data = &data[length as usize..];
+    }
+
+    options
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn udp(data: &[u8], out: &mut Dissection) {
+    let Some(udp) = UdpPacket::new(data) else {
+        return;
+    };
+
+    out.layers.push(Layer::Udp(UdpLayer {
+        src_port: udp.get_source(),
+        dst_port: udp.get_destination(),
+        length: udp.get_length(),
+        checksum: udp.get_checksum(),
+        payload_len: udp.payload().len(),
+    }));
+
+    if is_dns(udp.get_source(), udp.get_destination())
+        && let Some(dns) = dns::parse(udp.payload())
+    {
+        out.layers.push(Layer::Dns(dns));
+    }
+}
+
+fn is_dns(src_port: u16, dst_port: u16) -> bool {
+    dns::PORTS.contains(&src_port) || dns::PORTS.contains(&dst_port)
+}
+
+fn icmp(data: &[u8], out: &mut Dissection) {
+    let Some(icmp) = IcmpPacket::new(data) else {
+        return;
+    };
+
+    let icmp_type = icmp.get_icmp_type().0;
+    let (identifier, sequence) = match (icmp_type, icmp.payload()) {
+        (0 | 8, rest) if rest.len() >= 4 => (
+            Some(u16::from_be_bytes([rest[0], rest[1]])),
+            Some(u16::from_be_bytes([rest[2], rest[3]])),
+        ),
+        _ => (None, None),
+    };
+
+    out.layers.push(Layer::Icmp(IcmpLayer {
+        icmp_type,
+        code: icmp.get_icmp_code().0,
+        checksum: icmp.get_checksum(),
+        identifier,
+        sequence,
+    }));
+}
+
+fn icmpv6(data: &[u8], out: &mut Dissection) {
+    if data.len() < 4 {
+        return;
+    }
+
+    let icmp_type = data[0];
+    let body = &data[4..];
+    let mut layer = Icmpv6Layer {
+        icmp_type,
+        code: data[1],
+        checksum: u16::from_be_bytes([data[2], data[3]]),
+        identifier: None,
+        sequence: None,
+        target: None,
+        link_layer_address: None,
+        router_lifetime: None,
+        prefixes: Vec::new(),
+        flags: Vec::new(),
+    };
+
+    match icmp_type {
+        128 | 129 if body.len() >= 4 => {
+            layer.identifier = Some(u16::from_be_bytes([body[0], body[1]]));
+            layer.sequence = Some(u16::from_be_bytes([body[2], body[3]]));
+        }
+        // Router advertisement: hop limit, M/O flags, lifetime, two timers
+        134 if body.len() >= 12 => {
+            push_flag(&mut layer.flags, body[1] & 0x80 != 0, "managed");
+            push_flag(&mut layer.flags, body[1] & 0x40 != 0, "other");
+            layer.router_lifetime = Some(u16::from_be_bytes([body[2], body[3]]));
+            ndp_options(&body[12..], &mut layer);
+        }
+        // Neighbor solicitation/advertisement: flags word then the target
+        135 | 136 if body.len() >= 20 => {
+            if icmp_type == 136 {
+                push_flag(&mut layer.flags, body[0] & 0x80 != 0, "router");
+                push_flag(&mut layer.flags, body[0] & 0x40 != 0, "solicited");
+                push_flag(&mut layer.flags, body[0] & 0x20 != 0, "override");
+            }
+            layer.target = Some(ipv6_addr(&body[4..20]));
+            ndp_options(&body[20..], &mut layer);
+        }
+        // Redirect: reserved word, target and destination addresses
+        137 if body.len() >= 36 => {
+            layer.target = Some(ipv6_addr(&body[4..20]));
+            ndp_options(&body[36..], &mut layer);
+        }
+        133 if body.len() >= 4 => ndp_options(&body[4..], &mut layer),
+        _ => {}
+    }
+
+    out.layers.push(Layer::Icmpv6(layer));
+}
+
+/// Decode the neighbor discovery options we surface in the summary
+fn ndp_options(mut data: &[u8], layer: &mut Icmpv6Layer) {
+    while data.len() >= 8 {
+        let length = data[1] as usize * 8;
+        if length == 0 || length > data.len() {
+            break;
+        }
+        let option = &data[..length];
+
+        match option[0] {
+            // Source / target link-layer address
+            1 | 2 if length >= 8 => {
+                layer.link_layer_address = Some(
+                    option[2..8]
+                        .iter()
+                        .map(|b| format!("{:02x}", b))
+                        .collect::<Vec<_>>()
+                        .join(":"),
+                );
+            }
+            // Prefix information
+            3 if length >= 32 => {
+                layer
+                    .prefixes
+                    .push(format!("{}/{}", ipv6_addr(&option[16..32]), option[2]));
+            }
+            _ => {}
+        }
+
+        data = &data[length..];
+    }
+}
+
+fn push_flag(flags: &mut Vec<String>, set: bool, name: &str) {
+    if set {
+        flags.push(name.to_string());
+    }
+}
+
+fn ipv6_addr(bytes: &[u8]) -> Ipv6Addr {
+    let mut octets = [0u8; 16];
+    octets.copy_from_slice(&bytes[..16]);
+    Ipv6Addr::from(octets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::Frame;
+
+    #[test]
+    fn test_tcp_options() {
+        // MSS 1460, NOP, window scale 7, SACK permitted, timestamps
+        let raw = [
+            2, 4, 0x05, 0xb4, 1, 3, 3, 7, 4, 2, 8, 10, 0, 0, 0, 1, 0, 0, 0, 2,
+        ];
+        assert_eq!(
+            tcp_options(&raw),
+            vec![
+                TcpOption::Mss { value: 1460 },
+                TcpOption::Nop,
+                TcpOption::WindowScale { shift: 7 },
+                TcpOption::SackPermitted,
+                TcpOption::Timestamps {
+                    value: 1,
+                    echo_reply: 2
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_truncated_options_stop_cleanly() {
+        assert_eq!(tcp_options(&[2, 4, 0x05]), vec![]);
+        assert_eq!(tcp_options(&[1, 0, 2, 4, 0x05, 0xb4]), vec![TcpOption::Nop]);
+    }
+
+    #[test]
+    fn test_vlan_tagged_frame() {
+        let mut frame = vec![0xff; 6];
+        frame.extend_from_slice(&[0x02, 0, 0, 0, 0, 1]);
+        frame.extend_from_slice(&[0x81, 0x00, 0x20, 0x64, 0x08, 0x06]); // PCP 1, VLAN 100, ARP
+        frame.extend_from_slice(&[0, 1, 8, 0, 6, 4, 0, 1]);
+        frame.extend_from_slice(&[0x02, 0, 0, 0, 0, 1, 10, 0, 0, 1]);
+        frame.extend_from_slice(&[0, 0, 0, 0, 0, 0, 10, 0, 0, 2]);
+
+        let layers = dissect(LINKTYPE_ETHERNET, &frame);
+        assert_eq!(layers.len(), 3);
+        let Layer::Vlan(vlan) = &layers[1] else {
+            panic!("expected VLAN layer, got {:?}", layers[1]);
+        };
+        assert_eq!(vlan.id, 100);
+        assert_eq!(vlan.priority, 1);
+        assert!(matches!(layers[2], Layer::Arp(_)));
+    }
+
+    #[test]
+    fn test_qinq_tags_unwrapped() {
+        let mut frame = vec![0xff; 6];
+        frame.extend_from_slice(&[0x02, 0, 0, 0, 0, 1]);
+        frame.extend_from_slice(&[0x88, 0xa8, 0x00, 0x0a]); // S-tag, VLAN 10
+        frame.extend_from_slice(&[0x81, 0x00, 0x00, 0xc8, 0x86, 0xdd]); // C-tag, VLAN 200, IPv6
+        frame.extend_from_slice(&ipv6_header(59, 0));
+
+        let layers = dissect(LINKTYPE_ETHERNET, &frame);
+        let names: Vec<&str> = layers.iter().map(|l| l.name()).collect();
+        assert_eq!(names, vec!["Ethernet", "802.1ad", "802.1Q", "IPv6"]);
+        let summary = hakinet_common::LayerSummary::from_layers(&layers);
+        assert_eq!(summary.vlan_ids, vec![10, 200]);
+    }
+
+    #[test]
+    fn test_deeply_stacked_tags() {
+        let mut frame = vec![0xff; 6];
+        frame.extend_from_slice(&[0x02, 0, 0, 0, 0, 1]);
+        for _ in 0..500 {
+            frame.extend_from_slice(&[0x81, 0x00, 0x00, 0x0a]);
+        }
+        frame.extend_from_slice(&[0x86, 0xdd]);
+        frame.extend_from_slice(&ipv6_header(59, 0));
+
+        let layers = dissect(LINKTYPE_ETHERNET, &frame);
+        assert_eq!(layers.len(), 1 + MAX_VLAN_TAGS);
+        assert!(matches!(layers.last(), Some(Layer::Vlan(_))));
+    }
+
+    #[test]
+    fn test_non_ethernet_link_types() {
+        let ip = ipv6_header(59, 0);
+
+        let mut sll = vec![0, 4, 0, 1, 0, 6, 0x02, 0, 0, 0, 0, 1, 0, 0, 0x86, 0xdd];
+        sll.extend_from_slice(&ip);
+        let layers = dissect(LINKTYPE_LINUX_SLL, &sll);
+        let Layer::LinuxSll(header) = &layers[0] else {
+            panic!("expected SLL layer, got {:?}", layers[0]);
+        };
+        assert_eq!(header.summary(), "Sent by us from 02:00:00:00:00:01");
+        assert!(matches!(layers[1], Layer::Ipv6(_)));
+
+        let mut sll2 = vec![0x86, 0xdd, 0, 0, 0, 0, 0, 3, 0, 1, 0, 6];
+        sll2.extend_from_slice(&[0x02, 0, 0, 0, 0, 1, 0, 0]);
+        sll2.extend_from_slice(&ip);
+        let layers = dissect(LINKTYPE_LINUX_SLL2, &sll2);
+        let Layer::LinuxSll(header) = &layers[0] else {
+            panic!("expected SLL2 layer, got {:?}", layers[0]);
+        };
+        assert_eq!(header.interface_index, Some(3));
+        assert_eq!(header.packet_type, 0);
+        assert!(matches!(layers[1], Layer::Ipv6(_)));
+
+        let layers = dissect(LINKTYPE_RAW, &ip);
+        assert!(matches!(layers.as_slice(), [Layer::Ipv6(_)]));
+
+        // Darwin AF_INET6 in little-endian host order
+        let mut null = vec![30, 0, 0, 0];
+        null.extend_from_slice(&ip);
+        let layers = dissect(LINKTYPE_NULL, &null);
+        assert_eq!(layers[0], Layer::Loopback(LoopbackLayer { family: 30 }));
+        assert!(matches!(layers[1], Layer::Ipv6(_)));
+    }
+
+    fn ipv6_header(next_header: u8, payload_len: usize) -> Vec<u8> {
+        let mut header = vec![0x60, 0, 0, 0];
+        header.extend_from_slice(&(payload_len as u16).to_be_bytes());
+        header.extend_from_slice(&[next_header, 64]);
+        header.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        header.extend_from_slice(&"2001:db8::2".parse::<Ipv6Addr>().unwrap().octets());
+        header
+    }
+
+    #[test]
+    fn test_ipv6_extension_chain_to_udp() {
+        let mut payload = vec![60, 0, 1, 4, 0, 0, 0, 0]; // hop-by-hop, PadN
+        payload.extend_from_slice(&[17, 0, 1, 4, 0, 0, 0, 0]); // destination options
+        payload.extend_from_slice(&[0xc3, 0x50, 0x00, 0x35, 0x00, 0x08, 0, 0]); // UDP 50000 -> 53
+        let mut packet = ipv6_header(0, payload.len());
+        packet.extend_from_slice(&payload);
+
+        let mut out = Dissection::default();
+        ipv6(&packet, &mut out);
+        let layers = out.layers;
+
+        let Layer::Ipv6(ip) = &layers[0] else {
+            panic!("expected IPv6 layer");
+        };
+        assert_eq!(ip.upper_protocol, 17);
+        assert_eq!(
+            ip.extensions,
+            vec![
+                Ipv6Extension::HopByHop { length: 8 },
+                Ipv6Extension::DestinationOptions { length: 8 },
+            ]
+        );
+        let Layer::Udp(udp) = &layers[1] else {
+            panic!("expected UDP layer");
+        };
+        assert_eq!((udp.src_port, udp.dst_port), (50000, 53));
+    }
+
+    #[test]
+    fn test_ipv6_non_initial_fragment() {
+        let mut payload = vec![6, 0, 0x00, 0xb9, 0, 0, 0, 42]; // offset 23, MF
+        payload.extend_from_slice(&[0u8; 16]);
+        let mut packet = ipv6_header(44, payload.len());
+        packet.extend_from_slice(&payload);
+
+        let mut out = Dissection::default();
+        ipv6(&packet, &mut out);
+        let layers = out.layers;
+
+        assert_eq!(layers.len(), 1);
+        let Layer::Ipv6(ip) = &layers[0] else {
+            panic!("expected IPv6 layer");
+        };
+        assert_eq!(
+            ip.extensions,
+            vec![Ipv6Extension::Fragment {
+                offset: 23,
+                more_fragments: true,
+                identification: 42,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_icmpv6_neighbor_solicitation() {
+        let mut message = vec![135, 0, 0, 0, 0, 0, 0, 0];
+        message.extend_from_slice(&"fe80::1".parse::<Ipv6Addr>().unwrap().octets());
+        message.extend_from_slice(&[1, 1, 0x02, 0, 0, 0, 0, 0x0a]);
+
+        let mut out = Dissection::default();
+        icmpv6(&message, &mut out);
+        let layers = out.layers;
+
+        let Layer::Icmpv6(icmp) = &layers[0] else {
+            panic!("expected ICMPv6 layer");
+        };
+        assert_eq!(icmp.type_name(), Some("Neighbor solicitation"));
+        assert_eq!(icmp.target, Some("fe80::1".parse().unwrap()));
+        assert_eq!(
+            icmp.link_layer_address.as_deref(),
+            Some("02:00:00:00:00:0a")
+        );
+        assert_eq!(
+            layers[0].info().as_deref(),
+            Some("Neighbor solicitation for fe80::1 from 02:00:00:00:00:0a")
+        );
+    }
+
+    fn tcp_frame(seq: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+        Frame::new("10.0.0.1:40000", "10.0.0.2:80").tcp(seq, flags, payload)
+    }
+
+    #[test]
+    fn test_http_request_reassembled_across_segments() {
+        let mut dissector = Dissector::new(LINKTYPE_ETHERNET);
+        let ts = Timestamp::new(1, 0);
+        let first = b"GET /index.html HTTP/1.1\r\nHo";
+        let second = b"st: example.com\r\n\r\n";
+
+        dissector.dissect(&tcp_frame(999, 0x02, &[]), ts);
+        // The second half arrives first and waits for the gap to fill
+        let layers = dissector.dissect(&tcp_frame(1000 + first.len() as u32, 0x18, second), ts);
+        assert_eq!(layers.last().map(Layer::name), Some("TCP"));
+
+        let layers = dissector.dissect(&tcp_frame(1000, 0x18, first), ts);
+        let Some(Layer::Http(http)) = layers.last() else {
+            panic!("expected HTTP layer, got {:?}", layers.last());
+        };
+        assert_eq!(http.summary(), "GET /index.html HTTP/1.1 (example.com)");
+
+        // A retransmission carries nothing new
+        let layers = dissector.dissect(&tcp_frame(1000, 0x18, first), ts);
+        assert_eq!(layers.last().map(Layer::name), Some("TCP"));
+    }
+}
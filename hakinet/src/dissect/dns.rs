@@ -0,0 +1,323 @@
+//! DNS message decoding (RFC 1035, EDNS from RFC 6891)
+
+use hakinet_common::{DnsFlags, DnsLayer, DnsQuestion, DnsRecord, DnsRecordData, Edns};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// Well-known ports carrying DNS, including multicast DNS
+pub const PORTS: [u16; 2] = [53, 5353];
+
+const HEADER_LEN: usize = 12;
+const TYPE_OPT: u16 = 41;
+
+/// Guards against compression pointer loops
+const MAX_POINTERS: usize = 32;
+
+/// Decode a DNS message sent over UDP
+pub fn parse(data: &[u8]) -> Option<DnsLayer> {
+    if data.len() < HEADER_LEN {
+        return None;
+    }
+
+    let id = u16::from_be_bytes([data[0], data[1]]);
+    let bits = u16::from_be_bytes([data[2], data[3]]);
+    let counts = [4, 6, 8, 10].map(|i| u16::from_be_bytes([data[i], data[i + 1]]));
+
+    let mut reader = Reader {
+        message: data,
+        pos: HEADER_LEN,
+    };
+
+    let mut questions = Vec::new();
+    for _ in 0..counts[0] {
+        let name = reader.name()?;
+        questions.push(DnsQuestion {
+            name,
+            qtype: reader.u16()?,
+            qclass: reader.u16()?,
+        });
+    }
+
+    let mut layer = DnsLayer {
+        id,
+        response: bits & 0x8000 != 0,
+        opcode: ((bits >> 11) & 0x0f) as u8,
+        flags: DnsFlags::from_bits(bits),
+        rcode: bits & 0x000f,
+        questions,
+        answers: Vec::new(),
+        authority_count: counts[2],
+        additional_count: counts[3],
+        edns: None,
+    };
+
+    // Answers are kept; authority records are skipped; additional records
+    // are only read for the OPT pseudo-record. Truncated sections keep
+    // whatever decoded before the cut.
+    for _ in 0..counts[1] {
+        match reader.record() {
+            Some(record) => layer.answers.push(record),
+            None => return Some(layer),
+        }
+    }
+    for _ in 0..counts[2] {
+        if reader.record().is_none() {
+            return Some(layer);
+        }
+    }
+    for _ in 0..counts[3] {
+        let Some((rtype, class, ttl)) = reader.opt_header() else {
+            break;
+        };
+        if rtype == TYPE_OPT {
+            // OPT reuses CLASS for the payload size and TTL for the
+            // extended rcode, version and DO bit
+            layer.rcode |= ((ttl >> 24) as u16) << 4;
+            layer.edns = Some(Edns {
+                udp_payload_size: class,
+                version: (ttl >> 16) as u8,
+                dnssec_ok: ttl & 0x8000 != 0,
+            });
+        }
+    }
+
+    Some(layer)
+}
+
+/// Decode a DNS message sent over TCP, which carries a two-byte length prefix
+pub fn parse_tcp(data: &[u8]) -> Option<DnsLayer> {
+    if data.len() < 2 {
+        return None;
+    }
+    let length = u16::from_be_bytes([data[0], data[1]]) as usize;
+    parse(data.get(2..2 + length)?)
+}
+
+struct Reader<'a> {
+    message: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn bytes(&mut self, len: usize) -> Option<&[u8]> {
+        let bytes = self.message.get(self.pos..self.pos + len)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.bytes(2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.bytes(4)?;
+        Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Read a possibly compressed domain name at the current position
+    fn name(&mut self) -> Option<String> {
+        let (name, next) = read_name(self.message, self.pos)?;
+        self.pos = next;
+        Some(name)
+    }
+
+    fn record(&mut self) -> Option<DnsRecord> {
+        let name = self.name()?;
+        let rtype = self.u16()?;
+        let class = self.u16()?;
+        let ttl = self.u32()?;
+        let length = self.u16()?;
+        let start = self.pos;
+        let end = start + length as usize;
+        if end > self.message.len() {
+            return None;
+        }
+
+        let data = self
+            .record_data(rtype, length, end)
+            .unwrap_or(DnsRecordData::Other { length });
+        self.pos = end;
+
+        Some(DnsRecord {
+            name,
+            rtype,
+            class,
+            ttl,
+            data,
+        })
+    }
+
+    fn record_data(&mut self, rtype: u16, length: u16, end: usize) -> Option<DnsRecordData> {
+        let data = match (rtype, length) {
+            (1, 4) => {
+                let b = self.bytes(4)?;
+                DnsRecordData::A {
+                    address: Ipv4Addr::new(b[0], b[1], b[2], b[3]),
+                }
+            }
+            (28, 16) => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(self.bytes(16)?);
+                DnsRecordData::Aaaa {
+                    address: Ipv6Addr::from(octets),
+                }
+            }
+            (2, _) => DnsRecordData::Ns { name: self.name()? },
+            (5, _) => DnsRecordData::Cname { name: self.name()? },
+            (12, _) => DnsRecordData::Ptr { name: self.name()? },
+            (15, _) => DnsRecordData::Mx {
+                preference: self.u16()?,
+                exchange: self.name()?,
+            },
+            (16, _) => {
+                let mut strings = Vec::new();
+                while self.pos < end {
+                    let len = self.u8()? as usize;
+                    strings.push(String::from_utf8_lossy(self.bytes(len)?).into_owned());
+                }
+                DnsRecordData::Txt { strings }
+            }
+            (33, _) => DnsRecordData::Srv {
+                priority: self.u16()?,
+                weight: self.u16()?,
+                port: self.u16()?,
+                target: self.name()?,
+            },
+            _ => return None,
+        };
+
+        (self.pos <= end).then_some(data)
+    }
+
+    /// Read the fixed part of an additional record and skip its data
+    fn opt_header(&mut self) -> Option<(u16, u16, u32)> {
+        self.name()?;
+        let rtype = self.u16()?;
+        let class = self.u16()?;
+        let ttl = self.u32()?;
+        let length = self.u16()? as usize;
+        self.bytes(length)?;
+        Some((rtype, class, ttl))
+    }
+}
+
+/// Read a domain name starting at `pos`, following compression pointers.
+///
+/// Returns the dotted name and the offset just past the name in the
+/// original position (not past any pointer target).
+fn read_name(message: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut next = None;
+    let mut pointers = 0;
+
+    loop {
+        let len = *message.get(pos)? as usize;
+        match len & 0xc0 {
+            0x00 if len == 0 => {
+                pos += 1;
+                break;
+            }
+            0x00 => {
+                let label = message.get(pos + 1..pos + 1 + len)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + len;
+            }
+            0xc0 => {
+                pointers += 1;
+                if pointers > MAX_POINTERS {
+                    return None;
+                }
+                let target = ((len & 0x3f) << 8) | *message.get(pos + 1)? as usize;
+                next.get_or_insert(pos + 2);
+                pos = target;
+            }
+            // 0x40 and 0x80 label types are obsolete or reserved
+            _ => return None,
+        }
+    }
+
+    let name = if labels.is_empty() {
+        ".".to_string()
+    } else {
+        labels.join(".")
+    };
+    Some((name, next.unwrap_or(pos)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query() -> Vec<u8> {
+        let mut message = vec![0xbe, 0xef, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 1];
+        message.extend_from_slice(b"\x07example\x03com\x00");
+        message.extend_from_slice(&[0, 1, 0, 1]);
+        // OPT: root name, type 41, 1232-byte payload, DO bit
+        message.extend_from_slice(&[0, 0, 41, 0x04, 0xd0, 0, 0, 0x80, 0, 0, 0]);
+        message
+    }
+
+    #[test]
+    fn test_query_with_edns() {
+        let dns = parse(&query()).unwrap();
+        assert_eq!(dns.id, 0xbeef);
+        assert!(!dns.response);
+        assert!(dns.flags.recursion_desired);
+        assert_eq!(dns.questions[0].name, "example.com");
+        assert_eq!(
+            dns.edns,
+            Some(Edns {
+                udp_payload_size: 1232,
+                version: 0,
+                dnssec_ok: true,
+            })
+        );
+        assert_eq!(dns.summary(), "query A example.com");
+    }
+
+    #[test]
+    fn test_response_with_compressed_names() {
+        let mut message = vec![0xbe, 0xef, 0x81, 0x80, 0, 1, 0, 3, 0, 0, 0, 0];
+        message.extend_from_slice(b"\x03www\x07example\x03com\x00");
+        message.extend_from_slice(&[0, 1, 0, 1]);
+        // www.example.com CNAME example.com (pointer into the question)
+        message.extend_from_slice(&[0xc0, 12, 0, 5, 0, 1, 0, 0, 0, 60, 0, 2, 0xc0, 16]);
+        // example.com A 93.184.216.34
+        message.extend_from_slice(&[0xc0, 16, 0, 1, 0, 1, 0, 0, 1, 44, 0, 4, 93, 184, 216, 34]);
+        // example.com MX 10 mail.example.com
+        message.extend_from_slice(&[0xc0, 16, 0, 15, 0, 1, 0, 0, 1, 44, 0, 9, 0, 10]);
+        message.extend_from_slice(b"\x04mail\xc0\x10");
+
+        let dns = parse(&message).unwrap();
+        assert!(dns.response);
+        assert_eq!(dns.answers.len(), 3);
+        assert_eq!(
+            dns.answers[0].data,
+            DnsRecordData::Cname {
+                name: "example.com".to_string()
+            }
+        );
+        assert_eq!(dns.answers[1].ttl, 300);
+        assert_eq!(
+            dns.summary(),
+            "response A www.example.com CNAME example.com 93.184.216.34 MX 10 mail.example.com"
+        );
+    }
+
+    #[test]
+    fn test_tcp_length_prefix_and_pointer_loop() {
+        let message = query();
+        let mut framed = (message.len() as u16).to_be_bytes().to_vec();
+        framed.extend_from_slice(&message);
+        assert_eq!(parse_tcp(&framed).unwrap().id, 0xbeef);
+        assert!(parse_tcp(&framed[..10]).is_none());
+
+        let mut looped = vec![0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        looped.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1]);
+        assert!(parse(&looped).is_none());
+    }
+}
@@ -0,0 +1,325 @@
+//! HTTP/1.x message heads, decoded from reassembled TCP streams
+
+use hakinet_common::{HttpHeader, HttpLayer};
+use std::collections::VecDeque;
+
+/// Heads larger than this are assumed not to be HTTP after all
+const MAX_HEAD_BYTES: usize = 64 * 1024;
+/// Pipelined requests remembered while waiting for their responses
+const MAX_PENDING_REQUESTS: usize = 64;
+
+/// Prefixes that start an HTTP/1.x request or response
+const MARKERS: [&[u8]; 10] = [
+    b"GET ",
+    b"POST ",
+    b"PUT ",
+    b"DELETE ",
+    b"HEAD ",
+    b"OPTIONS ",
+    b"PATCH ",
+    b"CONNECT ",
+    b"TRACE ",
+    b"HTTP/1.",
+];
+
+/// Per-direction parser state: where we are between message heads
+#[derive(Debug, Default)]
+pub struct HttpStream {
+    body: Body,
+    /// Methods of the requests sent this way that are still unanswered,
+    /// oldest first; the opposite direction needs them to frame responses
+    pending: VecDeque<String>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+enum Body {
+    /// Expecting the next message head
+    #[default]
+    None,
+    /// Skipping a body with a known length
+    Length(u64),
+    /// Skipping a chunked body
+    Chunked(Chunk),
+    /// Body runs until the connection closes
+    UntilClose,
+}
+
+#[derive(Debug, PartialEq)]
+enum Chunk {
+    Size,
+    Data(u64),
+    Trailer,
+}
+
+/// Whether a stream starts like HTTP/1.x; `None` until enough bytes arrive
+pub fn detect(data: &[u8]) -> Option<bool> {
+    let mut undecided = false;
+    for marker in MARKERS {
+        if data.starts_with(marker) {
+            return Some(true);
+        }
+        undecided |= marker.starts_with(data);
+    }
+    (!undecided).then_some(false)
+}
+
+/// Decode every complete message head in `data`, skipping bodies.
+///
+/// `requests` is the state of the opposite direction, when it is HTTP too,
+/// so a response can be matched with the request it answers.
+/// Returns the number of bytes consumed; the remainder is an incomplete
+/// head or body that needs more of the stream.
+pub fn parse(
+    data: &[u8],
+    state: &mut HttpStream,
+    mut requests: Option<&mut HttpStream>,
+    messages: &mut Vec<HttpLayer>,
+) -> usize {
+    let mut pos = 0;
+
+    loop {
+        let rest = &data[pos..];
+        match &mut state.body {
+            Body::None => {
+                // Tolerate stray CRLFs between messages
+                if rest.starts_with(b"\r\n") {
+                    pos += 2;
+                    continue;
+                }
+                let Some(end) = find(rest, b"\r\n\r\n") else {
+                    if rest.len() > MAX_HEAD_BYTES {
+                        state.body = Body::UntilClose;
+                        continue;
+                    }
+                    break;
+                };
+                if let Some(message) = parse_head(&rest[..end]) {
+                    let request = match (&message.method, message.status) {
+                        (Some(method), _) => {
+                            if state.pending.len() == MAX_PENDING_REQUESTS {
+                                state.pending.pop_front();
+                            }
+                            state.pending.push_back(method.clone());
+                            None
+                        }
+                        // Interim responses precede the final one to the same request
+                        (None, Some(status)) if status >= 200 => requests
+                            .as_deref_mut()
+                            .and_then(|requests| requests.pending.pop_front()),
+                        (None, _) => None,
+                    };
+                    state.body = body_for(&message, request.as_deref());
+                    messages.push(message);
+                }
+                pos += end + 4;
+            }
+            Body::Length(remaining) => {
+                let take = (*remaining).min(rest.len() as u64);
+                pos += take as usize;
+                *remaining -= take;
+                if *remaining > 0 {
+                    break;
+                }
+                state.body = Body::None;
+            }
+            Body::Chunked(Chunk::Size) => {
+                let Some(end) = find(rest, b"\r\n") else {
+                    break;
+                };
+                let line = String::from_utf8_lossy(&rest[..end]);
+                let size = line.split(';').next().unwrap_or("").trim();
+                pos += end + 2;
+                state.body = match u64::from_str_radix(size, 16) {
+                    Ok(0) => Body::Chunked(Chunk::Trailer),
+                    // Chunk data is followed by its own CRLF
+                    Ok(size) => Body::Chunked(Chunk::Data(size.saturating_add(2))),
+                    Err(_) => Body::UntilClose,
+                };
+            }
+            Body::Chunked(Chunk::Data(remaining)) => {
+                let take = (*remaining).min(rest.len() as u64);
+                pos += take as usize;
+                *remaining -= take;
+                if *remaining > 0 {
+                    break;
+                }
+                state.body = Body::Chunked(Chunk::Size);
+            }
+            Body::Chunked(Chunk::Trailer) => {
+                let Some(end) = find(rest, b"\r\n") else {
+                    break;
+                };
+                pos += end + 2;
+                if end == 0 {
+                    state.body = Body::None;
+                }
+            }
+            Body::UntilClose => {
+                pos = data.len();
+                break;
+            }
+        }
+    }
+
+    pos
+}
+
+fn parse_head(head: &[u8]) -> Option<HttpLayer> {
+    let head = String::from_utf8_lossy(head);
+    let mut lines = head.split("\r\n");
+    let start = lines.next()?;
+
+    let mut message = HttpLayer {
+        version: String::new(),
+        method: None,
+        uri: None,
+        status: None,
+        reason: None,
+        host: None,
+        content_type: None,
+        content_length: None,
+        chunked: false,
+        headers: Vec::new(),
+    };
+
+    if start.starts_with("HTTP/") {
+        let mut parts = start.splitn(3, ' ');
+        message.version = parts.next()?.to_string();
+        message.status = Some(parts.next()?.parse().ok()?);
+        message.reason = parts.next().map(str::to_string);
+    } else {
+        let mut parts = start.splitn(3, ' ');
+        message.method = Some(parts.next()?.to_string());
+        message.uri = Some(parts.next()?.to_string());
+        message.version = parts.next().filter(|v| v.starts_with("HTTP/"))?.to_string();
+    }
+
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let (name, value) = (name.trim(), value.trim());
+        match name.to_ascii_lowercase().as_str() {
+            "host" => message.host = Some(value.to_string()),
+            "content-type" => message.content_type = Some(value.to_string()),
+            "content-length" => message.content_length = value.parse().ok(),
+            "transfer-encoding" => {
+                message.chunked = value.to_ascii_lowercase().contains("chunked");
+            }
+            _ => {}
+        }
+        message.headers.push(HttpHeader {
+            name: name.to_string(),
+            value: value.to_string(),
+        });
+    }
+
+    Some(message)
+}
+
+/// Work out how the body following a head is delimited (RFC 9112 section 6.3).
+/// `request` is the method a response answers, when known.
+fn body_for(message: &HttpLayer, request: Option<&str>) -> Body {
+    if let Some(status) = message.status
+        && (status < 200 || status == 204 || status == 304 || request == Some("HEAD"))
+    {
+        return Body::None;
+    }
+    if message.chunked {
+        return Body::Chunked(Chunk::Size);
+    }
+    match message.content_length {
+        Some(0) => Body::None,
+        Some(length) => Body::Length(length),
+        None if message.status.is_some() => Body::UntilClose,
+        None => Body::None,
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect(b"GET / HTTP/1.1\r\n"), Some(true));
+        assert_eq!(detect(b"HTTP/1.1 200 OK"), Some(true));
+        assert_eq!(detect(b"PO"), None);
+        assert_eq!(detect(b"\x16\x03\x01\x02\x00"), Some(false));
+    }
+
+    #[test]
+    fn test_request_split_across_segments() {
+        let mut state = HttpStream::default();
+        let mut messages = Vec::new();
+        let request = b"POST /api HTTP/1.1\r\nHost: example.com\r\nContent-Type: application/json\r\nContent-Length: 7\r\n\r\n{\"a\":1}GET / HTTP/1.1\r\n\r\n";
+
+        assert_eq!(parse(&request[..30], &mut state, None, &mut messages), 0);
+        assert!(messages.is_empty());
+
+        let consumed = parse(&request[..100], &mut state, None, &mut messages);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(
+            messages[0].summary(),
+            "POST /api HTTP/1.1 (example.com, application/json, 7 bytes)"
+        );
+
+        // The rest of the body and a pipelined request
+        parse(&request[consumed..], &mut state, None, &mut messages);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].method.as_deref(), Some("GET"));
+    }
+
+    #[test]
+    fn test_chunked_response_then_next_response() {
+        let mut state = HttpStream::default();
+        let mut messages = Vec::new();
+        let stream = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nContent-Type: text/html\r\n\r\n5\r\nhello\r\n0\r\n\r\nHTTP/1.1 304 Not Modified\r\n\r\n";
+
+        let consumed = parse(stream, &mut state, None, &mut messages);
+        assert_eq!(consumed, stream.len());
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[0].summary(),
+            "HTTP/1.1 200 OK (text/html, chunked)"
+        );
+        assert_eq!(messages[1].status, Some(304));
+        assert_eq!(state.body, Body::None);
+    }
+
+    #[test]
+    fn test_huge_chunk_size() {
+        let mut state = HttpStream::default();
+        let mut messages = Vec::new();
+        let stream =
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nffffffffffffffff\r\nabc";
+
+        assert_eq!(parse(stream, &mut state, None, &mut messages), stream.len());
+        assert_eq!(messages.len(), 1);
+        assert_eq!(state.body, Body::Chunked(Chunk::Data(u64::MAX - 3)));
+    }
+
+    #[test]
+    fn test_bodiless_responses_to_head() {
+        let mut client = HttpStream::default();
+        let mut server = HttpStream::default();
+        let mut messages = Vec::new();
+
+        let requests = b"HEAD /big HTTP/1.1\r\n\r\nGET /small HTTP/1.1\r\n\r\n";
+        parse(requests, &mut client, None, &mut messages);
+        assert_eq!(client.pending, ["HEAD", "GET"]);
+
+        // The HEAD response announces a length but sends no body
+        let responses = b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 5000\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhi";
+        let consumed = parse(responses, &mut server, Some(&mut client), &mut messages);
+        assert_eq!(consumed, responses.len());
+        assert_eq!(messages.len(), 5);
+        assert_eq!(messages[4].content_length, Some(2));
+        assert!(client.pending.is_empty());
+        assert_eq!(server.body, Body::None);
+    }
+}
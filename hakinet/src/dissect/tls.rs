@@ -0,0 +1,481 @@
+//! TLS ClientHello/ServerHello decoding with JA3, JA3S and JA4 fingerprints
+
+use hakinet_common::{TlsClientHello, TlsHandshake, TlsLayer, TlsServerHello};
+use md5::{Digest, Md5};
+use sha2::Sha256;
+
+const RECORD_HEADER_LEN: usize = 5;
+const CONTENT_HANDSHAKE: u8 = 22;
+const HANDSHAKE_CLIENT_HELLO: u8 = 1;
+const HANDSHAKE_SERVER_HELLO: u8 = 2;
+
+const EXT_SERVER_NAME: u16 = 0x0000;
+const EXT_SUPPORTED_GROUPS: u16 = 0x000a;
+const EXT_EC_POINT_FORMATS: u16 = 0x000b;
+const EXT_SIGNATURE_ALGORITHMS: u16 = 0x000d;
+const EXT_ALPN: u16 = 0x0010;
+const EXT_SUPPORTED_VERSIONS: u16 = 0x002b;
+
+/// Handshake messages larger than this are not worth waiting for
+const MAX_HANDSHAKE_BYTES: usize = 64 * 1024;
+
+/// Per-direction parser state
+#[derive(Debug, Default)]
+pub struct TlsStream {
+    /// Handshake bytes collected across records
+    handshake: Vec<u8>,
+    /// Set once the hello has been seen or the handshake went encrypted
+    done: bool,
+}
+
+/// Whether a stream starts with a TLS handshake record; `None` until
+/// enough bytes arrive
+pub fn detect(data: &[u8]) -> Option<bool> {
+    match data {
+        [CONTENT_HANDSHAKE, 0x03, minor, ..] => Some(*minor <= 0x04),
+        [CONTENT_HANDSHAKE] | [CONTENT_HANDSHAKE, 0x03] | [] => None,
+        _ => Some(false),
+    }
+}
+
+/// Decode complete records in `data`, pushing a layer for each hello.
+///
+/// Returns the number of bytes consumed.
+pub fn parse(data: &[u8], state: &mut TlsStream, messages: &mut Vec<TlsLayer>) -> usize {
+    let mut pos = 0;
+
+    while !state.done {
+        let Some(header) = data.get(pos..pos + RECORD_HEADER_LEN) else {
+            break;
+        };
+        let record_version = u16::from_be_bytes([header[1], header[2]]);
+        let length = u16::from_be_bytes([header[3], header[4]]) as usize;
+        let Some(fragment) = data.get(pos + RECORD_HEADER_LEN..pos + RECORD_HEADER_LEN + length)
+        else {
+            break;
+        };
+        pos += RECORD_HEADER_LEN + length;
+
+        if header[0] != CONTENT_HANDSHAKE {
+            // ChangeCipherSpec, alerts or application data: nothing more to learn
+            state.done = true;
+            break;
+        }
+
+        state.handshake.extend_from_slice(fragment);
+        while let Some(message) = next_handshake_message(&mut state.handshake) {
+            let handshake = match message.first() {
+                Some(&HANDSHAKE_CLIENT_HELLO) => {
+                    client_hello(&message[4..]).map(TlsHandshake::ClientHello)
+                }
+                Some(&HANDSHAKE_SERVER_HELLO) => {
+                    server_hello(&message[4..]).map(TlsHandshake::ServerHello)
+                }
+                _ => None,
+            };
+            if let Some(handshake) = handshake {
+                messages.push(TlsLayer {
+                    record_version,
+                    handshake,
+                });
+                state.done = true;
+            }
+        }
+        if state.handshake.len() > MAX_HANDSHAKE_BYTES {
+            state.done = true;
+        }
+    }
+
+    if state.done {
+        state.handshake = Vec::new();
+        return data.len();
+    }
+    pos
+}
+
+/// Split one complete handshake message (header included) off the buffer
+fn next_handshake_message(buffer: &mut Vec<u8>) -> Option<Vec<u8>> {
+    if buffer.len() < 4 {
+        return None;
+    }
+    let length = u32::from_be_bytes([0, buffer[1], buffer[2], buffer[3]]) as usize;
+    if buffer.len() < 4 + length {
+        return None;
+    }
+    let rest = buffer.split_off(4 + length);
+    Some(std::mem::replace(buffer, rest))
+}
+
+fn client_hello(body: &[u8]) -> Option<TlsClientHello> {
+    let mut reader = Reader { data: body, pos: 0 };
+    let version = reader.u16()?;
+    reader.bytes(32)?; // random
+    let session_id = reader.u8()? as usize;
+    reader.bytes(session_id)?;
+    let cipher_suites = u16_list(reader.vec16()?);
+    let compression = reader.u8()? as usize;
+    reader.bytes(compression)?;
+
+    let mut hello = TlsClientHello {
+        version,
+        supported_versions: Vec::new(),
+        cipher_suites,
+        extensions: Vec::new(),
+        server_name: None,
+        alpn: Vec::new(),
+        supported_groups: Vec::new(),
+        ec_point_formats: Vec::new(),
+        signature_algorithms: Vec::new(),
+        ja3: String::new(),
+        ja3_hash: String::new(),
+        ja4: String::new(),
+    };
+
+    for (ext_type, ext) in extensions(&mut reader) {
+        hello.extensions.push(ext_type);
+        let mut ext = Reader { data: ext, pos: 0 };
+        match ext_type {
+            EXT_SERVER_NAME => hello.server_name = server_name(&mut ext),
+            EXT_ALPN => hello.alpn = alpn(&mut ext),
+            EXT_SUPPORTED_GROUPS => hello.supported_groups = u16_list(ext.vec16().unwrap_or(&[])),
+            EXT_EC_POINT_FORMATS => hello.ec_point_formats = ext.vec8().unwrap_or(&[]).to_vec(),
+            EXT_SIGNATURE_ALGORITHMS => {
+                hello.signature_algorithms = u16_list(ext.vec16().unwrap_or(&[]))
+            }
+            EXT_SUPPORTED_VERSIONS => {
+                hello.supported_versions = u16_list(ext.vec8().unwrap_or(&[]))
+            }
+            _ => {}
+        }
+    }
+
+    hello.ja3 = ja3(&hello);
+    hello.ja3_hash = md5_hex(&hello.ja3);
+    hello.ja4 = ja4(&hello);
+    Some(hello)
+}
+
+fn server_hello(body: &[u8]) -> Option<TlsServerHello> {
+    let mut reader = Reader { data: body, pos: 0 };
+    let version = reader.u16()?;
+    reader.bytes(32)?; // random
+    let session_id = reader.u8()? as usize;
+    reader.bytes(session_id)?;
+    let cipher_suite = reader.u16()?;
+    reader.u8()?; // compression method
+
+    let mut hello = TlsServerHello {
+        version,
+        selected_version: None,
+        cipher_suite,
+        extensions: Vec::new(),
+        alpn: None,
+        ja3s: String::new(),
+        ja3s_hash: String::new(),
+    };
+
+    for (ext_type, ext) in extensions(&mut reader) {
+        hello.extensions.push(ext_type);
+        let mut ext = Reader { data: ext, pos: 0 };
+        match ext_type {
+            EXT_ALPN => hello.alpn = alpn(&mut ext).into_iter().next(),
+            EXT_SUPPORTED_VERSIONS => hello.selected_version = ext.u16(),
+            _ => {}
+        }
+    }
+
+    hello.ja3s = format!(
+        "{},{},{}",
+        hello.version,
+        hello.cipher_suite,
+        join_decimal(&hello.extensions)
+    );
+    hello.ja3s_hash = md5_hex(&hello.ja3s);
+    Some(hello)
+}
+
+/// Split the extension block into (type, data) pairs, stopping at truncation
+fn extensions<'a>(reader: &mut Reader<'a>) -> Vec<(u16, &'a [u8])> {
+    let mut extensions = Vec::new();
+    let Some(block) = reader.vec16() else {
+        return extensions;
+    };
+    let mut block = Reader {
+        data: block,
+        pos: 0,
+    };
+    while let (Some(ext_type), Some(data)) = (block.u16(), block.vec16()) {
+        extensions.push((ext_type, data));
+    }
+    extensions
+}
+
+fn server_name(ext: &mut Reader) -> Option<String> {
+    let mut list = Reader {
+        data: ext.vec16()?,
+        pos: 0,
+    };
+    while let Some(name_type) = list.u8() {
+        let name = list.vec16()?;
+        if name_type == 0 {
+            return Some(String::from_utf8_lossy(name).into_owned());
+        }
+    }
+    None
+}
+
+fn alpn(ext: &mut Reader) -> Vec<String> {
+    let mut protocols = Vec::new();
+    let Some(list) = ext.vec16() else {
+        return protocols;
+    };
+    let mut list = Reader { data: list, pos: 0 };
+    while let Some(protocol) = list.vec8() {
+        protocols.push(String::from_utf8_lossy(protocol).into_owned());
+    }
+    protocols
+}
+
+/// GREASE values (RFC 8701) are random padding and excluded from fingerprints
+fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff
+}
+
+fn ja3(hello: &TlsClientHello) -> String {
+    let formats: Vec<u16> = hello.ec_point_formats.iter().map(|&f| f as u16).collect();
+    format!(
+        "{},{},{},{},{}",
+        hello.version,
+        join_decimal(&hello.cipher_suites),
+        join_decimal(&hello.extensions),
+        join_decimal(&hello.supported_groups),
+        join_decimal(&formats)
+    )
+}
+
+/// JA4 client fingerprint: `t13d1516h2_<ciphers hash>_<extensions hash>`
+fn ja4(hello: &TlsClientHello) -> String {
+    let version = hello
+        .supported_versions
+        .iter()
+        .copied()
+        .filter(|v| !is_grease(*v))
+        .max()
+        .unwrap_or(hello.version);
+    let version = match version {
+        0x0304 => "13",
+        0x0303 => "12",
+        0x0302 => "11",
+        0x0301 => "10",
+        0x0300 => "s3",
+        _ => "00",
+    };
+    let sni = if hello.server_name.is_some() {
+        'd'
+    } else {
+        'i'
+    };
+
+    let mut ciphers: Vec<u16> = hello
+        .cipher_suites
+        .iter()
+        .copied()
+        .filter(|c| !is_grease(*c))
+        .collect();
+    let mut extensions: Vec<u16> = hello
+        .extensions
+        .iter()
+        .copied()
+        .filter(|e| !is_grease(*e))
+        .collect();
+    let alpn = match hello.alpn.first().map(|a| a.as_bytes()) {
+        Some([first, .., last])
+            if first.is_ascii_alphanumeric() && last.is_ascii_alphanumeric() =>
+        {
+            format!("{}{}", *first as char, *last as char)
+        }
+        Some([only]) if only.is_ascii_alphanumeric() => {
+            format!("{}{}", *only as char, *only as char)
+        }
+        Some(bytes @ [_, ..]) => {
+            let hex = hex(bytes);
+            format!("{}{}", &hex[..1], &hex[hex.len() - 1..])
+        }
+        _ => "00".to_string(),
+    };
+
+    let prefix = format!(
+        "t{}{}{:02}{:02}{}",
+        version,
+        sni,
+        ciphers.len().min(99),
+        extensions.len().min(99),
+        alpn
+    );
+
+    // The hashed parts are sorted; SNI and ALPN only count towards the prefix
+    ciphers.sort_unstable();
+    extensions.retain(|e| *e != EXT_SERVER_NAME && *e != EXT_ALPN);
+    extensions.sort_unstable();
+
+    let cipher_hash = truncated_sha256(&join_hex(&ciphers));
+    let mut extension_input = join_hex(&extensions);
+    if !hello.signature_algorithms.is_empty() {
+        extension_input.push('_');
+        extension_input.push_str(&join_hex(&hello.signature_algorithms));
+    }
+    let extension_hash = if extensions.is_empty() {
+        "000000000000".to_string()
+    } else {
+        truncated_sha256(&extension_input)
+    };
+
+    format!("{}_{}_{}", prefix, cipher_hash, extension_hash)
+}
+
+fn join_decimal(values: &[u16]) -> String {
+    values
+        .iter()
+        .filter(|v| !is_grease(**v))
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn join_hex(values: &[u16]) -> String {
+    values
+        .iter()
+        .map(|v| format!("{:04x}", v))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn truncated_sha256(input: &str) -> String {
+    if input.is_empty() {
+        return "000000000000".to_string();
+    }
+    hex(&Sha256::digest(input.as_bytes()))[..12].to_string()
+}
+
+fn md5_hex(input: &str) -> String {
+    hex(&Md5::digest(input.as_bytes()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn u16_list(data: &[u8]) -> Vec<u16> {
+    data.chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect()
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos + len)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.bytes(2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// A vector with a one-byte length prefix
+    fn vec8(&mut self) -> Option<&'a [u8]> {
+        let len = self.u8()? as usize;
+        self.bytes(len)
+    }
+
+    /// A vector with a two-byte length prefix
+    fn vec16(&mut self) -> Option<&'a [u8]> {
+        let len = self.u16()? as usize;
+        self.bytes(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ClientHello for example.com with GREASE, ALPN h2/http1.1 and TLS 1.3 offered
+    const CLIENT_HELLO: &str = "16030100820100007e0303000000000000000000000000000000000000000000\
+        00000000000000000000000000080a0a13011302c02b0100004d0a0a0000000000\
+        10000e00000b6578616d706c652e636f6d000a00060004001d0017000b00020100\
+        000d00060004040308040010000e000c02683208687474702f312e31002b000504\
+        03040303";
+
+    // TLS 1.3 ServerHello selecting TLS_AES_128_GCM_SHA256
+    const SERVER_HELLO: &str = "160303003a0200003603030000000000000000000000000000000000000000\
+        00000000000000000000000000130100000e002b0002030400330004001d0000";
+
+    fn unhex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_client_hello_fingerprints() {
+        let record = unhex(CLIENT_HELLO);
+        let mut state = TlsStream::default();
+        let mut messages = Vec::new();
+
+        // Split mid-record: nothing is decoded until the record completes
+        assert_eq!(parse(&record[..50], &mut state, &mut messages), 0);
+        assert_eq!(parse(&record, &mut state, &mut messages), record.len());
+
+        let TlsHandshake::ClientHello(hello) = &messages[0].handshake else {
+            panic!("expected ClientHello");
+        };
+        assert_eq!(hello.server_name.as_deref(), Some("example.com"));
+        assert_eq!(hello.alpn, vec!["h2", "http/1.1"]);
+        assert_eq!(hello.ja3, "771,4865-4866-49195,0-10-11-13-16-43,29-23,0");
+        assert_eq!(hello.ja3_hash, "11138d9933242c3a03b6aad35a296476");
+        assert_eq!(hello.ja4, "t13d0306h2_5559582ccdc4_fb71836bce29");
+        assert_eq!(
+            messages[0].summary(),
+            "Client Hello SNI=example.com ALPN=h2,http/1.1 JA4=t13d0306h2_5559582ccdc4_fb71836bce29"
+        );
+    }
+
+    #[test]
+    fn test_server_hello() {
+        let record = unhex(SERVER_HELLO);
+        let mut state = TlsStream::default();
+        let mut messages = Vec::new();
+        parse(&record, &mut state, &mut messages);
+
+        let TlsHandshake::ServerHello(hello) = &messages[0].handshake else {
+            panic!("expected ServerHello");
+        };
+        assert_eq!(hello.selected_version, Some(0x0304));
+        assert_eq!(hello.ja3s, "771,4865,43-51");
+        assert_eq!(hello.ja3s_hash, "f4febc55ea12b31ae17cfb7e614afda8");
+        assert_eq!(
+            messages[0].summary(),
+            "Server Hello TLS 1.3 TLS_AES_128_GCM_SHA256 JA3S=f4febc55ea12b31ae17cfb7e614afda8"
+        );
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect(&[0x16, 0x03]), None);
+        assert_eq!(detect(&[0x16, 0x03, 0x01, 0x00]), Some(true));
+        assert_eq!(detect(b"GET /"), Some(false));
+        assert!(is_grease(0x1a1a));
+        assert!(!is_grease(0x1a2a));
+    }
+}
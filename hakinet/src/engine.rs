@@ -0,0 +1,396 @@
+//! Runs libpcap on a dedicated thread per source and hands frames to the
+//! async pipeline through bounded queues, merged in timestamp order
+
+use anyhow::{Result, anyhow};
+use log::{debug, warn};
+use pcap::{Activated, Capture, Precision};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+use crate::capture::RawPacket;
+use crate::stats::CaptureStats;
+use hakinet_common::Timestamp;
+
+/// Frames buffered between each capture thread and the consumer. When the
+/// queue is full the thread stops reading and the kernel buffer takes up
+/// the slack, so losses show up in the pcap drop counters.
+pub const QUEUE_CAPACITY: usize = 16384;
+
+/// How often the thread refreshes the libpcap counters of a live capture
+const STATS_PERIOD: Duration = Duration::from_secs(1);
+
+pub struct CaptureEngine {
+    sources: Vec<Source>,
+    stop: Arc<AtomicBool>,
+    /// How long a live frame waits for earlier ones from quieter sources;
+    /// `None` waits for every source, which suits files
+    merge_window: Option<Duration>,
+}
+
+/// A frame and when its capture thread read it
+type Queued = (RawPacket, Instant);
+
+/// The consumer's end of one capture thread
+struct Source {
+    receiver: mpsc::Receiver<Queued>,
+    /// Next frame from this source and when it was read
+    head: Option<Queued>,
+    open: bool,
+    stats: Arc<Mutex<Option<CaptureStats>>>,
+    thread: JoinHandle<Capture<dyn Activated>>,
+}
+
+/// What the merge can do next
+enum Step {
+    Packet(RawPacket),
+    Finished,
+    /// Nothing to release before a source delivers or the deadline passes
+    Wait(Option<Instant>),
+}
+
+impl CaptureEngine {
+    /// Start reading from each capture on its own thread. Frames are tagged
+    /// with the index of their capture. With `live`, the libpcap counters
+    /// are sampled as well, which savefiles do not have.
+    pub fn spawn(
+        captures: Vec<(Capture<dyn Activated>, Precision)>,
+        live: bool,
+        merge_window: Option<Duration>,
+    ) -> Result<Self> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut sources = Vec::with_capacity(captures.len());
+
+        for (interface, (capture, precision)) in captures.into_iter().enumerate() {
+            let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+            let stats = Arc::new(Mutex::new(None));
+            let reader = Reader {
+                interface,
+                precision,
+                sender,
+                stop: stop.clone(),
+                stats: live.then(|| stats.clone()),
+            };
+
+            let thread = std::thread::Builder::new()
+                .name(format!("pcap-{}", interface))
+                .spawn(move || {
+                    let mut capture = capture;
+                    reader.run(&mut capture);
+                    capture
+                })
+                .map_err(|e| anyhow!("Cannot start capture thread: {}", e))?;
+
+            sources.push(Source {
+                receiver,
+                head: None,
+                open: true,
+                stats,
+                thread,
+            });
+        }
+
+        Ok(CaptureEngine {
+            sources,
+            stop,
+            merge_window,
+        })
+    }
+
+    /// The most recent libpcap counters of a live capture, summed over
+    /// its sources
+    pub fn stats(&self) -> Option<CaptureStats> {
+        let stats: Vec<CaptureStats> = self
+            .sources
+            .iter()
+            .filter_map(|source| *source.stats.lock().unwrap_or_else(|e| e.into_inner()))
+            .collect();
+        (!stats.is_empty()).then(|| stats.into_iter().sum())
+    }
+
+    /// Frames read but not yet consumed
+    pub fn queued(&self) -> usize {
+        self.sources
+            .iter()
+            .map(|source| source.receiver.len() + usize::from(source.head.is_some()))
+            .sum()
+    }
+
+    /// The next frame in timestamp order, or `None` once every source is
+    /// exhausted
+    pub async fn next_packet(&mut self) -> Option<RawPacket> {
+        loop {
+            match self.step() {
+                Step::Packet(packet) => return Some(packet),
+                Step::Finished => return None,
+                Step::Wait(deadline) => self.wait(deadline).await,
+            }
+        }
+    }
+
+    /// The next frame if one can be released without waiting
+    pub fn try_next_packet(&mut self) -> Option<RawPacket> {
+        match self.step() {
+            Step::Packet(packet) => Some(packet),
+            Step::Finished | Step::Wait(_) => None,
+        }
+    }
+
+    fn step(&mut self) -> Step {
+        for source in self
+            .sources
+            .iter_mut()
+            .filter(|source| source.head.is_none())
+        {
+            match source.receiver.try_recv() {
+                Ok(queued) => source.head = Some(queued),
+                Err(mpsc::error::TryRecvError::Empty) => {}
+                Err(mpsc::error::TryRecvError::Disconnected) => source.open = false,
+            }
+        }
+
+        let oldest = self
+            .sources
+            .iter()
+            .enumerate()
+            .filter_map(|(index, source)| source.head.as_ref().map(|(packet, _)| (index, packet)))
+            .min_by_key(|(_, packet)| packet.timestamp)
+            .map(|(index, _)| index);
+        let Some(oldest) = oldest else {
+            return if self.sources.iter().any(|source| source.open) {
+                Step::Wait(None)
+            } else {
+                Step::Finished
+            };
+        };
+
+        // A source with nothing queued could still deliver an earlier frame.
+        // The window counts from when the frame was read, so a backlog that
+        // built up behind a quiet source is released without further delay.
+        let now = Instant::now();
+        let pending = self
+            .sources
+            .iter()
+            .any(|source| source.open && source.head.is_none());
+        let deadline = self
+            .merge_window
+            .zip(self.sources[oldest].head.as_ref())
+            .map(|(window, (_, read))| *read + window);
+        if !pending || deadline.is_some_and(|deadline| now >= deadline) {
+            let (packet, _) = self.sources[oldest]
+                .head
+                .take()
+                .expect("head was just found");
+            Step::Packet(packet)
+        } else {
+            Step::Wait(deadline)
+        }
+    }
+
+    /// Wait until a source without a queued frame delivers or closes, or
+    /// until `deadline`
+    async fn wait(&mut self, deadline: Option<Instant>) {
+        let sources = &mut self.sources;
+        let receive = std::future::poll_fn(|cx| {
+            for source in sources
+                .iter_mut()
+                .filter(|source| source.open && source.head.is_none())
+            {
+                match source.receiver.poll_recv(cx) {
+                    Poll::Ready(Some(queued)) => {
+                        source.head = Some(queued);
+                        return Poll::Ready(());
+                    }
+                    Poll::Ready(None) => {
+                        source.open = false;
+                        return Poll::Ready(());
+                    }
+                    Poll::Pending => {}
+                }
+            }
+            Poll::Pending
+        });
+        match deadline {
+            Some(deadline) => {
+                let _ = tokio::time::timeout_at(deadline.into(), receive).await;
+            }
+            None => receive.await,
+        }
+    }
+
+    /// Stop the threads and take the capture handles back, e.g. for their
+    /// statistics. Live handles notice within one read timeout.
+    pub async fn stop(self) -> Result<Vec<Capture<dyn Activated>>> {
+        self.stop.store(true, Ordering::Relaxed);
+        let mut threads = Vec::with_capacity(self.sources.len());
+        for mut source in self.sources {
+            source.receiver.close();
+            threads.push(source.thread);
+        }
+        tokio::task::spawn_blocking(move || {
+            threads
+                .into_iter()
+                .map(|thread| {
+                    thread
+                        .join()
+                        .map_err(|_| anyhow!("Capture thread panicked"))
+                })
+                .collect()
+        })
+        .await?
+    }
+}
+
+/// State moved onto the capture thread
+struct Reader {
+    interface: usize,
+    precision: Precision,
+    sender: mpsc::Sender<Queued>,
+    stop: Arc<AtomicBool>,
+    stats: Option<Arc<Mutex<Option<CaptureStats>>>>,
+}
+
+impl Reader {
+    fn run(mut self, capture: &mut Capture<dyn Activated>) {
+        let mut next_sample = Instant::now();
+        while !self.stop.load(Ordering::Relaxed) {
+            if self.stats.is_some() && Instant::now() >= next_sample {
+                self.sample(capture);
+                next_sample = Instant::now() + STATS_PERIOD;
+            }
+
+            match read_packet(capture, self.precision) {
+                Ok(mut packet) => {
+                    packet.interface = self.interface;
+                    if self.sender.blocking_send((packet, Instant::now())).is_err() {
+                        // The consumer has gone away
+                        break;
+                    }
+                }
+                Err(pcap::Error::TimeoutExpired) => {
+                    // Timeout is normal, continue
+                    continue;
+                }
+                Err(pcap::Error::NoMorePackets) => {
+                    // End of the capture file
+                    break;
+                }
+                Err(e) => {
+                    warn!("Error capturing packet: {}", e);
+                    continue;
+                }
+            }
+        }
+        debug!("Capture thread finished");
+    }
+
+    fn sample(&mut self, capture: &mut Capture<dyn Activated>) {
+        match capture.stats() {
+            Ok(stat) => {
+                if let Some(stats) = &self.stats {
+                    *stats.lock().unwrap_or_else(|e| e.into_inner()) = Some(stat.into());
+                }
+            }
+            Err(e) => {
+                debug!("Capture statistics unavailable: {}", e);
+                self.stats = None;
+            }
+        }
+    }
+}
+
+/// Pull the next frame from the source, copying it out of the pcap buffer
+pub fn read_packet(
+    capture: &mut Capture<dyn Activated>,
+    precision: Precision,
+) -> Result<RawPacket, pcap::Error> {
+    let packet = capture.next_packet()?;
+    // With nanosecond precision libpcap stores nanoseconds in tv_usec
+    let nanos = match precision {
+        Precision::Nano => packet.header.ts.tv_usec as u32,
+        Precision::Micro => packet.header.ts.tv_usec as u32 * 1000,
+    };
+    Ok(RawPacket {
+        timestamp: Timestamp::new(packet.header.ts.tv_sec as u64, nanos),
+        len: packet.header.len,
+        data: packet.data.to_vec(),
+        interface: 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/sample.pcap");
+
+    fn open() -> (Capture<dyn Activated>, Precision) {
+        let capture = Capture::from_file_with_precision(FIXTURE, Precision::Nano).unwrap();
+        (capture.into(), Precision::Nano)
+    }
+
+    #[tokio::test]
+    async fn test_engine_reads_whole_file() {
+        let mut engine = CaptureEngine::spawn(vec![open()], false, None).unwrap();
+
+        let mut timestamps = Vec::new();
+        while let Some(packet) = engine.next_packet().await {
+            timestamps.push(packet.timestamp);
+        }
+        assert_eq!(timestamps.len(), 3);
+        assert!(timestamps.is_sorted());
+        assert_eq!(engine.stats(), None);
+
+        let captures = engine.stop().await.unwrap();
+        assert_eq!(captures[0].get_datalink().0, 1);
+    }
+
+    #[tokio::test]
+    async fn test_engine_merges_sources() {
+        let mut engine = CaptureEngine::spawn(vec![open(), open()], false, None).unwrap();
+
+        let mut packets = Vec::new();
+        while let Some(packet) = engine.next_packet().await {
+            packets.push(packet);
+        }
+        assert_eq!(packets.len(), 6);
+        assert!(packets.is_sorted_by_key(|packet| packet.timestamp));
+        assert_eq!(
+            packets
+                .iter()
+                .filter(|packet| packet.interface == 1)
+                .count(),
+            3
+        );
+        assert_eq!(engine.stop().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_engine_drains_backlog_behind_silent_source() {
+        let window = Duration::from_millis(200);
+        let mut engine = CaptureEngine::spawn(vec![open()], false, Some(window)).unwrap();
+        // A live interface that never sees a frame
+        let (silent, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        engine.sources.push(Source {
+            receiver,
+            head: None,
+            open: true,
+            stats: Arc::new(Mutex::new(None)),
+            thread: std::thread::spawn(|| open().0),
+        });
+
+        // Let the busy source's frames wait in its queue past the window
+        tokio::time::sleep(window + Duration::from_millis(100)).await;
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert!(engine.next_packet().await.is_some());
+        }
+        assert!(start.elapsed() < window);
+
+        drop(silent);
+        assert!(engine.next_packet().await.is_none());
+    }
+}
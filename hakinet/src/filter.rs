@@ -1,40 +1,266 @@
+use anyhow::{Result, anyhow};
 use hakinet_common::PacketInfo;
-use anyhow::Result;
+use std::net::IpAddr;
 
+pub mod bpf;
+mod fields;
+mod parser;
+
+use fields::{Field, Value};
+
+/// A Wireshark-style display filter, evaluated against decoded packets.
+///
+/// Unlike BPF, which the kernel applies to raw frames, display filters see
+/// every field the dissectors produce, so they also work on the payload of
+/// reassembled streams and on offline files.
 pub struct PacketFilter {
-    filter_expr: Option<String>,
+    filter_expr: String,
+    expr: Expr,
 }
 
-impl PacketFilter {
-    pub fn new(filter_expr: Option<String>) -> Self {
-        PacketFilter { filter_expr }
+/// Parsed form of a display filter
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    /// The field or protocol is present
+    Exists(Field),
+    Compare(Field, CmpOp, Literal),
+    Contains(Field, String),
+    In(Field, Vec<SetItem>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Gt,
+    Lt,
+    Ge,
+    Le,
+}
+
+impl CmpOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            CmpOp::Eq => "==",
+            CmpOp::Ne => "!=",
+            CmpOp::Gt => ">",
+            CmpOp::Lt => "<",
+            CmpOp::Ge => ">=",
+            CmpOp::Le => "<=",
+        }
+    }
+
+    fn is_ordering(&self) -> bool {
+        !matches!(self, CmpOp::Eq | CmpOp::Ne)
+    }
+}
+
+/// A constant in a filter, already checked against its field's type
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Int(u64),
+    Str(String),
+    /// An address with a prefix length; a plain address has the full length
+    Net(IpAddr, u8),
+    Bool(bool),
+}
+
+impl Literal {
+    fn matches(&self, value: &Value) -> bool {
+        match (self, value) {
+            (Literal::Int(a), Value::Int(b)) => a == b,
+            (Literal::Str(a), Value::Str(b)) => a == b,
+            (Literal::Bool(a), Value::Bool(b)) => a == b,
+            (Literal::Net(net, prefix), Value::Ip(addr)) => in_subnet(*addr, *net, *prefix),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SetItem {
+    Value(Literal),
+    /// Inclusive integer range, `low..high`
+    Range(u64, u64),
+}
+
+impl SetItem {
+    fn matches(&self, value: &Value) -> bool {
+        match (self, value) {
+            (SetItem::Value(literal), value) => literal.matches(value),
+            (SetItem::Range(low, high), Value::Int(v)) => (low..=high).contains(&v),
+            _ => false,
+        }
+    }
+}
+
+impl Expr {
+    /// Evaluate against a packet. A comparison holds if any occurrence of
+    /// the field satisfies it, except `!=`, which needs every occurrence to
+    /// differ; absent fields never match.
+    pub fn matches(&self, packet: &PacketInfo) -> bool {
+        match self {
+            Expr::Or(a, b) => a.matches(packet) || b.matches(packet),
+            Expr::And(a, b) => a.matches(packet) && b.matches(packet),
+            Expr::Not(expr) => !expr.matches(packet),
+            Expr::Exists(field) => !field.values(packet).is_empty(),
+            Expr::Compare(field, CmpOp::Ne, literal) => {
+                let values = field.values(packet);
+                !values.is_empty() && values.iter().all(|value| !literal.matches(value))
+            }
+            Expr::Compare(field, op, literal) => field
+                .values(packet)
+                .iter()
+                .any(|value| compare(value, *op, literal)),
+            Expr::Contains(field, needle) => field
+                .values(packet)
+                .iter()
+                .any(|value| matches!(value, Value::Str(s) if s.contains(needle.as_str()))),
+            Expr::In(field, items) => field
+                .values(packet)
+                .iter()
+                .any(|value| items.iter().any(|item| item.matches(value))),
+        }
     }
+}
 
-    pub fn matches(&self, _packet: &PacketInfo) -> bool {
-        // For now, we rely on pcap's built-in filtering
-        // This could be extended for additional custom filtering
-        true
+fn compare(value: &Value, op: CmpOp, literal: &Literal) -> bool {
+    match (value, literal) {
+        (Value::Int(v), Literal::Int(l)) => match op {
+            CmpOp::Eq => v == l,
+            CmpOp::Ne => v != l,
+            CmpOp::Gt => v > l,
+            CmpOp::Lt => v < l,
+            CmpOp::Ge => v >= l,
+            CmpOp::Le => v <= l,
+        },
+        _ => op == CmpOp::Eq && literal.matches(value),
     }
+}
 
-    pub fn is_valid_bpf_filter(filter: &str) -> Result<()> {
-        // Basic validation of BPF filter syntax
-        // This is a simplified check - pcap will do the real validation
-        if filter.trim().is_empty() {
-            return Err(anyhow::anyhow!("Filter expression cannot be empty"));
+fn in_subnet(addr: IpAddr, net: IpAddr, prefix: u8) -> bool {
+    match (addr, net) {
+        (IpAddr::V4(addr), IpAddr::V4(net)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(addr) & mask == u32::from(net) & mask
         }
-        Ok(())
+        (IpAddr::V6(addr), IpAddr::V6(net)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(addr) & mask == u128::from(net) & mask
+        }
+        _ => false,
+    }
+}
+
+impl PacketFilter {
+    /// Parse a display filter such as `tcp.dstport == 443 && ip.src in 10.0.0.0/8`
+    pub fn new(filter_expr: &str) -> Result<Self> {
+        let expr =
+            parser::parse(filter_expr).map_err(|e| anyhow!("Invalid display filter: {}", e))?;
+        Ok(PacketFilter {
+            filter_expr: filter_expr.to_string(),
+            expr,
+        })
+    }
+
+    pub fn expression(&self) -> &str {
+        &self.filter_expr
+    }
+
+    pub fn matches(&self, packet: &PacketInfo) -> bool {
+        self.expr.matches(packet)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::capture::parse_packet;
+    use crate::dissect::{Dissector, LINKTYPE_ETHERNET};
+    use crate::testutil::Frame;
+    use hakinet_common::Timestamp;
+
+    /// 10.1.2.3:40000 → 192.168.0.1:443 SYN
+    fn tcp_packet() -> PacketInfo {
+        let frame = Frame::new("10.1.2.3:40000", "192.168.0.1:443").tcp(1, 0x02, &[]);
+        parse_packet(
+            &mut Dissector::new(LINKTYPE_ETHERNET),
+            &frame,
+            Timestamp::new(1, 0),
+        )
+    }
+
+    /// DNS query for www.corp.example.com
+    fn dns_packet() -> PacketInfo {
+        let query = b"\x12\x34\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\x03www\x04corp\x07example\x03com\x00\x00\x01\x00\x01";
+        let frame = Frame::new("10.0.0.5:54321", "10.0.0.53:53").udp(query);
+        parse_packet(
+            &mut Dissector::new(LINKTYPE_ETHERNET),
+            &frame,
+            Timestamp::new(1, 0),
+        )
+    }
+
+    fn matches(filter: &str, packet: &PacketInfo) -> bool {
+        PacketFilter::new(filter).unwrap().matches(packet)
+    }
+
+    #[test]
+    fn test_comparisons_and_logic() {
+        let tcp = tcp_packet();
+        assert!(matches("tcp.dstport == 443 && ip.src in 10.0.0.0/8", &tcp));
+        assert!(matches("tcp and not udp", &tcp));
+        assert!(matches("tcp.port in {22 80..90, 443}", &tcp));
+        assert!(matches("tcp.flags.syn == 1 and tcp.flags.ack == 0", &tcp));
+        assert!(matches(
+            "ip.addr == 192.168.0.1 || ip.addr == 1.1.1.1",
+            &tcp
+        ));
+        assert!(matches("frame.len >= 54 && tcp.srcport gt 1024", &tcp));
+        assert!(matches("eth.src == 02-00-00-00-00-01", &tcp));
+        assert!(!matches("ip.src != 10.1.2.3", &tcp));
+        assert!(!matches("!(tcp.dstport == 443)", &tcp));
+        assert!(!matches("dns", &tcp));
+        // Absent fields never compare unequal
+        assert!(!matches("udp.port != 53", &tcp));
+    }
 
     #[test]
-    fn test_filter_validation() {
-        assert!(PacketFilter::is_valid_bpf_filter("tcp port 80").is_ok());
-        assert!(PacketFilter::is_valid_bpf_filter("udp and port 53").is_ok());
-        assert!(PacketFilter::is_valid_bpf_filter("host 192.168.1.1").is_ok());
-        assert!(PacketFilter::is_valid_bpf_filter("").is_err());
+    fn test_strings() {
+        let dns = dns_packet();
+        assert!(matches("dns.qname contains \"corp\"", &dns));
+        assert!(matches("dns.qry.name == \"www.corp.example.com\"", &dns));
+        assert!(matches("udp.port == 53 and dns.flags.response == 0", &dns));
+        assert!(!matches("dns.qname contains \"CORP\"", &dns));
+        assert!(!matches("dns.qname contains corp && tcp", &dns));
+    }
+
+    #[test]
+    fn test_errors_point_at_token() {
+        fn error(filter: &str) -> (String, &str) {
+            match parser::parse(filter) {
+                Err(e) => (e.message.clone(), &filter[e.start..e.end]),
+                Ok(expr) => panic!("{} parsed as {:?}", filter, expr),
+            }
+        }
+
+        assert_eq!(
+            error("tcp.dstprt == 443"),
+            ("unknown field 'tcp.dstprt'".to_string(), "tcp.dstprt")
+        );
+        assert_eq!(error("ip.src == 10.0.0.300").1, "10.0.0.300");
+        assert_eq!(error("dns.qname > \"a\"").1, ">");
+        assert_eq!(error("tcp.port == 80 &&").1, "");
+        assert_eq!(error("(tcp").0, "expected ')', found end of filter");
+        assert_eq!(error("tcp.port = 80").1, "=");
+
+        let message = PacketFilter::new("udp && ip.src == foo")
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(message.ends_with("udp && ip.src == foo\n                     ^~~"));
     }
 }
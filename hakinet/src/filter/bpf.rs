@@ -0,0 +1,296 @@
+//! BPF compilation against a link type, and a `tcpdump -d` style disassembler
+
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use pcap::{BpfProgram, Capture, Linktype};
+
+/// How `hakinet filter --dump` prints the compiled program, after tcpdump
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// Human-readable assembly (tcpdump -d)
+    Asm,
+    /// C array initializer (tcpdump -dd)
+    C,
+    /// Decimal code, jt, jf and k (tcpdump -ddd)
+    Raw,
+}
+
+/// Resolve a link type given by libpcap name (`EN10MB`, `LINUX_SLL`) or number
+pub fn parse_linktype(name: &str) -> Result<Linktype> {
+    if let Ok(value) = name.parse::<i32>() {
+        return Ok(Linktype(value));
+    }
+    Linktype::from_name(name).map_err(|_| anyhow!("Unknown link type '{}'", name))
+}
+
+pub fn linktype_name(linktype: Linktype) -> String {
+    linktype
+        .get_name()
+        .unwrap_or_else(|_| linktype.0.to_string())
+}
+
+/// Compile a filter with a dead handle, so syntax errors and filters that
+/// make no sense for the link type are caught before any device is opened
+pub fn compile(filter: &str, linktype: Linktype, optimize: bool) -> Result<BpfProgram> {
+    if filter.trim().is_empty() {
+        return Err(anyhow!("Filter expression cannot be empty"));
+    }
+
+    let dead = Capture::dead(linktype).map_err(|e| {
+        anyhow!(
+            "Cannot create a pcap handle for link type {}: {}",
+            linktype.0,
+            e
+        )
+    })?;
+    dead.compile(filter, optimize).map_err(|e| {
+        let message = match e {
+            pcap::Error::PcapError(message) => message,
+            e => e.to_string(),
+        };
+        anyhow!(
+            "Invalid BPF filter '{}' for link type {}: {}",
+            filter,
+            linktype_name(linktype),
+            message
+        )
+    })
+}
+
+/// One classic BPF instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+/// The instructions of a compiled program.
+///
+/// The pcap crate keeps the fields private but prints them as
+/// `code jt jf k`, which is what is parsed here.
+pub fn instructions(program: &BpfProgram) -> Vec<Instruction> {
+    program
+        .get_instructions()
+        .iter()
+        .filter_map(|insn| {
+            let text = insn.to_string();
+            let mut fields = text.split_whitespace();
+            Some(Instruction {
+                code: fields.next()?.parse().ok()?,
+                jt: fields.next()?.parse().ok()?,
+                jf: fields.next()?.parse().ok()?,
+                k: fields.next()?.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Render a program the way tcpdump's -d, -dd and -ddd flags do
+pub fn dump(instructions: &[Instruction], format: DumpFormat) -> Vec<String> {
+    match format {
+        DumpFormat::Asm => instructions
+            .iter()
+            .enumerate()
+            .map(|(pc, insn)| disassemble(insn, pc))
+            .collect(),
+        DumpFormat::C => instructions
+            .iter()
+            .map(|insn| {
+                format!(
+                    "{{ 0x{:x}, {}, {}, 0x{:08x} }},",
+                    insn.code, insn.jt, insn.jf, insn.k
+                )
+            })
+            .collect(),
+        DumpFormat::Raw => std::iter::once(instructions.len().to_string())
+            .chain(
+                instructions
+                    .iter()
+                    .map(|insn| format!("{} {} {} {}", insn.code, insn.jt, insn.jf, insn.k)),
+            )
+            .collect(),
+    }
+}
+
+// Instruction classes, sizes, modes and operations from <pcap/bpf.h>
+const BPF_LD: u16 = 0x00;
+const BPF_LDX: u16 = 0x01;
+const BPF_ST: u16 = 0x02;
+const BPF_STX: u16 = 0x03;
+const BPF_ALU: u16 = 0x04;
+const BPF_JMP: u16 = 0x05;
+const BPF_RET: u16 = 0x06;
+const BPF_MISC: u16 = 0x07;
+
+const BPF_W: u16 = 0x00;
+const BPF_H: u16 = 0x08;
+const BPF_B: u16 = 0x10;
+
+const BPF_IMM: u16 = 0x00;
+const BPF_ABS: u16 = 0x20;
+const BPF_IND: u16 = 0x40;
+const BPF_MEM: u16 = 0x60;
+const BPF_LEN: u16 = 0x80;
+const BPF_MSH: u16 = 0xa0;
+
+const BPF_X: u16 = 0x08;
+const BPF_A: u16 = 0x10;
+
+/// Disassemble one instruction at `pc`, following libpcap's bpf_image()
+fn disassemble(insn: &Instruction, pc: usize) -> String {
+    let k = insn.k;
+    let class = insn.code & 0x07;
+    let size = insn.code & 0x18;
+    let mode = insn.code & 0xe0;
+    let uses_x = insn.code & BPF_X != 0;
+
+    let (op, operand): (&str, String) = match class {
+        BPF_RET => match insn.code & 0x18 {
+            BPF_A => ("ret", String::new()),
+            BPF_X => ("ret", "x".to_string()),
+            _ => ("ret", format!("#{}", k)),
+        },
+        BPF_LD | BPF_LDX => {
+            let op = match (class, size, mode) {
+                (BPF_LDX, BPF_B, BPF_MSH) => "ldxb",
+                (BPF_LDX, _, _) => "ldx",
+                (_, BPF_H, _) => "ldh",
+                (_, BPF_B, _) => "ldb",
+                _ => "ld",
+            };
+            let operand = match mode {
+                BPF_IMM => format!("#0x{:x}", k),
+                BPF_ABS => format!("[{}]", k),
+                BPF_IND => format!("[x + {}]", k),
+                BPF_MEM => format!("M[{}]", k),
+                BPF_LEN => "#pktlen".to_string(),
+                BPF_MSH => format!("4*([{}]&0xf)", k),
+                _ => return unknown(insn, pc),
+            };
+            (op, operand)
+        }
+        BPF_ST => ("st", format!("M[{}]", k)),
+        BPF_STX => ("stx", format!("M[{}]", k)),
+        BPF_ALU => {
+            let (op, hex) = match insn.code & 0xf0 {
+                0x00 => ("add", false),
+                0x10 => ("sub", false),
+                0x20 => ("mul", false),
+                0x30 => ("div", false),
+                0x40 => ("or", true),
+                0x50 => ("and", true),
+                0x60 => ("lsh", false),
+                0x70 => ("rsh", false),
+                0x80 => return format!("({:03}) {:<8}", pc, "neg"),
+                0x90 => ("mod", false),
+                0xa0 => ("xor", true),
+                _ => return unknown(insn, pc),
+            };
+            let operand = match (uses_x, hex) {
+                (true, _) => "x".to_string(),
+                (false, true) => format!("#0x{:x}", k),
+                (false, false) => format!("#{}", k),
+            };
+            (op, operand)
+        }
+        BPF_JMP => {
+            let op = match insn.code & 0xf0 {
+                0x00 => return format!("({:03}) {:<8} {}", pc, "ja", pc + 1 + k as usize),
+                0x10 => "jeq",
+                0x20 => "jgt",
+                0x30 => "jge",
+                0x40 => "jset",
+                _ => return unknown(insn, pc),
+            };
+            let operand = if uses_x {
+                "x".to_string()
+            } else {
+                format!("#0x{:x}", k)
+            };
+            return format!(
+                "({:03}) {:<8} {:<16} jt {}\tjf {}",
+                pc,
+                op,
+                operand,
+                pc + 1 + insn.jt as usize,
+                pc + 1 + insn.jf as usize
+            );
+        }
+        BPF_MISC => match insn.code & 0xf8 {
+            0x00 => ("tax", String::new()),
+            0x80 => ("txa", String::new()),
+            _ => return unknown(insn, pc),
+        },
+        _ => return unknown(insn, pc),
+    };
+
+    format!("({:03}) {:<8} {}", pc, op, operand)
+        .trim_end()
+        .to_string()
+}
+
+fn unknown(insn: &Instruction, pc: usize) -> String {
+    format!("({:03}) unimp    0x{:x}", pc, insn.code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insn(code: u16, jt: u8, jf: u8, k: u32) -> Instruction {
+        Instruction { code, jt, jf, k }
+    }
+
+    #[test]
+    fn test_disassemble_tcp_port_80() {
+        // tcpdump -ddd 'ip and tcp port 80', trimmed to the IPv4 branch
+        let program = [
+            insn(0x28, 0, 0, 12),
+            insn(0x15, 0, 10, 0x0800),
+            insn(0x30, 0, 0, 23),
+            insn(0x15, 0, 8, 6),
+            insn(0x28, 0, 0, 20),
+            insn(0x45, 6, 0, 0x1fff),
+            insn(0xb1, 0, 0, 14),
+            insn(0x48, 0, 0, 14),
+            insn(0x15, 2, 0, 80),
+            insn(0x48, 0, 0, 16),
+            insn(0x15, 0, 1, 80),
+            insn(0x06, 0, 0, 262144),
+            insn(0x06, 0, 0, 0),
+        ];
+        let asm = dump(&program, DumpFormat::Asm);
+        assert_eq!(asm[0], "(000) ldh      [12]");
+        assert_eq!(asm[1], "(001) jeq      #0x800           jt 2\tjf 12");
+        assert_eq!(asm[2], "(002) ldb      [23]");
+        assert_eq!(asm[5], "(005) jset     #0x1fff          jt 12\tjf 6");
+        assert_eq!(asm[6], "(006) ldxb     4*([14]&0xf)");
+        assert_eq!(asm[7], "(007) ldh      [x + 14]");
+        assert_eq!(asm[11], "(011) ret      #262144");
+
+        assert_eq!(
+            dump(&program, DumpFormat::C)[0],
+            "{ 0x28, 0, 0, 0x0000000c },"
+        );
+        let raw = dump(&program, DumpFormat::Raw);
+        assert_eq!((raw[0].as_str(), raw[1].as_str()), ("13", "40 0 0 12"));
+    }
+
+    #[test]
+    fn test_filter_validation() {
+        let ethernet = Linktype(1);
+        assert!(compile("tcp port 80", ethernet, true).is_ok());
+        assert!(compile("udp and port 53", ethernet, true).is_ok());
+        assert!(compile("host 192.168.1.1", ethernet, true).is_ok());
+        assert!(compile("", ethernet, true).is_err());
+
+        let error = compile("tcp prot 80", ethernet, true).err().unwrap();
+        assert!(
+            error
+                .to_string()
+                .starts_with("Invalid BPF filter 'tcp prot 80'")
+        );
+    }
+}
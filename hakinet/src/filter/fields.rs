@@ -0,0 +1,453 @@
+//! Fields available to display filters, named after Wireshark's
+
+use hakinet_common::{DnsRecordData, Layer, PacketInfo, TlsHandshake};
+use std::net::IpAddr;
+
+/// The type of a field, which decides the literals and operators it accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// A protocol name, which can only be tested for presence
+    Protocol,
+    Int,
+    Str,
+    Ip,
+    Mac,
+    Bool,
+}
+
+impl Kind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::Protocol => "protocol",
+            Kind::Int => "integer",
+            Kind::Str => "string",
+            Kind::Ip => "IP address",
+            Kind::Mac => "MAC address",
+            Kind::Bool => "boolean",
+        }
+    }
+}
+
+/// A field value taken from a decoded packet
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(u64),
+    Str(String),
+    Ip(IpAddr),
+    Bool(bool),
+}
+
+type Extract = fn(&PacketInfo, &mut Vec<Value>);
+
+#[derive(Clone, Copy)]
+pub struct Field {
+    pub name: &'static str,
+    pub kind: Kind,
+    extract: Extract,
+}
+
+impl std::fmt::Debug for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+impl Field {
+    pub fn lookup(name: &str) -> Option<Field> {
+        FIELDS.iter().find(|field| field.name == name).copied()
+    }
+
+    /// Every occurrence of the field in the packet; empty if it is absent
+    pub fn values(&self, packet: &PacketInfo) -> Vec<Value> {
+        let mut values = Vec::new();
+        (self.extract)(packet, &mut values);
+        values
+    }
+}
+
+const fn field(name: &'static str, kind: Kind, extract: Extract) -> Field {
+    Field {
+        name,
+        kind,
+        extract,
+    }
+}
+
+/// Iterate over the layers of one variant, e.g. every IPv4 header of a tunnel
+macro_rules! each {
+    ($packet:expr, $variant:ident) => {
+        $packet.layers.iter().filter_map(|layer| match layer {
+            Layer::$variant(inner) => Some(inner),
+            _ => None,
+        })
+    };
+}
+
+/// Client and server hellos of the packet's TLS layers
+fn client_hellos(packet: &PacketInfo) -> impl Iterator<Item = &hakinet_common::TlsClientHello> {
+    each!(packet, Tls).filter_map(|tls| match &tls.handshake {
+        TlsHandshake::ClientHello(hello) => Some(hello),
+        _ => None,
+    })
+}
+
+fn server_hellos(packet: &PacketInfo) -> impl Iterator<Item = &hakinet_common::TlsServerHello> {
+    each!(packet, Tls).filter_map(|tls| match &tls.handshake {
+        TlsHandshake::ServerHello(hello) => Some(hello),
+        _ => None,
+    })
+}
+
+fn int(value: impl Into<u64>) -> Value {
+    Value::Int(value.into())
+}
+
+fn text(value: &str) -> Value {
+    Value::Str(value.to_string())
+}
+
+fn ip(value: impl Into<IpAddr>) -> Value {
+    Value::Ip(value.into())
+}
+
+static FIELDS: &[Field] = &[
+    // Protocols
+    field("eth", Kind::Protocol, |p, out| {
+        out.extend(each!(p, Ethernet).map(|_| Value::Bool(true)))
+    }),
+    field("sll", Kind::Protocol, |p, out| {
+        out.extend(each!(p, LinuxSll).map(|_| Value::Bool(true)))
+    }),
+    field("vlan", Kind::Protocol, |p, out| {
+        out.extend(each!(p, Vlan).map(|_| Value::Bool(true)))
+    }),
+    field("arp", Kind::Protocol, |p, out| {
+        out.extend(each!(p, Arp).map(|_| Value::Bool(true)))
+    }),
+    field("ip", Kind::Protocol, |p, out| {
+        out.extend(each!(p, Ipv4).map(|_| Value::Bool(true)))
+    }),
+    field("ipv6", Kind::Protocol, |p, out| {
+        out.extend(each!(p, Ipv6).map(|_| Value::Bool(true)))
+    }),
+    field("tcp", Kind::Protocol, |p, out| {
+        out.extend(each!(p, Tcp).map(|_| Value::Bool(true)))
+    }),
+    field("udp", Kind::Protocol, |p, out| {
+        out.extend(each!(p, Udp).map(|_| Value::Bool(true)))
+    }),
+    field("icmp", Kind::Protocol, |p, out| {
+        out.extend(each!(p, Icmp).map(|_| Value::Bool(true)))
+    }),
+    field("icmpv6", Kind::Protocol, |p, out| {
+        out.extend(each!(p, Icmpv6).map(|_| Value::Bool(true)))
+    }),
+    field("dns", Kind::Protocol, |p, out| {
+        out.extend(each!(p, Dns).map(|_| Value::Bool(true)))
+    }),
+    field("http", Kind::Protocol, |p, out| {
+        out.extend(each!(p, Http).map(|_| Value::Bool(true)))
+    }),
+    field("tls", Kind::Protocol, |p, out| {
+        out.extend(each!(p, Tls).map(|_| Value::Bool(true)))
+    }),
+    // Frame
+    field("frame.len", Kind::Int, |p, out| {
+        out.push(int(p.length as u64))
+    }),
+    field("frame.interface_name", Kind::Str, |p, out| {
+        out.extend(p.interface.as_deref().map(text))
+    }),
+    // Ethernet and VLAN
+    field("eth.src", Kind::Mac, |p, out| {
+        out.extend(each!(p, Ethernet).map(|eth| text(&eth.src)))
+    }),
+    field("eth.dst", Kind::Mac, |p, out| {
+        out.extend(each!(p, Ethernet).map(|eth| text(&eth.dst)))
+    }),
+    field("eth.addr", Kind::Mac, |p, out| {
+        out.extend(each!(p, Ethernet).flat_map(|eth| [text(&eth.src), text(&eth.dst)]))
+    }),
+    field("eth.type", Kind::Int, |p, out| {
+        out.extend(each!(p, Ethernet).map(|eth| int(eth.ethertype)))
+    }),
+    field("vlan.id", Kind::Int, |p, out| {
+        out.extend(each!(p, Vlan).map(|vlan| int(vlan.id)))
+    }),
+    field("vlan.priority", Kind::Int, |p, out| {
+        out.extend(each!(p, Vlan).map(|vlan| int(vlan.priority)))
+    }),
+    // ARP
+    field("arp.opcode", Kind::Int, |p, out| {
+        out.extend(each!(p, Arp).map(|arp| int(arp.operation)))
+    }),
+    field("arp.src.hw_mac", Kind::Mac, |p, out| {
+        out.extend(each!(p, Arp).map(|arp| text(&arp.sender_mac)))
+    }),
+    field("arp.src.proto_ipv4", Kind::Ip, |p, out| {
+        out.extend(each!(p, Arp).map(|arp| ip(arp.sender_ip)))
+    }),
+    field("arp.dst.hw_mac", Kind::Mac, |p, out| {
+        out.extend(each!(p, Arp).map(|arp| text(&arp.target_mac)))
+    }),
+    field("arp.dst.proto_ipv4", Kind::Ip, |p, out| {
+        out.extend(each!(p, Arp).map(|arp| ip(arp.target_ip)))
+    }),
+    // IPv4
+    field("ip.src", Kind::Ip, |p, out| {
+        out.extend(each!(p, Ipv4).map(|ip4| ip(ip4.src)))
+    }),
+    field("ip.dst", Kind::Ip, |p, out| {
+        out.extend(each!(p, Ipv4).map(|ip4| ip(ip4.dst)))
+    }),
+    field("ip.addr", Kind::Ip, |p, out| {
+        out.extend(each!(p, Ipv4).flat_map(|ip4| [ip(ip4.src), ip(ip4.dst)]))
+    }),
+    field("ip.ttl", Kind::Int, |p, out| {
+        out.extend(each!(p, Ipv4).map(|ip4| int(ip4.ttl)))
+    }),
+    field("ip.proto", Kind::Int, |p, out| {
+        out.extend(each!(p, Ipv4).map(|ip4| int(ip4.protocol)))
+    }),
+    field("ip.len", Kind::Int, |p, out| {
+        out.extend(each!(p, Ipv4).map(|ip4| int(ip4.total_length)))
+    }),
+    field("ip.id", Kind::Int, |p, out| {
+        out.extend(each!(p, Ipv4).map(|ip4| int(ip4.identification)))
+    }),
+    field("ip.dsfield.dscp", Kind::Int, |p, out| {
+        out.extend(each!(p, Ipv4).map(|ip4| int(ip4.dscp)))
+    }),
+    field("ip.flags.df", Kind::Bool, |p, out| {
+        out.extend(each!(p, Ipv4).map(|ip4| Value::Bool(ip4.dont_fragment)))
+    }),
+    field("ip.flags.mf", Kind::Bool, |p, out| {
+        out.extend(each!(p, Ipv4).map(|ip4| Value::Bool(ip4.more_fragments)))
+    }),
+    field("ip.frag_offset", Kind::Int, |p, out| {
+        out.extend(each!(p, Ipv4).map(|ip4| int(ip4.fragment_offset)))
+    }),
+    // IPv6
+    field("ipv6.src", Kind::Ip, |p, out| {
+        out.extend(each!(p, Ipv6).map(|ip6| ip(ip6.src)))
+    }),
+    field("ipv6.dst", Kind::Ip, |p, out| {
+        out.extend(each!(p, Ipv6).map(|ip6| ip(ip6.dst)))
+    }),
+    field("ipv6.addr", Kind::Ip, |p, out| {
+        out.extend(each!(p, Ipv6).flat_map(|ip6| [ip(ip6.src), ip(ip6.dst)]))
+    }),
+    field("ipv6.hlim", Kind::Int, |p, out| {
+        out.extend(each!(p, Ipv6).map(|ip6| int(ip6.hop_limit)))
+    }),
+    field("ipv6.nxt", Kind::Int, |p, out| {
+        out.extend(each!(p, Ipv6).map(|ip6| int(ip6.next_header)))
+    }),
+    field("ipv6.flow", Kind::Int, |p, out| {
+        out.extend(each!(p, Ipv6).map(|ip6| int(ip6.flow_label)))
+    }),
+    field("ipv6.plen", Kind::Int, |p, out| {
+        out.extend(each!(p, Ipv6).map(|ip6| int(ip6.payload_length)))
+    }),
+    // TCP
+    field("tcp.srcport", Kind::Int, |p, out| {
+        out.extend(each!(p, Tcp).map(|tcp| int(tcp.src_port)))
+    }),
+    field("tcp.dstport", Kind::Int, |p, out| {
+        out.extend(each!(p, Tcp).map(|tcp| int(tcp.dst_port)))
+    }),
+    field("tcp.port", Kind::Int, |p, out| {
+        out.extend(each!(p, Tcp).flat_map(|tcp| [int(tcp.src_port), int(tcp.dst_port)]))
+    }),
+    field("tcp.seq", Kind::Int, |p, out| {
+        out.extend(each!(p, Tcp).map(|tcp| int(tcp.seq)))
+    }),
+    field("tcp.ack", Kind::Int, |p, out| {
+        out.extend(each!(p, Tcp).map(|tcp| int(tcp.ack)))
+    }),
+    field("tcp.len", Kind::Int, |p, out| {
+        out.extend(each!(p, Tcp).map(|tcp| int(tcp.payload_len as u64)))
+    }),
+    field("tcp.window_size", Kind::Int, |p, out| {
+        out.extend(each!(p, Tcp).map(|tcp| int(tcp.window)))
+    }),
+    field("tcp.flags.syn", Kind::Bool, |p, out| {
+        out.extend(each!(p, Tcp).map(|tcp| Value::Bool(tcp.flags.syn)))
+    }),
+    field("tcp.flags.ack", Kind::Bool, |p, out| {
+        out.extend(each!(p, Tcp).map(|tcp| Value::Bool(tcp.flags.ack)))
+    }),
+    field("tcp.flags.fin", Kind::Bool, |p, out| {
+        out.extend(each!(p, Tcp).map(|tcp| Value::Bool(tcp.flags.fin)))
+    }),
+    field("tcp.flags.reset", Kind::Bool, |p, out| {
+        out.extend(each!(p, Tcp).map(|tcp| Value::Bool(tcp.flags.rst)))
+    }),
+    field("tcp.flags.push", Kind::Bool, |p, out| {
+        out.extend(each!(p, Tcp).map(|tcp| Value::Bool(tcp.flags.psh)))
+    }),
+    field("tcp.flags.urg", Kind::Bool, |p, out| {
+        out.extend(each!(p, Tcp).map(|tcp| Value::Bool(tcp.flags.urg)))
+    }),
+    // UDP
+    field("udp.srcport", Kind::Int, |p, out| {
+        out.extend(each!(p, Udp).map(|udp| int(udp.src_port)))
+    }),
+    field("udp.dstport", Kind::Int, |p, out| {
+        out.extend(each!(p, Udp).map(|udp| int(udp.dst_port)))
+    }),
+    field("udp.port", Kind::Int, |p, out| {
+        out.extend(each!(p, Udp).flat_map(|udp| [int(udp.src_port), int(udp.dst_port)]))
+    }),
+    field("udp.length", Kind::Int, |p, out| {
+        out.extend(each!(p, Udp).map(|udp| int(udp.length)))
+    }),
+    // ICMP
+    field("icmp.type", Kind::Int, |p, out| {
+        out.extend(each!(p, Icmp).map(|icmp| int(icmp.icmp_type)))
+    }),
+    field("icmp.code", Kind::Int, |p, out| {
+        out.extend(each!(p, Icmp).map(|icmp| int(icmp.code)))
+    }),
+    field("icmpv6.type", Kind::Int, |p, out| {
+        out.extend(each!(p, Icmpv6).map(|icmp| int(icmp.icmp_type)))
+    }),
+    field("icmpv6.code", Kind::Int, |p, out| {
+        out.extend(each!(p, Icmpv6).map(|icmp| int(icmp.code)))
+    }),
+    // DNS
+    field("dns.id", Kind::Int, |p, out| {
+        out.extend(each!(p, Dns).map(|dns| int(dns.id)))
+    }),
+    field("dns.flags.response", Kind::Bool, |p, out| {
+        out.extend(each!(p, Dns).map(|dns| Value::Bool(dns.response)))
+    }),
+    field("dns.flags.rcode", Kind::Int, |p, out| {
+        out.extend(each!(p, Dns).map(|dns| int(dns.rcode)))
+    }),
+    field("dns.qry.name", Kind::Str, |p, out| {
+        out.extend(each!(p, Dns).flat_map(|dns| dns.questions.iter().map(|q| text(&q.name))))
+    }),
+    field("dns.qname", Kind::Str, |p, out| {
+        out.extend(each!(p, Dns).flat_map(|dns| dns.questions.iter().map(|q| text(&q.name))))
+    }),
+    field("dns.qry.type", Kind::Int, |p, out| {
+        out.extend(each!(p, Dns).flat_map(|dns| dns.questions.iter().map(|q| int(q.qtype))))
+    }),
+    field("dns.count.answers", Kind::Int, |p, out| {
+        out.extend(each!(p, Dns).map(|dns| int(dns.answers.len() as u64)))
+    }),
+    field("dns.resp.name", Kind::Str, |p, out| {
+        out.extend(each!(p, Dns).flat_map(|dns| dns.answers.iter().map(|a| text(&a.name))))
+    }),
+    field("dns.a", Kind::Ip, |p, out| {
+        out.extend(each!(p, Dns).flat_map(|dns| {
+            dns.answers.iter().filter_map(|a| match &a.data {
+                DnsRecordData::A { address } => Some(ip(*address)),
+                _ => None,
+            })
+        }))
+    }),
+    field("dns.aaaa", Kind::Ip, |p, out| {
+        out.extend(each!(p, Dns).flat_map(|dns| {
+            dns.answers.iter().filter_map(|a| match &a.data {
+                DnsRecordData::Aaaa { address } => Some(ip(*address)),
+                _ => None,
+            })
+        }))
+    }),
+    field("dns.cname", Kind::Str, |p, out| {
+        out.extend(each!(p, Dns).flat_map(|dns| {
+            dns.answers.iter().filter_map(|a| match &a.data {
+                DnsRecordData::Cname { name } => Some(text(name)),
+                _ => None,
+            })
+        }))
+    }),
+    // HTTP
+    field("http.request", Kind::Bool, |p, out| {
+        out.extend(
+            each!(p, Http)
+                .filter(|http| http.is_request())
+                .map(|_| Value::Bool(true)),
+        )
+    }),
+    field("http.response", Kind::Bool, |p, out| {
+        out.extend(
+            each!(p, Http)
+                .filter(|http| !http.is_request())
+                .map(|_| Value::Bool(true)),
+        )
+    }),
+    field("http.request.method", Kind::Str, |p, out| {
+        out.extend(each!(p, Http).filter_map(|http| http.method.as_deref().map(text)))
+    }),
+    field("http.request.uri", Kind::Str, |p, out| {
+        out.extend(each!(p, Http).filter_map(|http| http.uri.as_deref().map(text)))
+    }),
+    field("http.request.version", Kind::Str, |p, out| {
+        out.extend(each!(p, Http).map(|http| text(&http.version)))
+    }),
+    field("http.response.code", Kind::Int, |p, out| {
+        out.extend(each!(p, Http).filter_map(|http| http.status.map(int)))
+    }),
+    field("http.host", Kind::Str, |p, out| {
+        out.extend(each!(p, Http).filter_map(|http| http.host.as_deref().map(text)))
+    }),
+    field("http.content_type", Kind::Str, |p, out| {
+        out.extend(each!(p, Http).filter_map(|http| http.content_type.as_deref().map(text)))
+    }),
+    field("http.content_length", Kind::Int, |p, out| {
+        out.extend(each!(p, Http).filter_map(|http| http.content_length.map(int)))
+    }),
+    field("http.user_agent", Kind::Str, |p, out| {
+        out.extend(each!(p, Http).filter_map(|http| http.header("User-Agent").map(text)))
+    }),
+    field("http.server", Kind::Str, |p, out| {
+        out.extend(each!(p, Http).filter_map(|http| http.header("Server").map(text)))
+    }),
+    // TLS
+    field("tls.handshake.type", Kind::Int, |p, out| {
+        out.extend(each!(p, Tls).map(|tls| match tls.handshake {
+            TlsHandshake::ClientHello(_) => int(1u8),
+            TlsHandshake::ServerHello(_) => int(2u8),
+        }))
+    }),
+    field("tls.handshake.version", Kind::Int, |p, out| {
+        out.extend(client_hellos(p).map(|hello| int(hello.version)));
+        out.extend(server_hellos(p).map(|hello| int(hello.version)));
+    }),
+    field("tls.handshake.ciphersuite", Kind::Int, |p, out| {
+        out.extend(client_hellos(p).flat_map(|hello| hello.cipher_suites.iter().map(|&c| int(c))));
+        out.extend(server_hellos(p).map(|hello| int(hello.cipher_suite)));
+    }),
+    field(
+        "tls.handshake.extensions_server_name",
+        Kind::Str,
+        |p, out| {
+            out.extend(client_hellos(p).filter_map(|hello| hello.server_name.as_deref().map(text)))
+        },
+    ),
+    field("tls.sni", Kind::Str, |p, out| {
+        out.extend(client_hellos(p).filter_map(|hello| hello.server_name.as_deref().map(text)))
+    }),
+    field("tls.handshake.extensions_alpn_str", Kind::Str, |p, out| {
+        out.extend(client_hellos(p).flat_map(|hello| hello.alpn.iter().map(|a| text(a))));
+        out.extend(server_hellos(p).filter_map(|hello| hello.alpn.as_deref().map(text)));
+    }),
+    field("tls.handshake.ja3", Kind::Str, |p, out| {
+        out.extend(client_hellos(p).map(|hello| text(&hello.ja3)))
+    }),
+    field("tls.handshake.ja3_hash", Kind::Str, |p, out| {
+        out.extend(client_hellos(p).map(|hello| text(&hello.ja3_hash)))
+    }),
+    field("tls.handshake.ja4", Kind::Str, |p, out| {
+        out.extend(client_hellos(p).map(|hello| text(&hello.ja4)))
+    }),
+    field("tls.handshake.ja3s", Kind::Str, |p, out| {
+        out.extend(server_hellos(p).map(|hello| text(&hello.ja3s)))
+    }),
+    field("tls.handshake.ja3s_hash", Kind::Str, |p, out| {
+        out.extend(server_hellos(p).map(|hello| text(&hello.ja3s_hash)))
+    }),
+];
@@ -0,0 +1,476 @@
+//! Tokenizer and recursive-descent parser for display filters.
+//!
+//! Grammar, loosest binding first:
+//!
+//! ```text
+//! expr    = and ( ("||" | "or") and )*
+//! and     = unary ( ("&&" | "and") unary )*
+//! unary   = ("!" | "not") unary | primary
+//! primary = "(" expr ")" | field [ op literal | "contains" string | "in" set ]
+//! set     = "{" item* "}" | cidr
+//! ```
+
+use super::fields::{Field, Kind};
+use super::{CmpOp, Expr, Literal, SetItem};
+use std::fmt;
+use std::net::IpAddr;
+
+/// A display filter that failed to parse, with the span of the offending token
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    filter: String,
+    /// Byte range of the token in the filter text
+    pub start: usize,
+    pub end: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let column = self.filter[..self.start].chars().count();
+        let width = self.filter[self.start..self.end].chars().count().max(1);
+        writeln!(f, "{}", self.message)?;
+        writeln!(f, "    {}", self.filter)?;
+        write!(f, "    {}^{}", " ".repeat(column), "~".repeat(width - 1))
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Field names and unquoted literals: numbers, addresses, words
+    Word(String),
+    Str(String),
+    Cmp(CmpOp),
+    Contains,
+    In,
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    Comma,
+    End,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Word(word) => format!("'{}'", word),
+            Token::Str(s) => format!("\"{}\"", s),
+            Token::Cmp(op) => format!("'{}'", op.symbol()),
+            Token::Contains => "'contains'".to_string(),
+            Token::In => "'in'".to_string(),
+            Token::And => "'&&'".to_string(),
+            Token::Or => "'||'".to_string(),
+            Token::Not => "'!'".to_string(),
+            Token::LParen => "'('".to_string(),
+            Token::RParen => "')'".to_string(),
+            Token::LBrace => "'{'".to_string(),
+            Token::RBrace => "'}'".to_string(),
+            Token::Comma => "','".to_string(),
+            Token::End => "end of filter".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Spanned {
+    token: Token,
+    start: usize,
+    end: usize,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | ':' | '/' | '-')
+}
+
+fn tokenize(filter: &str) -> Result<Vec<Spanned>, (String, usize, usize)> {
+    let mut tokens = Vec::new();
+    let mut chars = filter.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        if is_word_char(c) {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !is_word_char(c) {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let word = &filter[start..end];
+            let token = match word {
+                "and" => Token::And,
+                "or" => Token::Or,
+                "not" => Token::Not,
+                "contains" => Token::Contains,
+                "in" => Token::In,
+                "eq" => Token::Cmp(CmpOp::Eq),
+                "ne" => Token::Cmp(CmpOp::Ne),
+                "gt" => Token::Cmp(CmpOp::Gt),
+                "lt" => Token::Cmp(CmpOp::Lt),
+                "ge" => Token::Cmp(CmpOp::Ge),
+                "le" => Token::Cmp(CmpOp::Le),
+                _ => Token::Word(word.to_string()),
+            };
+            tokens.push(Spanned { token, start, end });
+        } else if c == '"' {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some((end, '"')) => {
+                        tokens.push(Spanned {
+                            token: Token::Str(value),
+                            start,
+                            end: end + 1,
+                        });
+                        break;
+                    }
+                    Some((i, '\\')) => match chars.next() {
+                        Some((_, c @ ('"' | '\\'))) => value.push(c),
+                        Some((_, 'n')) => value.push('\n'),
+                        Some((_, 't')) => value.push('\t'),
+                        Some((j, c)) => {
+                            return Err((
+                                format!("unknown escape sequence '\\{}'", c),
+                                i,
+                                j + c.len_utf8(),
+                            ));
+                        }
+                        None => {
+                            return Err(("unterminated string".to_string(), start, filter.len()));
+                        }
+                    },
+                    Some((_, c)) => value.push(c),
+                    None => {
+                        return Err(("unterminated string".to_string(), start, filter.len()));
+                    }
+                }
+            }
+        } else {
+            chars.next();
+            let next = chars.peek().map(|&(_, c)| c);
+            let (token, len) = match (c, next) {
+                ('=', Some('=')) => (Token::Cmp(CmpOp::Eq), 2),
+                ('!', Some('=')) => (Token::Cmp(CmpOp::Ne), 2),
+                ('>', Some('=')) => (Token::Cmp(CmpOp::Ge), 2),
+                ('<', Some('=')) => (Token::Cmp(CmpOp::Le), 2),
+                ('&', Some('&')) => (Token::And, 2),
+                ('|', Some('|')) => (Token::Or, 2),
+                ('>', _) => (Token::Cmp(CmpOp::Gt), 1),
+                ('<', _) => (Token::Cmp(CmpOp::Lt), 1),
+                ('!', _) => (Token::Not, 1),
+                ('(', _) => (Token::LParen, 1),
+                (')', _) => (Token::RParen, 1),
+                ('{', _) => (Token::LBrace, 1),
+                ('}', _) => (Token::RBrace, 1),
+                (',', _) => (Token::Comma, 1),
+                ('=', _) => return Err(("use '==' for equality".to_string(), start, start + 1)),
+                _ => {
+                    return Err((
+                        format!("unexpected character '{}'", c),
+                        start,
+                        start + c.len_utf8(),
+                    ));
+                }
+            };
+            if len == 2 {
+                chars.next();
+            }
+            tokens.push(Spanned {
+                token,
+                start,
+                end: start + len,
+            });
+        }
+    }
+
+    tokens.push(Spanned {
+        token: Token::End,
+        start: filter.len(),
+        end: filter.len(),
+    });
+    Ok(tokens)
+}
+
+/// Parse a display filter into an expression tree
+pub fn parse(filter: &str) -> Result<Expr, ParseError> {
+    let error = |(message, start, end): (String, usize, usize)| ParseError {
+        message,
+        filter: filter.to_string(),
+        start,
+        end,
+    };
+
+    let tokens = tokenize(filter).map_err(error)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.expr().map_err(error)?;
+    let next = parser.peek();
+    if next.token != Token::End {
+        return Err(error((
+            format!("unexpected {}", next.token.describe()),
+            next.start,
+            next.end,
+        )));
+    }
+    Ok(expr)
+}
+
+type ParseResult<T> = Result<T, (String, usize, usize)>;
+
+struct Parser {
+    tokens: Vec<Spanned>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Spanned {
+        &self.tokens[self.pos]
+    }
+
+    fn next(&mut self) -> Spanned {
+        let token = self.tokens[self.pos].clone();
+        if token.token != Token::End {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn unexpected<T>(&self, token: &Spanned, expected: &str) -> ParseResult<T> {
+        Err((
+            format!("expected {}, found {}", expected, token.token.describe()),
+            token.start,
+            token.end,
+        ))
+    }
+
+    fn expr(&mut self) -> ParseResult<Expr> {
+        let mut left = self.and()?;
+        while self.peek().token == Token::Or {
+            self.next();
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> ParseResult<Expr> {
+        let mut left = self.unary()?;
+        while self.peek().token == Token::And {
+            self.next();
+            left = Expr::And(Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> ParseResult<Expr> {
+        if self.peek().token == Token::Not {
+            self.next();
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> ParseResult<Expr> {
+        let token = self.next();
+        let name = match token.token {
+            Token::LParen => {
+                let expr = self.expr()?;
+                let close = self.next();
+                if close.token != Token::RParen {
+                    return self.unexpected(&close, "')'");
+                }
+                return Ok(expr);
+            }
+            Token::Word(ref name) => name.clone(),
+            _ => return self.unexpected(&token, "a field name"),
+        };
+
+        let Some(field) = Field::lookup(&name) else {
+            return Err((format!("unknown field '{}'", name), token.start, token.end));
+        };
+
+        let op = self.peek().clone();
+        match op.token {
+            Token::Cmp(_) | Token::Contains | Token::In if field.kind == Kind::Protocol => Err((
+                format!(
+                    "'{}' is a protocol and can only be tested for presence",
+                    field.name
+                ),
+                token.start,
+                token.end,
+            )),
+            Token::Cmp(cmp) => {
+                self.next();
+                if cmp.is_ordering() && field.kind != Kind::Int {
+                    return Err((
+                        format!(
+                            "'{}' needs an integer field, but '{}' is a {}",
+                            cmp.symbol(),
+                            field.name,
+                            field.kind.name()
+                        ),
+                        op.start,
+                        op.end,
+                    ));
+                }
+                let literal = self.literal(&field)?;
+                Ok(Expr::Compare(field, cmp, literal))
+            }
+            Token::Contains => {
+                self.next();
+                if field.kind != Kind::Str {
+                    return Err((
+                        format!(
+                            "'contains' needs a string field, but '{}' is a {}",
+                            field.name,
+                            field.kind.name()
+                        ),
+                        op.start,
+                        op.end,
+                    ));
+                }
+                let value = self.next();
+                match value.token {
+                    Token::Str(s) | Token::Word(s) => Ok(Expr::Contains(field, s)),
+                    _ => self.unexpected(&value, "a string"),
+                }
+            }
+            Token::In => {
+                self.next();
+                Ok(Expr::In(field, self.set(&field)?))
+            }
+            _ => Ok(Expr::Exists(field)),
+        }
+    }
+
+    /// Members of an `in` set; an IP field also accepts a bare subnet
+    fn set(&mut self, field: &Field) -> ParseResult<Vec<SetItem>> {
+        if self.peek().token != Token::LBrace {
+            if field.kind == Kind::Ip {
+                return Ok(vec![SetItem::Value(self.literal(field)?)]);
+            }
+            let token = self.next();
+            return self.unexpected(&token, "'{'");
+        }
+        self.next();
+
+        let mut items = Vec::new();
+        loop {
+            let token = self.peek().clone();
+            match &token.token {
+                Token::RBrace => {
+                    self.next();
+                    break;
+                }
+                Token::Comma => {
+                    self.next();
+                }
+                Token::Word(word) if field.kind == Kind::Int && word.contains("..") => {
+                    self.next();
+                    let (low, high) = word.split_once("..").unwrap_or_default();
+                    let (Some(low), Some(high)) = (parse_int(low), parse_int(high)) else {
+                        return Err((format!("invalid range '{}'", word), token.start, token.end));
+                    };
+                    items.push(SetItem::Range(low, high));
+                }
+                Token::End => return self.unexpected(&token, "'}'"),
+                _ => items.push(SetItem::Value(self.literal(field)?)),
+            }
+        }
+
+        if items.is_empty() {
+            let token = &self.tokens[self.pos - 1];
+            return Err(("empty set".to_string(), token.start, token.end));
+        }
+        Ok(items)
+    }
+
+    /// Read a value and check it against the field's type
+    fn literal(&mut self, field: &Field) -> ParseResult<Literal> {
+        let token = self.next();
+        let text = match &token.token {
+            Token::Word(word) => word.as_str(),
+            Token::Str(s) if field.kind == Kind::Str => return Ok(Literal::Str(s.clone())),
+            Token::Str(s) => s.as_str(),
+            _ => return self.unexpected(&token, &format!("a {}", field.kind.name())),
+        };
+
+        let literal = match field.kind {
+            Kind::Int => parse_int(text).map(Literal::Int),
+            Kind::Str => Some(Literal::Str(text.to_string())),
+            Kind::Bool => match text {
+                "1" | "true" | "True" | "TRUE" => Some(Literal::Bool(true)),
+                "0" | "false" | "False" | "FALSE" => Some(Literal::Bool(false)),
+                _ => None,
+            },
+            Kind::Ip => parse_net(text).map(|(addr, prefix)| Literal::Net(addr, prefix)),
+            Kind::Mac => parse_mac(text).map(Literal::Str),
+            Kind::Protocol => None,
+        };
+
+        literal.ok_or_else(|| {
+            (
+                format!(
+                    "'{}' is not a valid {} for '{}'",
+                    text,
+                    field.kind.name(),
+                    field.name
+                ),
+                token.start,
+                token.end,
+            )
+        })
+    }
+}
+
+fn parse_int(text: &str) -> Option<u64> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// An address, optionally with a prefix length (`10.0.0.0/8`)
+fn parse_net(text: &str) -> Option<(IpAddr, u8)> {
+    let (addr, prefix) = match text.split_once('/') {
+        Some((addr, prefix)) => (
+            addr.parse::<IpAddr>().ok()?,
+            Some(prefix.parse::<u8>().ok()?),
+        ),
+        None => (text.parse::<IpAddr>().ok()?, None),
+    };
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    match prefix {
+        Some(prefix) if prefix > max => None,
+        Some(prefix) => Some((addr, prefix)),
+        None => Some((addr, max)),
+    }
+}
+
+/// Normalise a MAC address to the lowercase colon form used by the dissectors
+fn parse_mac(text: &str) -> Option<String> {
+    let octets: Vec<&str> = text.split([':', '-']).collect();
+    if octets.len() != 6 {
+        return None;
+    }
+    let octets = octets
+        .iter()
+        .map(|octet| {
+            (octet.len() <= 2)
+                .then(|| u8::from_str_radix(octet, 16).ok())
+                .flatten()
+                .map(|b| format!("{:02x}", b))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(octets.join(":"))
+}
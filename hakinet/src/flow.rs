@@ -0,0 +1,542 @@
+//! Per-flow connection tracking for the conversation report
+
+use anyhow::Result;
+use clap::ValueEnum;
+use colored::*;
+use hakinet_common::{Layer, PacketInfo, Timestamp, format_bytes, format_duration};
+use log::warn;
+use pnet::packet::ip::IpNextHeaderProtocol;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+
+/// Flows tracked at once; beyond this the least recently seen are evicted
+const MAX_FLOWS: usize = 131_072;
+
+/// File format for `--flows-output`
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlowFormat {
+    Json,
+    Csv,
+}
+
+/// Connection state of a TCP flow, as far as the capture shows it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TcpState {
+    /// First packet seen was not a SYN
+    Midstream,
+    SynSent,
+    SynReceived,
+    Established,
+    /// FIN seen from one side only
+    Closing,
+    /// FIN seen from both sides
+    Closed,
+    Reset,
+}
+
+impl TcpState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TcpState::Midstream => "MIDSTREAM",
+            TcpState::SynSent => "SYN_SENT",
+            TcpState::SynReceived => "SYN_RECEIVED",
+            TcpState::Established => "ESTABLISHED",
+            TcpState::Closing => "CLOSING",
+            TcpState::Closed => "CLOSED",
+            TcpState::Reset => "RESET",
+        }
+    }
+}
+
+/// 5-tuple identifying a flow; side A is whoever sent the first packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct FlowKey {
+    protocol: u8,
+    a: IpAddr,
+    a_port: u16,
+    b: IpAddr,
+    b_port: u16,
+}
+
+impl FlowKey {
+    fn reverse(&self) -> Self {
+        FlowKey {
+            protocol: self.protocol,
+            a: self.b,
+            a_port: self.b_port,
+            b: self.a,
+            b_port: self.a_port,
+        }
+    }
+}
+
+/// Counters for one conversation, A being the side that spoke first
+#[derive(Debug, Clone, Serialize)]
+pub struct Flow {
+    pub protocol: String,
+    pub address_a: IpAddr,
+    pub port_a: Option<u16>,
+    pub address_b: IpAddr,
+    pub port_b: Option<u16>,
+    pub packets_a_to_b: u64,
+    pub bytes_a_to_b: u64,
+    pub packets_b_to_a: u64,
+    pub bytes_b_to_a: u64,
+    pub first_seen: Timestamp,
+    pub last_seen: Timestamp,
+    /// Seconds between the first and last packet
+    pub duration: f64,
+    pub tcp_state: Option<TcpState>,
+    #[serde(skip)]
+    fin_a: bool,
+    #[serde(skip)]
+    fin_b: bool,
+    /// Position in the table's eviction order: when the flow was last seen,
+    /// then the packet count at that moment to break ties
+    #[serde(skip)]
+    recency: (Timestamp, u64),
+}
+
+impl Flow {
+    pub fn packets(&self) -> u64 {
+        self.packets_a_to_b + self.packets_b_to_a
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes_a_to_b + self.bytes_b_to_a
+    }
+
+    /// Advance the TCP state machine with a segment sent by A or B
+    fn track_tcp(&mut self, flags: &hakinet_common::TcpFlags, from_a: bool) {
+        let state = self.tcp_state.get_or_insert(if flags.syn && !flags.ack {
+            TcpState::SynSent
+        } else {
+            TcpState::Midstream
+        });
+
+        if flags.rst {
+            *state = TcpState::Reset;
+            return;
+        }
+        if flags.fin {
+            if from_a {
+                self.fin_a = true;
+            } else {
+                self.fin_b = true;
+            }
+            *state = if self.fin_a && self.fin_b {
+                TcpState::Closed
+            } else {
+                TcpState::Closing
+            };
+            return;
+        }
+
+        *state = match *state {
+            TcpState::SynSent if flags.syn && flags.ack && !from_a => TcpState::SynReceived,
+            TcpState::SynReceived if flags.ack && !flags.syn && from_a => TcpState::Established,
+            // Traffic in both directions on a connection we joined late
+            TcpState::Midstream if !from_a => TcpState::Established,
+            state => state,
+        };
+    }
+}
+
+/// Conversation table built up over a capture
+pub struct FlowTable {
+    flows: HashMap<FlowKey, Flow>,
+    /// Every flow by its recency, least recently seen first
+    order: BTreeMap<(Timestamp, u64), FlowKey>,
+    limit: usize,
+    packets: u64,
+    /// Flows dropped to stay within the limit
+    evicted: u64,
+}
+
+impl Default for FlowTable {
+    fn default() -> Self {
+        Self::with_limit(MAX_FLOWS)
+    }
+}
+
+impl FlowTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with_limit(limit: usize) -> Self {
+        FlowTable {
+            flows: HashMap::new(),
+            order: BTreeMap::new(),
+            limit,
+            packets: 0,
+            evicted: 0,
+        }
+    }
+
+    /// Account a decoded packet to its flow; non-IP packets are ignored
+    pub fn update(&mut self, packet: &PacketInfo) {
+        let Some((key, tcp_flags)) = flow_key(&packet.layers) else {
+            return;
+        };
+
+        let (key, from_a) = if self.flows.contains_key(&key) {
+            (key, true)
+        } else if self.flows.contains_key(&key.reverse()) {
+            (key.reverse(), false)
+        } else {
+            if self.flows.len() >= self.limit {
+                self.evict_oldest();
+            }
+            (key, true)
+        };
+
+        self.packets += 1;
+        let has_ports = matches!(key.protocol, 6 | 17);
+        let flow = self.flows.entry(key).or_insert_with(|| Flow {
+            protocol: protocol_name(key.protocol),
+            address_a: key.a,
+            port_a: has_ports.then_some(key.a_port),
+            address_b: key.b,
+            port_b: has_ports.then_some(key.b_port),
+            packets_a_to_b: 0,
+            bytes_a_to_b: 0,
+            packets_b_to_a: 0,
+            bytes_b_to_a: 0,
+            first_seen: packet.timestamp,
+            last_seen: packet.timestamp,
+            duration: 0.0,
+            tcp_state: None,
+            fin_a: false,
+            fin_b: false,
+            recency: (packet.timestamp, 0),
+        });
+
+        let length = packet.length as u64;
+        if from_a {
+            flow.packets_a_to_b += 1;
+            flow.bytes_a_to_b += length;
+        } else {
+            flow.packets_b_to_a += 1;
+            flow.bytes_b_to_a += length;
+        }
+        flow.last_seen = flow.last_seen.max(packet.timestamp);
+        flow.duration = flow.last_seen.since(flow.first_seen).as_secs_f64();
+        self.order.remove(&flow.recency);
+        flow.recency = (flow.last_seen, self.packets);
+        self.order.insert(flow.recency, key);
+
+        if let Some(flags) = tcp_flags {
+            flow.track_tcp(&flags, from_a);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.flows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flows.is_empty()
+    }
+
+    /// Flows dropped from the table to bound its memory
+    pub fn evicted(&self) -> u64 {
+        self.evicted
+    }
+
+    fn evict_oldest(&mut self) {
+        if self.evicted == 0 {
+            warn!(
+                "Tracking {} flows; evicting the least recently seen from the conversation table",
+                self.limit
+            );
+        }
+        if let Some((_, key)) = self.order.pop_first() {
+            self.flows.remove(&key);
+            self.evicted += 1;
+        }
+    }
+
+    /// Flows ordered by total bytes, busiest first
+    pub fn sorted(&self) -> Vec<&Flow> {
+        let mut flows: Vec<&Flow> = self.flows.values().collect();
+        flows.sort_by(|a, b| {
+            b.bytes()
+                .cmp(&a.bytes())
+                .then(a.first_seen.cmp(&b.first_seen))
+        });
+        flows
+    }
+
+    /// Print the conversation table, like Wireshark's Statistics → Conversations
+    pub fn print_table(&self) {
+        println!();
+        println!(
+            "{}",
+            format!("🔗 Conversations ({} flows)", self.len())
+                .bright_green()
+                .bold()
+        );
+        if self.evicted > 0 {
+            println!(
+                "{}",
+                format!(
+                    "⚠️  {} least recently seen flows were evicted to stay within {} flows",
+                    self.evicted, self.limit
+                )
+                .yellow()
+            );
+        }
+        if self.is_empty() {
+            println!("No IP conversations seen");
+            return;
+        }
+
+        let header = [
+            "Proto",
+            "Address A",
+            "Port",
+            "Address B",
+            "Port",
+            "Pkts A→B",
+            "Bytes A→B",
+            "Pkts B→A",
+            "Bytes B→A",
+            "Duration",
+        ]
+        .map(String::from);
+        println!("{} State", table_row(&header, ' ').bold());
+
+        for flow in self.sorted() {
+            let state = flow.tcp_state.map(|s| s.as_str()).unwrap_or("-");
+            let state = match flow.tcp_state {
+                Some(TcpState::Established | TcpState::SynSent | TcpState::SynReceived) => {
+                    state.bright_yellow()
+                }
+                Some(TcpState::Reset) => state.bright_red(),
+                _ => state.normal(),
+            };
+            let row = [
+                flow.protocol.clone(),
+                flow.address_a.to_string(),
+                port_str(flow.port_a),
+                flow.address_b.to_string(),
+                port_str(flow.port_b),
+                flow.packets_a_to_b.to_string(),
+                format_bytes(flow.bytes_a_to_b),
+                flow.packets_b_to_a.to_string(),
+                format_bytes(flow.bytes_b_to_a),
+                format_duration(flow.last_seen.since(flow.first_seen)),
+            ];
+            println!("{} {}", table_row(&row, '↔'), state);
+        }
+    }
+
+    /// Write the conversation table to a file
+    pub async fn export(&self, path: &str, format: FlowFormat) -> Result<()> {
+        let output = match format {
+            FlowFormat::Json => self.format_json()?,
+            FlowFormat::Csv => self.format_csv(),
+        };
+        tokio::fs::write(path, output).await?;
+        println!("📁 Flows saved to: {}", path);
+        Ok(())
+    }
+
+    fn format_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.sorted())?)
+    }
+
+    fn format_csv(&self) -> String {
+        let mut csv = String::new();
+        csv.push_str("protocol,address_a,port_a,address_b,port_b,packets_a_to_b,bytes_a_to_b,packets_b_to_a,bytes_b_to_a,first_seen,last_seen,duration,tcp_state\n");
+
+        for flow in self.sorted() {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{},{},{},{},{},{:.6},{}\n",
+                flow.protocol,
+                flow.address_a,
+                flow.port_a.map(|p| p.to_string()).unwrap_or_default(),
+                flow.address_b,
+                flow.port_b.map(|p| p.to_string()).unwrap_or_default(),
+                flow.packets_a_to_b,
+                flow.bytes_a_to_b,
+                flow.packets_b_to_a,
+                flow.bytes_b_to_a,
+                flow.first_seen,
+                flow.last_seen,
+                flow.duration,
+                flow.tcp_state.map(|s| s.as_str()).unwrap_or("")
+            ));
+        }
+
+        csv
+    }
+}
+
+/// Lay out one line of the conversation table, `sep` joining the two endpoints
+fn table_row(columns: &[String; 10], sep: char) -> String {
+    format!(
+        "{:<6} {:>39} {:>5} {} {:<39} {:<5} {:>8} {:>10} {:>8} {:>10} {:>10}",
+        columns[0],
+        columns[1],
+        columns[2],
+        sep,
+        columns[3],
+        columns[4],
+        columns[5],
+        columns[6],
+        columns[7],
+        columns[8],
+        columns[9]
+    )
+}
+
+fn protocol_name(protocol: u8) -> String {
+    match protocol {
+        1 => "ICMP".to_string(),
+        6 => "TCP".to_string(),
+        17 => "UDP".to_string(),
+        58 => "ICMPv6".to_string(),
+        other => IpNextHeaderProtocol::new(other).to_string().to_uppercase(),
+    }
+}
+
+fn port_str(port: Option<u16>) -> String {
+    port.map(|p| p.to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// Directional 5-tuple of a packet, plus its TCP flags for TCP packets
+fn flow_key(layers: &[Layer]) -> Option<(FlowKey, Option<hakinet_common::TcpFlags>)> {
+    let mut key: Option<FlowKey> = None;
+
+    for layer in layers {
+        match layer {
+            Layer::Ipv4(ip) => {
+                key = Some(FlowKey {
+                    protocol: ip.protocol,
+                    a: ip.src.into(),
+                    a_port: 0,
+                    b: ip.dst.into(),
+                    b_port: 0,
+                })
+            }
+            Layer::Ipv6(ip) => {
+                key = Some(FlowKey {
+                    protocol: ip.upper_protocol,
+                    a: ip.src.into(),
+                    a_port: 0,
+                    b: ip.dst.into(),
+                    b_port: 0,
+                })
+            }
+            Layer::Tcp(tcp) => {
+                let mut key = key?;
+                key.a_port = tcp.src_port;
+                key.b_port = tcp.dst_port;
+                return Some((key, Some(tcp.flags)));
+            }
+            Layer::Udp(udp) => {
+                let mut key = key?;
+                key.a_port = udp.src_port;
+                key.b_port = udp.dst_port;
+                return Some((key, None));
+            }
+            _ => {}
+        }
+    }
+
+    key.map(|key| (key, None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::parse_packet;
+    use crate::dissect::{Dissector, LINKTYPE_ETHERNET};
+    use crate::testutil::Frame;
+
+    fn tcp_frame(from_client: bool, flags: u8) -> Vec<u8> {
+        let (client, server) = ("10.0.0.1:40000", "10.0.0.2:80");
+        let frame = if from_client {
+            Frame::new(client, server)
+        } else {
+            Frame::new(server, client)
+        };
+        frame.tcp(1, flags, &[])
+    }
+
+    #[test]
+    fn test_tcp_handshake_and_teardown() {
+        let mut dissector = Dissector::new(LINKTYPE_ETHERNET);
+        let mut flows = FlowTable::new();
+        let segments = [
+            (true, 0x02, TcpState::SynSent),
+            (false, 0x12, TcpState::SynReceived),
+            (true, 0x10, TcpState::Established),
+            (false, 0x11, TcpState::Closing),
+            (true, 0x11, TcpState::Closed),
+        ];
+
+        for (i, (from_client, flags, expected)) in segments.into_iter().enumerate() {
+            let frame = tcp_frame(from_client, flags);
+            let packet = parse_packet(&mut dissector, &frame, Timestamp::new(100 + i as u64, 0));
+            flows.update(&packet);
+
+            let flow = flows.sorted()[0];
+            assert_eq!(flow.tcp_state, Some(expected), "after segment {}", i);
+        }
+
+        let flow = flows.sorted()[0];
+        assert_eq!(flows.len(), 1);
+        assert_eq!(flow.address_a.to_string(), "10.0.0.1");
+        assert_eq!(flow.port_b, Some(80));
+        assert_eq!((flow.packets_a_to_b, flow.packets_b_to_a), (3, 2));
+        assert_eq!(flow.bytes(), 5 * 54);
+        assert_eq!(flow.duration, 4.0);
+    }
+
+    #[test]
+    fn test_csv_export() {
+        let mut dissector = Dissector::new(LINKTYPE_ETHERNET);
+        let mut flows = FlowTable::new();
+        let frame = tcp_frame(true, 0x04);
+        flows.update(&parse_packet(&mut dissector, &frame, Timestamp::new(1, 0)));
+
+        let csv = flows.format_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[1],
+            "TCP,10.0.0.1,40000,10.0.0.2,80,1,54,0,0,1.000000000,1.000000000,0.000000,RESET"
+        );
+    }
+
+    #[test]
+    fn test_evicts_least_recently_seen() {
+        let mut dissector = Dissector::new(LINKTYPE_ETHERNET);
+        let mut flows = FlowTable::with_limit(2);
+        let mut update = |flows: &mut FlowTable, client_port: u16, secs: u64| {
+            let frame =
+                Frame::new(&format!("10.0.0.1:{}", client_port), "10.0.0.2:80").tcp(1, 0x02, &[]);
+            let packet = parse_packet(&mut dissector, &frame, Timestamp::new(secs, 0));
+            flows.update(&packet);
+        };
+
+        update(&mut flows, 40000, 1);
+        update(&mut flows, 40001, 2);
+        update(&mut flows, 40000, 3);
+        assert_eq!((flows.len(), flows.evicted()), (2, 0));
+
+        // A third conversation pushes out the one seen least recently
+        update(&mut flows, 40002, 4);
+        assert_eq!((flows.len(), flows.evicted()), (2, 1));
+        assert_eq!(flows.order.len(), 2);
+        let mut ports: Vec<_> = flows.sorted().iter().map(|flow| flow.port_a).collect();
+        ports.sort();
+        assert_eq!(ports, vec![Some(40000), Some(40002)]);
+    }
+}
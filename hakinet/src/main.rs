@@ -1,14 +1,38 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use colored::*;
 use log::info;
-use hakinet_common::{print_cat_banner, print_cat_working, print_cat_done};
+use hakinet_common::{print_cat_banner, print_cat_working, print_cat_done, Shutdown};
+use std::time::Duration;
 
 mod capture;
+mod display;
+mod dissect;
+mod engine;
 mod filter;
+mod flow;
+mod options;
 mod output;
+mod reassembly;
+mod replay;
+mod rotate;
+mod savefile;
+mod stats;
+mod summary;
+#[cfg(test)]
+mod testutil;
+mod tui;
 
-use capture::PacketCapture;
+use capture::{DEFAULT_DROP_THRESHOLD, PacketCapture, TimeFormat};
+use display::HexDump;
+use filter::bpf::{self, DumpFormat};
+use flow::FlowFormat;
+use options::{CaptureDirection, CaptureOptions, TimestampPrecision, TimestampSource};
+use output::OutputFormat;
+use replay::{IpMapping, Pacing, ReplayOptions, Rewrite};
+use rotate::RotationPolicy;
+use savefile::SaveFormat;
+use tui::TuiSettings;
 
 #[derive(Parser)]
 #[command(name = "hakinet")]
@@ -23,77 +47,479 @@ struct Cli {
 enum Commands {
     /// Start packet capture
     Capture {
-        /// Network interface to capture from
-        #[arg(short, long, default_value = "any")]
+        /// Interface to capture from; repeat or separate with commas to merge
+        /// several, or use "any" on Linux (default: the default device)
+        #[arg(short, long, value_delimiter = ',')]
+        interface: Vec<String>,
+
+        #[command(flatten)]
+        device: DeviceArgs,
+
+        #[command(flatten)]
+        pipeline: PipelineArgs,
+    },
+    /// Read packets from a pcap or pcapng file
+    Read {
+        /// Capture file to read
+        #[arg(value_name = "FILE")]
+        file: String,
+
+        #[command(flatten)]
+        pipeline: PipelineArgs,
+    },
+    /// Send the frames of a pcap or pcapng file out of an interface
+    Replay {
+        /// Capture file to replay
+        #[arg(value_name = "FILE")]
+        file: String,
+
+        /// Interface to send on
+        #[arg(short, long)]
         interface: String,
 
-        /// Number of packets to capture (0 = unlimited)
-        #[arg(short, long, default_value = "0")]
-        count: usize,
+        /// Replay at this multiple of the recorded speed
+        #[arg(short = 'x', long, default_value_t = 1.0, conflicts_with_all = ["pps", "topspeed"])]
+        multiplier: f64,
+
+        /// Send at a fixed number of packets per second instead
+        #[arg(long, conflicts_with = "topspeed")]
+        pps: Option<f64>,
+
+        /// Send as fast as the interface accepts frames
+        #[arg(short = 't', long)]
+        topspeed: bool,
+
+        /// Passes over the file; 0 repeats until interrupted
+        #[arg(short, long = "loop", value_name = "N", default_value_t = 1)]
+        loops: u32,
+
+        /// Rewrite the source MAC address of Ethernet frames
+        #[arg(long, value_name = "MAC")]
+        src_mac: Option<pnet::util::MacAddr>,
+
+        /// Rewrite the destination MAC address of Ethernet frames
+        #[arg(long, value_name = "MAC")]
+        dst_mac: Option<pnet::util::MacAddr>,
+
+        /// Replace an IP address wherever it appears as source or destination;
+        /// repeat or separate with commas
+        #[arg(long, value_name = "OLD=NEW", value_delimiter = ',')]
+        ip_map: Vec<IpMapping>,
+    },
+    /// Browse packets interactively in the terminal
+    Tui {
+        /// Interface to capture from; repeat or separate with commas to merge
+        /// several, or use "any" on Linux (default: the default device)
+        #[arg(short, long, value_delimiter = ',')]
+        interface: Vec<String>,
+
+        /// Browse a saved capture file instead of a live interface
+        #[arg(short = 'r', long, value_name = "FILE")]
+        read: Option<String>,
 
         /// Packet filter expression (BPF syntax)
         #[arg(short, long)]
         filter: Option<String>,
 
-        /// Output file (JSON format)
-        #[arg(short, long)]
-        output: Option<String>,
+        /// Initial display filter (Wireshark syntax); press / to change it
+        #[arg(short = 'Y', long, value_name = "FILTER")]
+        display_filter: Option<String>,
 
-        /// Enable verbose output
-        #[arg(short, long)]
-        verbose: bool,
+        /// How packet times are shown
+        #[arg(short = 't', long, default_value = "relative")]
+        time_format: TimeFormat,
+
+        /// Packets kept in memory; older ones are discarded
+        #[arg(long, value_name = "N", default_value_t = tui::DEFAULT_MAX_PACKETS)]
+        max_packets: usize,
+
+        #[command(flatten)]
+        device: DeviceArgs,
     },
     /// List available network interfaces
-    Interfaces,
+    Interfaces {
+        /// Print the list as JSON for scripts
+        #[arg(long)]
+        json: bool,
+    },
+    /// Check a BPF filter expression and optionally dump the compiled program
+    Filter {
+        /// Filter expression (BPF syntax)
+        #[arg(value_name = "EXPRESSION")]
+        expression: String,
+
+        /// Link type to compile for, by libpcap name or number
+        #[arg(short, long, default_value = "EN10MB")]
+        linktype: String,
+
+        /// Print the compiled BPF instructions
+        #[arg(short, long)]
+        dump: bool,
+
+        /// Instruction format for --dump: asm (tcpdump -d), c (-dd) or raw (-ddd)
+        #[arg(long, default_value = "asm")]
+        dump_format: DumpFormat,
+
+        /// Skip the BPF optimizer
+        #[arg(long)]
+        no_optimize: bool,
+    },
+}
+
+/// How the live capture handle is opened
+#[derive(Args)]
+struct DeviceArgs {
+    /// Bytes kept from each frame; small values capture headers only
+    #[arg(short, long, value_name = "BYTES", default_value_t = options::DEFAULT_SNAPLEN)]
+    snaplen: i32,
+
+    /// Don't put the interface into promiscuous mode
+    #[arg(short = 'p', long)]
+    no_promiscuous: bool,
+
+    /// Put a wireless interface into monitor mode
+    #[arg(short = 'I', long)]
+    monitor_mode: bool,
+
+    /// Kernel capture buffer size in KiB
+    #[arg(short = 'B', long, value_name = "KIB")]
+    buffer_size: Option<i32>,
+
+    /// Hand packets over as soon as they arrive instead of in batches
+    #[arg(long)]
+    immediate_mode: bool,
+
+    /// Read timeout in milliseconds
+    #[arg(long, value_name = "MS", default_value_t = options::DEFAULT_TIMEOUT_MS)]
+    read_timeout: i32,
+
+    /// Only capture packets received (in), sent (out) or both (inout)
+    #[arg(short = 'Q', long)]
+    direction: Option<CaptureDirection>,
+
+    /// Clock used for packet timestamps
+    #[arg(short = 'j', long, value_name = "TYPE")]
+    time_stamp_type: Option<TimestampSource>,
+
+    /// Timestamp resolution; nanoseconds when available by default
+    #[arg(long, value_name = "PRECISION")]
+    time_stamp_precision: Option<TimestampPrecision>,
 }
 
+impl DeviceArgs {
+    fn options(&self) -> CaptureOptions {
+        CaptureOptions::new()
+            .with_snaplen(self.snaplen)
+            .with_promisc(!self.no_promiscuous)
+            .with_monitor(self.monitor_mode)
+            .with_buffer_size(self.buffer_size.map(|kib| kib.saturating_mul(1024)))
+            .with_immediate(self.immediate_mode)
+            .with_timeout(self.read_timeout)
+            .with_direction(self.direction)
+            .with_timestamp_source(self.time_stamp_type)
+            .with_precision(self.time_stamp_precision)
+    }
+}
+
+/// Options shared by live capture and offline reading
+#[derive(Args)]
+struct PipelineArgs {
+    /// Number of packets to capture (0 = unlimited)
+    #[arg(short, long, default_value = "0")]
+    count: usize,
+
+    /// Packet filter expression (BPF syntax)
+    #[arg(short, long)]
+    filter: Option<String>,
+
+    /// Display filter applied to decoded packets (Wireshark syntax)
+    #[arg(short = 'Y', long, value_name = "FILTER")]
+    display_filter: Option<String>,
+
+    /// Output file (JSON format)
+    #[arg(short, long)]
+    output: Option<String>,
+
+    /// Layout of the --output file
+    #[arg(long, default_value = "json", requires = "output")]
+    output_format: OutputFormat,
+
+    /// Save raw frames to a capture file (can be combined with --output)
+    #[arg(short = 'w', long, value_name = "FILE")]
+    write_pcap: Option<String>,
+
+    /// Format of the capture file written by --write-pcap
+    #[arg(long, default_value = "pcap")]
+    format: SaveFormat,
+
+    /// Comment stored in the pcapng section header
+    #[arg(long, requires = "write_pcap")]
+    comment: Option<String>,
+
+    /// Start a new output file after this many megabytes (tcpdump -C)
+    #[arg(short = 'C', long, value_name = "MB")]
+    file_size: Option<u64>,
+
+    /// Start a new output file every this many seconds (tcpdump -G)
+    #[arg(short = 'G', long, value_name = "SECONDS")]
+    rotate_seconds: Option<u64>,
+
+    /// Keep only the newest N rotated files (tcpdump -W)
+    #[arg(short = 'W', long, value_name = "N")]
+    file_count: Option<usize>,
+
+    /// Command run with the name of each finished file (tcpdump -z)
+    #[arg(short = 'z', long, value_name = "COMMAND")]
+    post_rotate: Option<String>,
+
+    /// How packet times are printed on the console
+    #[arg(short = 't', long, default_value = "absolute")]
+    time_format: TimeFormat,
+
+    /// Save the end-of-capture summary as JSON
+    #[arg(long, value_name = "FILE")]
+    summary_output: Option<String>,
+
+    /// Track conversations and print a table of them at the end
+    #[arg(long)]
+    flows: bool,
+
+    /// Export the conversation table to a file
+    #[arg(long, value_name = "FILE", requires = "flows")]
+    flows_output: Option<String>,
 
+    /// Format of the file written by --flows-output
+    #[arg(long, default_value = "json", requires = "flows_output")]
+    flows_format: FlowFormat,
+
+    /// Don't print a line per packet
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Seconds between live status lines with rates and drops (0 to disable)
+    #[arg(long, value_name = "SECONDS", default_value = "1")]
+    stats_interval: u64,
+
+    /// Warn when more than this percentage of packets is dropped
+    #[arg(long, value_name = "PERCENT", default_value_t = DEFAULT_DROP_THRESHOLD)]
+    drop_threshold: f64,
+
+    /// Print the decoded layers of each packet; repeat (-vv) for every field
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Hex and ASCII dump from the network layer on; repeat (-XX) to include
+    /// the link-layer header
+    #[arg(short = 'X', action = clap::ArgAction::Count)]
+    hex: u8,
+
+    /// Enable debug logging
+    #[arg(long)]
+    debug: bool,
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
 
+    let log_level = match &cli.command {
+        Commands::Capture { pipeline, .. } | Commands::Read { pipeline, .. } if pipeline.debug => {
+            log::LevelFilter::Debug
+        }
+        // Log lines would tear through the full-screen interface
+        Commands::Tui { .. } => log::LevelFilter::Off,
+        _ => log::LevelFilter::Info,
+    };
+
     // Initialize logger
     env_logger::Builder::from_default_env()
-        .filter_level(log::LevelFilter::Info)
+        .filter_level(log_level)
         .init();
 
-    print_cat_banner("Hakinet", "Your cute network sniffer cat");
+    let shutdown = Shutdown::install();
+
+    // Keep --dump and --json output clean for piping, like tcpdump -d
+    if !matches!(
+        cli.command,
+        Commands::Filter { dump: true, .. }
+            | Commands::Tui { .. }
+            | Commands::Interfaces { json: true }
+    ) {
+        print_cat_banner("Hakinet", "Your cute network sniffer cat");
+    }
 
     match cli.command {
         Commands::Capture {
             interface,
-            count,
-            filter,
-            output,
-            verbose,
+            device,
+            pipeline,
         } => {
-            if verbose {
-                env_logger::Builder::from_default_env()
-                    .filter_level(log::LevelFilter::Debug)
-                    .init();
-            }
-
-            info!("Starting packet capture on interface: {}", interface);
+            info!("Starting packet capture on: {}", interface.join(", "));
             print_cat_working("Hakinet is hunting for packets...");
 
-            let mut capture = PacketCapture::new(&interface)?;
+            let capture = PacketCapture::with_interfaces(&interface, &device.options())?;
+            run_pipeline(capture, pipeline, &shutdown).await?;
 
+            print_cat_done("Packet hunting complete!");
+            println!("{}", "Thanks for using Hakinet! 🐾".bright_magenta());
+        }
+        Commands::Read { file, pipeline } => {
+            info!("Reading packets from file: {}", file);
+            print_cat_working("Hakinet is sniffing through a saved capture...");
+
+            let capture = PacketCapture::from_file(&file)?;
+            run_pipeline(capture, pipeline, &shutdown).await?;
+
+            print_cat_done("Capture file digested!");
+            println!("{}", "Thanks for using Hakinet! 🐾".bright_magenta());
+        }
+        Commands::Replay {
+            file,
+            interface,
+            multiplier,
+            pps,
+            topspeed,
+            loops,
+            src_mac,
+            dst_mac,
+            ip_map,
+        } => {
+            info!("Replaying {} on {}", file, interface);
+            print_cat_working("Hakinet is putting the packets back on the wire...");
+
+            let pacing = match (topspeed, pps) {
+                (true, _) => Pacing::TopSpeed,
+                (false, Some(pps)) => Pacing::Pps(pps),
+                (false, None) => Pacing::Original { multiplier },
+            };
+            let options = ReplayOptions::default()
+                .with_pacing(pacing)
+                .with_loops(loops)
+                .with_rewrite(Rewrite {
+                    src_mac,
+                    dst_mac,
+                    ip_map,
+                });
+            let replay_shutdown = shutdown.clone();
+            let stats = tokio::task::spawn_blocking(move || {
+                replay::replay(&file, &interface, &options, &replay_shutdown)
+            })
+            .await??;
+            stats.print();
+
+            print_cat_done("Replay complete!");
+            println!("{}", "Thanks for using Hakinet! 🐾".bright_magenta());
+        }
+        Commands::Tui {
+            interface,
+            read,
+            filter,
+            display_filter,
+            time_format,
+            max_packets,
+            device,
+        } => {
+            let mut capture = match &read {
+                Some(file) => PacketCapture::from_file(file)?,
+                None => PacketCapture::with_interfaces(&interface, &device.options())?,
+            };
             if let Some(filter_expr) = filter {
                 capture.set_filter(&filter_expr)?;
-                info!("Applied filter: {}", filter_expr);
             }
 
-            capture.start_capture(count, output).await?;
-
-            print_cat_done("Packet hunting complete!");
-            println!("{}", "Thanks for using Hakinet! 🐾".bright_magenta());
+            let settings = TuiSettings {
+                display_filter,
+                time_format,
+                max_packets,
+            };
+            tui::run(capture, settings, &shutdown).await?;
+        }
+        Commands::Interfaces { json } => {
+            if !json {
+                println!("{}", "Available network interfaces:".bright_blue().bold());
+            }
+            capture::list_interfaces(json)?;
         }
-        Commands::Interfaces => {
-            println!("{}", "Available network interfaces:".bright_blue().bold());
-            capture::list_interfaces()?;
+        Commands::Filter {
+            expression,
+            linktype,
+            dump,
+            dump_format,
+            no_optimize,
+        } => {
+            let linktype = bpf::parse_linktype(&linktype)?;
+            let program = bpf::compile(&expression, linktype, !no_optimize)?;
+            let instructions = bpf::instructions(&program);
+
+            if dump {
+                for line in bpf::dump(&instructions, dump_format) {
+                    println!("{}", line);
+                }
+            } else {
+                println!(
+                    "{}",
+                    format!(
+                        "✅ Filter is valid for {} ({} instructions)",
+                        bpf::linktype_name(linktype),
+                        instructions.len()
+                    )
+                    .bright_green()
+                );
+            }
         }
     }
 
+    shutdown.exit_if_triggered();
     Ok(())
 }
+
+/// Apply the shared options to a packet source and run it to completion
+async fn run_pipeline(
+    mut capture: PacketCapture,
+    pipeline: PipelineArgs,
+    shutdown: &Shutdown,
+) -> Result<()> {
+    capture.set_shutdown(shutdown.clone());
+
+    if let Some(filter_expr) = pipeline.filter {
+        capture.set_filter(&filter_expr)?;
+        info!("Applied filter: {}", filter_expr);
+    }
+
+    if let Some(display_filter) = pipeline.display_filter {
+        capture.set_display_filter(&display_filter)?;
+        info!("Applied display filter: {}", display_filter);
+    }
+
+    capture.set_time_format(pipeline.time_format);
+    capture.set_output_format(pipeline.output_format);
+    capture.set_quiet(pipeline.quiet);
+    capture.set_packet_detail(pipeline.verbose, HexDump::from_count(pipeline.hex));
+    capture.set_status(
+        (pipeline.stats_interval > 0).then(|| Duration::from_secs(pipeline.stats_interval)),
+        pipeline.drop_threshold,
+    );
+
+    if let Some(path) = &pipeline.summary_output {
+        capture.set_summary_output(path);
+    }
+
+    if pipeline.flows {
+        capture.set_flows(pipeline.flows_output, pipeline.flows_format);
+    }
+
+    if let Some(policy) = RotationPolicy::from_args(
+        pipeline.file_size,
+        pipeline.rotate_seconds,
+        pipeline.file_count,
+        pipeline.post_rotate,
+    )? {
+        capture.set_rotation(policy);
+    }
+
+    if let Some(path) = pipeline.write_pcap {
+        capture.set_pcap_output(&path, pipeline.format, pipeline.comment);
+    }
+
+    capture.start_capture(pipeline.count, pipeline.output).await
+}
@@ -0,0 +1,303 @@
+//! Settings applied to a live capture handle before it is activated
+
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use log::debug;
+use pcap::{Active, Capture, Device, Direction, Precision, TimestampType};
+use std::ffi::c_int;
+
+/// Default number of bytes kept from each frame
+pub const DEFAULT_SNAPLEN: i32 = 65535;
+
+/// Largest snaplen libpcap accepts
+pub const MAX_SNAPLEN: i32 = 262144;
+
+/// Default read timeout, which also bounds how long stopping a capture takes
+pub const DEFAULT_TIMEOUT_MS: i32 = 1000;
+
+/// Which frames to capture relative to the host, as in tcpdump -Q
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureDirection {
+    /// Frames received by the host
+    In,
+    /// Frames sent by the host
+    Out,
+    /// Both directions
+    Inout,
+}
+
+impl From<CaptureDirection> for Direction {
+    fn from(direction: CaptureDirection) -> Self {
+        match direction {
+            CaptureDirection::In => Direction::In,
+            CaptureDirection::Out => Direction::Out,
+            CaptureDirection::Inout => Direction::InOut,
+        }
+    }
+}
+
+/// Clock used for packet timestamps, named as in tcpdump -j
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimestampSource {
+    /// Host clock, precision unspecified
+    Host,
+    /// Host clock, low precision but cheap to read
+    #[value(name = "host_lowprec")]
+    HostLowPrec,
+    /// Host clock, high precision
+    #[value(name = "host_hiprec")]
+    HostHighPrec,
+    /// Network adapter clock, synchronized with the host
+    Adapter,
+    /// Network adapter clock, not synchronized with the host
+    #[value(name = "adapter_unsynced")]
+    AdapterUnsynced,
+}
+
+impl From<TimestampSource> for TimestampType {
+    fn from(source: TimestampSource) -> Self {
+        match source {
+            TimestampSource::Host => TimestampType::Host,
+            TimestampSource::HostLowPrec => TimestampType::HostLowPrec,
+            TimestampSource::HostHighPrec => TimestampType::HostHighPrec,
+            TimestampSource::Adapter => TimestampType::Adapter,
+            TimestampSource::AdapterUnsynced => TimestampType::AdapterUnsynced,
+        }
+    }
+}
+
+/// Resolution of packet timestamps
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimestampPrecision {
+    Micro,
+    Nano,
+}
+
+impl From<TimestampPrecision> for Precision {
+    fn from(precision: TimestampPrecision) -> Self {
+        match precision {
+            TimestampPrecision::Micro => Precision::Micro,
+            TimestampPrecision::Nano => Precision::Nano,
+        }
+    }
+}
+
+/// libpcap calls made directly because the pcap crate discards their status
+mod ffi {
+    use std::ffi::c_int;
+
+    /// Opaque libpcap handle
+    #[repr(C)]
+    pub struct Pcap {
+        _private: [u8; 0],
+    }
+
+    pub const PCAP_TSTAMP_PRECISION_NANO: c_int = 1;
+    pub const PCAP_WARNING_TSTAMP_TYPE_NOTSUP: c_int = 3;
+
+    unsafe extern "C" {
+        pub fn pcap_set_tstamp_precision(p: *mut Pcap, precision: c_int) -> c_int;
+        pub fn pcap_get_tstamp_precision(p: *mut Pcap) -> c_int;
+        pub fn pcap_set_tstamp_type(p: *mut Pcap, tstamp_type: c_int) -> c_int;
+    }
+}
+
+/// How to open a live capture
+#[derive(Debug, Clone)]
+pub struct CaptureOptions {
+    pub snaplen: i32,
+    pub promisc: bool,
+    pub monitor: bool,
+    /// Kernel buffer size in bytes; libpcap's default when `None`
+    pub buffer_size: Option<i32>,
+    pub immediate: bool,
+    pub timeout_ms: i32,
+    pub direction: Option<CaptureDirection>,
+    pub timestamp_source: Option<TimestampSource>,
+    /// Nanoseconds with a fallback to microseconds when `None`
+    pub precision: Option<TimestampPrecision>,
+}
+
+impl Default for CaptureOptions {
+    fn default() -> Self {
+        CaptureOptions {
+            snaplen: DEFAULT_SNAPLEN,
+            promisc: true,
+            monitor: false,
+            buffer_size: None,
+            immediate: false,
+            timeout_ms: DEFAULT_TIMEOUT_MS,
+            direction: None,
+            timestamp_source: None,
+            precision: None,
+        }
+    }
+}
+
+impl CaptureOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_snaplen(mut self, snaplen: i32) -> Self {
+        self.snaplen = snaplen;
+        self
+    }
+
+    pub fn with_promisc(mut self, promisc: bool) -> Self {
+        self.promisc = promisc;
+        self
+    }
+
+    /// 802.11 monitor mode (tcpdump -I)
+    pub fn with_monitor(mut self, monitor: bool) -> Self {
+        self.monitor = monitor;
+        self
+    }
+
+    pub fn with_buffer_size(mut self, bytes: Option<i32>) -> Self {
+        self.buffer_size = bytes;
+        self
+    }
+
+    /// Deliver packets as soon as they arrive instead of batching them
+    pub fn with_immediate(mut self, immediate: bool) -> Self {
+        self.immediate = immediate;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout_ms: i32) -> Self {
+        self.timeout_ms = timeout_ms;
+        self
+    }
+
+    pub fn with_direction(mut self, direction: Option<CaptureDirection>) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    pub fn with_timestamp_source(mut self, source: Option<TimestampSource>) -> Self {
+        self.timestamp_source = source;
+        self
+    }
+
+    pub fn with_precision(mut self, precision: Option<TimestampPrecision>) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if !(1..=MAX_SNAPLEN).contains(&self.snaplen) {
+            return Err(anyhow!(
+                "Snapshot length must be between 1 and {} bytes",
+                MAX_SNAPLEN
+            ));
+        }
+        if self.buffer_size.is_some_and(|size| size <= 0) {
+            return Err(anyhow!("Buffer size must be greater than zero"));
+        }
+        // A zero timeout blocks forever, so the capture could not be stopped
+        if self.timeout_ms <= 0 {
+            return Err(anyhow!("Read timeout must be at least 1 ms"));
+        }
+        Ok(())
+    }
+
+    /// Open `device` with these settings, along with the timestamp
+    /// precision libpcap actually applied. Without an explicit precision
+    /// nanoseconds are tried first.
+    pub fn open(&self, device: Device) -> Result<(Capture<Active>, Precision)> {
+        let name = device.name.clone();
+        let mut inactive = Capture::from_device(device)?
+            .promisc(self.promisc)
+            .rfmon(self.monitor)
+            .snaplen(self.snaplen)
+            .timeout(self.timeout_ms)
+            .immediate_mode(self.immediate);
+        if let Some(size) = self.buffer_size {
+            inactive = inactive.buffer_size(size);
+        }
+        if let Some(source) = self.timestamp_source {
+            let tstamp_type = TimestampType::from(source) as c_int;
+            let status =
+                unsafe { ffi::pcap_set_tstamp_type(inactive.as_ptr().cast(), tstamp_type) };
+            // A warning means libpcap would silently fall back to the default clock
+            match status {
+                0 => {}
+                ffi::PCAP_WARNING_TSTAMP_TYPE_NOTSUP => {
+                    let label = source
+                        .to_possible_value()
+                        .map(|value| value.get_name().to_string())
+                        .unwrap_or_default();
+                    return Err(anyhow!("{} does not support {} timestamps", name, label));
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "{} does not allow choosing the timestamp source",
+                        name
+                    ));
+                }
+            }
+        }
+
+        let wanted = self.precision.map_or(Precision::Nano, Precision::from);
+        let unsupported = || {
+            let resolution = match wanted {
+                Precision::Nano => "nanosecond",
+                Precision::Micro => "microsecond",
+            };
+            anyhow!("{} does not support {} timestamps", name, resolution)
+        };
+        let status =
+            unsafe { ffi::pcap_set_tstamp_precision(inactive.as_ptr().cast(), wanted as c_int) };
+        if status != 0 {
+            if self.precision.is_some() {
+                return Err(unsupported());
+            }
+            debug!(
+                "Nanosecond timestamps unavailable on {}, using microseconds",
+                name
+            );
+        }
+
+        let capture = inactive.open()?;
+        // Read back what was applied rather than trusting the request
+        let precision = match unsafe { ffi::pcap_get_tstamp_precision(capture.as_ptr().cast()) } {
+            ffi::PCAP_TSTAMP_PRECISION_NANO => Precision::Nano,
+            _ => Precision::Micro,
+        };
+        if self.precision.is_some() && precision != wanted {
+            return Err(unsupported());
+        }
+
+        if let Some(direction) = self.direction {
+            capture.direction(direction.into())?;
+        }
+        Ok((capture, precision))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert!(CaptureOptions::new().validate().is_ok());
+        assert!(CaptureOptions::new().with_snaplen(96).validate().is_ok());
+        assert!(CaptureOptions::new().with_snaplen(0).validate().is_err());
+        assert!(
+            CaptureOptions::new()
+                .with_snaplen(MAX_SNAPLEN + 1)
+                .validate()
+                .is_err()
+        );
+        assert!(CaptureOptions::new().with_timeout(0).validate().is_err());
+        assert!(
+            CaptureOptions::new()
+                .with_buffer_size(Some(0))
+                .validate()
+                .is_err()
+        );
+    }
+}
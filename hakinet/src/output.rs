@@ -1,79 +1,247 @@
 use anyhow::Result;
+use clap::ValueEnum;
 use log::info;
-use serde_json;
+use std::io::SeekFrom;
 use tokio::fs::OpenOptions;
-use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt, BufWriter};
 
+use crate::rotate::{Rotation, RotationPolicy};
 use hakinet_common::PacketInfo;
 
+/// Layout of the `--output` file
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// JSON array of pretty-printed packets
+    Json,
+    /// JSON array with one compact packet per line
+    Compact,
+    /// Newline-delimited JSON, one packet per line, for tail/jq/log shippers
+    Ndjson,
+}
+
+impl OutputFormat {
+    fn is_array(self) -> bool {
+        self != OutputFormat::Ndjson
+    }
+}
+
+/// Written after every packet of an array file and overwritten by the next
+/// one, so the file on disk is always a complete array
+const ARRAY_END: &[u8] = b"\n]\n";
+
 pub struct OutputWriter {
     writer: Option<BufWriter<tokio::fs::File>>,
     output_file: Option<String>,
+    format: OutputFormat,
+    /// Path of the file being written, which differs from `output_file`
+    /// when rotating
+    current_file: Option<String>,
+    rotation: Option<Rotation>,
+    file_bytes: u64,
+    file_packets: usize,
     packet_count: usize,
 }
 
 impl OutputWriter {
-    pub fn new(output_file: Option<String>) -> Result<Self> {
+    /// With a rotation policy, `output_file` is a strftime pattern
+    pub fn new(
+        output_file: Option<String>,
+        format: OutputFormat,
+        rotation: Option<RotationPolicy>,
+    ) -> Result<Self> {
+        let rotation = match (&output_file, rotation) {
+            (Some(pattern), Some(policy)) => Some(Rotation::new(pattern, policy)?),
+            _ => None,
+        };
+
         Ok(OutputWriter {
             writer: None,
             output_file,
+            format,
+            current_file: None,
+            rotation,
+            file_bytes: 0,
+            file_packets: 0,
             packet_count: 0,
         })
     }
 
-    pub async fn write_packet(&mut self, packet: &PacketInfo) -> Result<()> {
-        if let Some(output_file) = &self.output_file {
-            if self.writer.is_none() {
-                let file = OpenOptions::new()
-                    .create(true)
-                    .write(true)
-                    .truncate(true)
-                    .open(output_file)
-                    .await?;
-
-                let mut buf_writer = BufWriter::new(file);
-
-                // Write JSON array opening bracket
-                buf_writer.write_all(b"[\n").await?;
-
-                self.writer = Some(buf_writer);
-                info!("Created output file: {}", output_file);
-            }
+    async fn open(&mut self, path: &str) -> Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)
+            .await?;
 
-            if let Some(ref mut writer) = self.writer {
-                // Add comma separator for subsequent packets
-                if self.packet_count > 0 {
-                    writer.write_all(b",\n").await?;
-                }
+        let mut buf_writer = BufWriter::new(file);
+        self.file_bytes = 0;
+        if self.format.is_array() {
+            // Write JSON array opening bracket
+            buf_writer.write_all(b"[\n").await?;
+            self.file_bytes = 2;
+        }
 
-                // Write packet as JSON
-                let json = serde_json::to_string_pretty(packet)?;
-                writer.write_all(json.as_bytes()).await?;
+        self.writer = Some(buf_writer);
+        self.current_file = Some(path.to_string());
+        self.file_packets = 0;
+        self.sync().await?;
+        info!("Created output file: {}", path);
+        Ok(())
+    }
 
-                self.packet_count += 1;
+    /// Push everything written so far to disk. Array files get their closing
+    /// bracket, and the position is moved back over it for the next packet.
+    async fn sync(&mut self) -> Result<()> {
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+        if self.format.is_array() {
+            writer.write_all(ARRAY_END).await?;
+            writer.flush().await?;
+            writer
+                .seek(SeekFrom::Current(-(ARRAY_END.len() as i64)))
+                .await?;
+        } else {
+            writer.flush().await?;
+        }
+        Ok(())
+    }
 
-                // Flush periodically to ensure data is written
-                if self.packet_count % 10 == 0 {
-                    writer.flush().await?;
-                }
+    /// Finish the current file and hand it to the rotation policy
+    async fn finish_file(&mut self) -> Result<()> {
+        if let Some(mut writer) = self.writer.take() {
+            if self.format.is_array() {
+                writer.write_all(ARRAY_END).await?;
+            }
+            writer.flush().await?;
+            if let (Some(rotation), Some(path)) = (self.rotation.as_mut(), &self.current_file) {
+                rotation.retire(path);
             }
         }
-
         Ok(())
     }
 
-    pub async fn close(&mut self) -> Result<()> {
+    pub async fn write_packet(&mut self, packet: &PacketInfo) -> Result<()> {
+        let Some(output_file) = self.output_file.clone() else {
+            return Ok(());
+        };
+
+        let due = self
+            .rotation
+            .as_ref()
+            .is_some_and(|rotation| rotation.is_due(self.file_bytes, packet.timestamp));
+        if due {
+            self.finish_file().await?;
+        }
+        if self.writer.is_none() {
+            let path = match self.rotation.as_mut() {
+                Some(rotation) => rotation.next_path(packet.timestamp),
+                None => output_file,
+            };
+            self.open(&path).await?;
+        }
+
+        let json = match self.format {
+            OutputFormat::Json => serde_json::to_string_pretty(packet)?,
+            OutputFormat::Compact | OutputFormat::Ndjson => serde_json::to_string(packet)?,
+        };
+
         if let Some(ref mut writer) = self.writer {
-            // Close JSON array
-            writer.write_all(b"\n]\n").await?;
-            writer.flush().await?;
+            // Add comma separator for subsequent packets
+            if self.format.is_array() && self.file_packets > 0 {
+                writer.write_all(b",\n").await?;
+                self.file_bytes += 2;
+            }
 
-            if let Some(output_file) = &self.output_file {
-                info!("Saved {} packets to: {}", self.packet_count, output_file);
-                println!("📁 Output saved to: {}", output_file);
+            writer.write_all(json.as_bytes()).await?;
+            self.file_bytes += json.len() as u64;
+            if self.format == OutputFormat::Ndjson {
+                writer.write_all(b"\n").await?;
+                self.file_bytes += 1;
             }
+
+            self.file_packets += 1;
+            self.packet_count += 1;
+        }
+
+        // Flush every packet so the file can be followed live and survives
+        // an interrupted capture
+        self.sync().await
+    }
+
+    pub async fn close(&mut self) -> Result<()> {
+        if self.writer.is_none() {
+            return Ok(());
+        }
+        self.finish_file().await?;
+
+        if let Some(output_file) = &self.current_file {
+            info!("Saved {} packets to: {}", self.packet_count, output_file);
+            println!("📁 Output saved to: {}", output_file);
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(port: u16) -> PacketInfo {
+        let mut packet = PacketInfo::new();
+        packet.protocol = "UDP".to_string();
+        packet.dst_port = Some(port);
+        packet
+    }
+
+    #[tokio::test]
+    async fn test_array_is_valid_before_close() {
+        let path = std::env::temp_dir().join(format!("hakinet-output-{}.json", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        let mut writer =
+            OutputWriter::new(Some(path.clone()), OutputFormat::Compact, None).unwrap();
+
+        for port in [53, 123] {
+            writer.write_packet(&packet(port)).await.unwrap();
+        }
+        // As left behind by a killed capture
+        let packets: Vec<PacketInfo> =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[1].dst_port, Some(123));
+
+        writer.close().await.unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.ends_with("}\n]\n"));
+        assert_eq!(
+            serde_json::from_str::<Vec<PacketInfo>>(&text)
+                .unwrap()
+                .len(),
+            2
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_lines() {
+        let path =
+            std::env::temp_dir().join(format!("hakinet-output-{}.ndjson", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        let mut writer = OutputWriter::new(Some(path.clone()), OutputFormat::Ndjson, None).unwrap();
+
+        for port in [53, 123, 443] {
+            writer.write_packet(&packet(port)).await.unwrap();
+        }
+        let text = std::fs::read_to_string(&path).unwrap();
+        let ports: Vec<Option<u16>> = text
+            .lines()
+            .map(|line| serde_json::from_str::<PacketInfo>(line).unwrap().dst_port)
+            .collect();
+        assert_eq!(ports, vec![Some(53), Some(123), Some(443)]);
+
+        writer.close().await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
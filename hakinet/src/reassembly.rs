@@ -0,0 +1,414 @@
+//! TCP stream reassembly shared by the stream-based dissectors.
+//!
+//! Each direction of a connection is reassembled on its own. Segments are
+//! placed by sequence number, so retransmissions and overlaps are trimmed and
+//! out-of-order segments wait until the gap before them is filled. Dissectors
+//! read the contiguous bytes from [`Stream::data`] and [`Stream::consume`]
+//! what they have decoded, keeping their own per-direction state in
+//! [`Stream::state`].
+
+use hakinet_common::{TcpFlags, Timestamp};
+use log::debug;
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// Out-of-order bytes held per direction before the gap is given up on
+const MAX_PENDING_BYTES: usize = 1 << 20;
+/// Contiguous bytes held per direction that no dissector has consumed
+const MAX_BUFFERED_BYTES: usize = 1 << 20;
+/// Streams tracked at once; the least recently seen are evicted beyond this
+const MAX_STREAMS: usize = 16384;
+/// Streams idle for longer than this (in capture time) are dropped
+const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+/// How many segments to process between idle sweeps
+const SWEEP_INTERVAL: u64 = 4096;
+
+/// One direction of a TCP connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StreamKey {
+    pub src: IpAddr,
+    pub src_port: u16,
+    pub dst: IpAddr,
+    pub dst_port: u16,
+}
+
+impl StreamKey {
+    /// The opposite direction of the same connection
+    pub fn reverse(&self) -> Self {
+        StreamKey {
+            src: self.dst,
+            src_port: self.dst_port,
+            dst: self.src,
+            dst_port: self.src_port,
+        }
+    }
+}
+
+/// Reassembled bytes for one direction plus dissector state of type `S`
+#[derive(Debug)]
+pub struct Stream<S> {
+    /// Sequence number of the next byte expected in order
+    next_seq: Option<u32>,
+    /// Segments received ahead of `next_seq`
+    pending: Vec<(u32, Vec<u8>)>,
+    pending_bytes: usize,
+    /// In-order bytes not yet consumed by a dissector
+    data: Vec<u8>,
+    /// Set when bytes were lost; cleared by [`Stream::take_gap`]
+    gap: bool,
+    closed: bool,
+    /// Position in the reassembler's eviction order: when the stream was
+    /// last seen, then the segment count at that moment to break ties
+    recency: (Timestamp, u64),
+    pub state: S,
+}
+
+impl<S: Default> Stream<S> {
+    fn new(recency: (Timestamp, u64)) -> Self {
+        Stream {
+            next_seq: None,
+            pending: Vec::new(),
+            pending_bytes: 0,
+            data: Vec::new(),
+            gap: false,
+            closed: false,
+            recency,
+            state: S::default(),
+        }
+    }
+}
+
+impl<S> Stream<S> {
+    /// Contiguous bytes received in order and not yet consumed
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The contiguous bytes together with the dissector state, for parsers
+    /// that update their state while reading
+    pub fn data_and_state(&mut self) -> (&[u8], &mut S) {
+        (&self.data, &mut self.state)
+    }
+
+    /// Drop the first `len` bytes once a dissector has decoded them
+    pub fn consume(&mut self, len: usize) {
+        self.data.drain(..len.min(self.data.len()));
+    }
+
+    /// Whether bytes were lost since the last call, so a dissector can
+    /// resynchronise instead of misreading the data after the hole
+    pub fn take_gap(&mut self) -> bool {
+        std::mem::take(&mut self.gap)
+    }
+
+    /// Place a segment; returns true if new in-order bytes became available
+    fn segment(&mut self, seq: u32, flags: TcpFlags, payload: &[u8]) -> bool {
+        if flags.syn {
+            self.next_seq = Some(seq.wrapping_add(1));
+            self.pending.clear();
+            self.pending_bytes = 0;
+            self.data.clear();
+            self.closed = false;
+        }
+        if flags.fin || flags.rst {
+            self.closed = true;
+        }
+        if payload.is_empty() || flags.syn {
+            return false;
+        }
+
+        // Picked up mid-connection: start from the first segment we see
+        let next_seq = *self.next_seq.get_or_insert(seq);
+        let offset = seq.wrapping_sub(next_seq) as i32;
+
+        if offset > 0 {
+            if !self
+                .pending
+                .iter()
+                .any(|(s, p)| *s == seq && p.len() >= payload.len())
+            {
+                self.pending_bytes += payload.len();
+                self.pending.push((seq, payload.to_vec()));
+            }
+            if self.pending_bytes > MAX_PENDING_BYTES {
+                self.skip_gap();
+                return self.drain_pending();
+            }
+            return false;
+        }
+
+        let appended = self.append(offset, payload);
+        self.drain_pending() || appended
+    }
+
+    /// Append a segment starting `offset` bytes from `next_seq` (zero or
+    /// negative), trimming bytes that were already delivered
+    fn append(&mut self, offset: i32, payload: &[u8]) -> bool {
+        let skip = offset.unsigned_abs() as usize;
+        if skip >= payload.len() {
+            // Pure retransmission
+            return false;
+        }
+
+        let fresh = &payload[skip..];
+        if self.data.len() + fresh.len() > MAX_BUFFERED_BYTES {
+            // Nobody is consuming this direction; keep the newest bytes only
+            self.data.clear();
+            self.gap = true;
+        }
+        self.data.extend_from_slice(fresh);
+        self.next_seq = self
+            .next_seq
+            .map(|seq| seq.wrapping_add(fresh.len() as u32));
+        true
+    }
+
+    /// Move queued segments that now line up into the contiguous buffer
+    fn drain_pending(&mut self) -> bool {
+        let mut appended = false;
+
+        while let Some(next_seq) = self.next_seq {
+            let Some(index) = self
+                .pending
+                .iter()
+                .position(|(seq, _)| seq.wrapping_sub(next_seq) as i32 <= 0)
+            else {
+                break;
+            };
+            let (seq, payload) = self.pending.swap_remove(index);
+            self.pending_bytes -= payload.len();
+            appended |= self.append(seq.wrapping_sub(next_seq) as i32, &payload);
+        }
+
+        appended
+    }
+
+    /// Give up waiting for missing bytes and resume at the earliest queued segment
+    fn skip_gap(&mut self) {
+        let Some(next_seq) = self.next_seq else {
+            return;
+        };
+        if let Some(earliest) = self
+            .pending
+            .iter()
+            .map(|(seq, _)| *seq)
+            .min_by_key(|seq| seq.wrapping_sub(next_seq))
+        {
+            debug!(
+                "Skipping {} missing bytes in TCP stream",
+                earliest.wrapping_sub(next_seq)
+            );
+            self.next_seq = Some(earliest);
+            self.data.clear();
+            self.gap = true;
+        }
+    }
+}
+
+/// Tracks every TCP stream seen in a capture
+pub struct TcpReassembler<S> {
+    streams: HashMap<StreamKey, Stream<S>>,
+    /// Every stream by its recency, least recently seen first
+    order: BTreeMap<(Timestamp, u64), StreamKey>,
+    segments: u64,
+}
+
+impl<S: Default> TcpReassembler<S> {
+    pub fn new() -> Self {
+        TcpReassembler {
+            streams: HashMap::new(),
+            order: BTreeMap::new(),
+            segments: 0,
+        }
+    }
+
+    /// Feed one segment. Returns the stream when new in-order bytes are
+    /// ready for its dissectors.
+    pub fn push(
+        &mut self,
+        key: StreamKey,
+        seq: u32,
+        flags: TcpFlags,
+        payload: &[u8],
+        timestamp: Timestamp,
+    ) -> Option<&mut Stream<S>> {
+        self.segments += 1;
+        if self.segments.is_multiple_of(SWEEP_INTERVAL) {
+            self.sweep(timestamp);
+        }
+        if !self.streams.contains_key(&key) && self.streams.len() >= MAX_STREAMS {
+            self.evict_oldest();
+        }
+
+        let recency = (timestamp, self.segments);
+        let stream = self
+            .streams
+            .entry(key)
+            .or_insert_with(|| Stream::new(recency));
+        self.order.remove(&stream.recency);
+        self.order.insert(recency, key);
+        stream.recency = recency;
+        let ready = stream.segment(seq, flags, payload);
+
+        // A connection whose both halves are closed has nothing more to say
+        if flags.rst {
+            self.remove(&key.reverse());
+        }
+        let stream = self.streams.get_mut(&key)?;
+        ready.then_some(stream)
+    }
+
+    /// A stream together with the opposite direction of its connection,
+    /// for dissectors that match responses to requests
+    pub fn with_reverse(
+        &mut self,
+        key: &StreamKey,
+    ) -> Option<(&mut Stream<S>, Option<&mut Stream<S>>)> {
+        let reverse = key.reverse();
+        if reverse == *key {
+            // A socket connected to itself
+            return Some((self.streams.get_mut(key)?, None));
+        }
+        match self.streams.get_disjoint_mut([key, &reverse]) {
+            [Some(stream), reverse] => Some((stream, reverse)),
+            [None, _] => None,
+        }
+    }
+
+    fn remove(&mut self, key: &StreamKey) {
+        if let Some(stream) = self.streams.remove(key) {
+            self.order.remove(&stream.recency);
+        }
+    }
+
+    /// Drop closed streams and streams idle past the timeout
+    fn sweep(&mut self, now: Timestamp) {
+        let before = self.streams.len();
+        self.streams
+            .retain(|_, stream| !stream.closed && now.since(stream.recency.0) < IDLE_TIMEOUT);
+        let streams = &self.streams;
+        self.order.retain(|_, key| streams.contains_key(key));
+        debug!(
+            "Reassembly sweep dropped {} streams",
+            before - self.streams.len()
+        );
+    }
+
+    fn evict_oldest(&mut self) {
+        if let Some((_, key)) = self.order.pop_first() {
+            self.streams.remove(&key);
+        }
+    }
+}
+
+impl<S: Default> Default for TcpReassembler<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> StreamKey {
+        client(40000)
+    }
+
+    fn client(src_port: u16) -> StreamKey {
+        StreamKey {
+            src: "10.0.0.1".parse().unwrap(),
+            src_port,
+            dst: "10.0.0.2".parse().unwrap(),
+            dst_port: 80,
+        }
+    }
+
+    fn data_flags() -> TcpFlags {
+        TcpFlags::from_bits(0x18)
+    }
+
+    #[test]
+    fn test_in_order_and_retransmission() {
+        let mut streams: TcpReassembler<()> = TcpReassembler::new();
+        let ts = Timestamp::new(1, 0);
+
+        assert!(
+            streams
+                .push(key(), 99, TcpFlags::from_bits(0x02), &[], ts)
+                .is_none()
+        );
+        let stream = streams.push(key(), 100, data_flags(), b"GET ", ts).unwrap();
+        assert_eq!(stream.data(), b"GET ");
+
+        // Full retransmission adds nothing, partial overlap adds only the new tail
+        assert!(
+            streams
+                .push(key(), 100, data_flags(), b"GET ", ts)
+                .is_none()
+        );
+        let stream = streams
+            .push(key(), 102, data_flags(), b"T / HTTP", ts)
+            .unwrap();
+        assert_eq!(stream.data(), b"GET / HTTP");
+
+        stream.consume(4);
+        assert_eq!(stream.data(), b"/ HTTP");
+    }
+
+    #[test]
+    fn test_out_of_order_segments_wait_for_gap() {
+        let mut streams: TcpReassembler<()> = TcpReassembler::new();
+        let ts = Timestamp::new(1, 0);
+
+        streams.push(key(), 1000, data_flags(), b"abc", ts).unwrap();
+        assert!(
+            streams
+                .push(key(), 1006, data_flags(), b"ghi", ts)
+                .is_none()
+        );
+        assert!(
+            streams
+                .push(key(), 1009, data_flags(), b"jkl", ts)
+                .is_none()
+        );
+        let stream = streams.push(key(), 1003, data_flags(), b"def", ts).unwrap();
+        assert_eq!(stream.data(), b"abcdefghijkl");
+        assert!(!stream.take_gap());
+    }
+
+    #[test]
+    fn test_sequence_wraparound() {
+        let mut streams: TcpReassembler<()> = TcpReassembler::new();
+        let ts = Timestamp::new(1, 0);
+
+        streams
+            .push(key(), u32::MAX - 1, data_flags(), b"ab", ts)
+            .unwrap();
+        assert!(streams.push(key(), 2, data_flags(), b"ef", ts).is_none());
+        let stream = streams.push(key(), 0, data_flags(), b"cd", ts).unwrap();
+        assert_eq!(stream.data(), b"abcdef");
+    }
+
+    #[test]
+    fn test_evicts_least_recently_seen() {
+        let mut streams: TcpReassembler<()> = TcpReassembler::new();
+        let syn = TcpFlags::from_bits(0x02);
+
+        for i in 0..MAX_STREAMS {
+            let ts = Timestamp::new(1, i as u32);
+            streams.push(client(i as u16), 0, syn, &[], ts);
+        }
+        // Seeing the first stream again moves it to the back of the queue
+        let ts = Timestamp::new(2, 0);
+        streams.push(client(0), 1, data_flags(), b"a", ts);
+        streams.push(client(u16::MAX), 0, syn, &[], ts);
+
+        assert_eq!(streams.streams.len(), MAX_STREAMS);
+        assert_eq!(streams.order.len(), MAX_STREAMS);
+        assert!(streams.streams.contains_key(&client(0)));
+        assert!(!streams.streams.contains_key(&client(1)));
+        assert_eq!(streams.order.first_key_value().unwrap().1, &client(2));
+    }
+}
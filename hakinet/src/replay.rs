@@ -0,0 +1,532 @@
+//! Sending the frames of a capture file out of an interface, like tcpreplay
+
+use anyhow::{Result, anyhow};
+use colored::*;
+use log::{debug, info, warn};
+use pcap::{Activated, Capture, Precision};
+use pnet::util::MacAddr;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::dissect::{
+    LINKTYPE_ETHERNET, LINKTYPE_IPV4, LINKTYPE_IPV6, LINKTYPE_LINUX_SLL, LINKTYPE_LINUX_SLL2,
+    LINKTYPE_LOOP, LINKTYPE_NULL, LINKTYPE_RAW,
+};
+use crate::engine;
+use crate::filter::bpf;
+use crate::summary::format_bits;
+use hakinet_common::{Shutdown, Timestamp};
+
+/// Longest stretch spent sleeping before checking for a signal
+const WAIT_SLICE: Duration = Duration::from_millis(100);
+
+/// How fast frames are sent
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pacing {
+    /// Keep the gaps between the recorded timestamps, divided by `multiplier`
+    Original { multiplier: f64 },
+    /// A fixed number of packets per second
+    Pps(f64),
+    /// As fast as the interface accepts them
+    TopSpeed,
+}
+
+impl Default for Pacing {
+    fn default() -> Self {
+        Pacing::Original { multiplier: 1.0 }
+    }
+}
+
+/// Replace one address with another in the IP headers, e.g. `10.0.0.1=192.168.1.1`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpMapping {
+    pub from: IpAddr,
+    pub to: IpAddr,
+}
+
+impl FromStr for IpMapping {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (from, to) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid address mapping '{}', use OLD=NEW", s))?;
+        let mapping = IpMapping {
+            from: from.trim().parse()?,
+            to: to.trim().parse()?,
+        };
+        if mapping.from.is_ipv4() != mapping.to.is_ipv4() {
+            return Err(anyhow!(
+                "Cannot map {} to {} across address families",
+                from,
+                to
+            ));
+        }
+        Ok(mapping)
+    }
+}
+
+/// Header rewriting applied to each frame before it is sent. Checksums
+/// covering the rewritten addresses are updated to match.
+#[derive(Debug, Clone, Default)]
+pub struct Rewrite {
+    pub src_mac: Option<MacAddr>,
+    pub dst_mac: Option<MacAddr>,
+    pub ip_map: Vec<IpMapping>,
+}
+
+impl Rewrite {
+    pub fn is_empty(&self) -> bool {
+        self.src_mac.is_none() && self.dst_mac.is_none() && self.ip_map.is_empty()
+    }
+
+    /// Rewrite a frame of the given link type in place. MAC addresses are
+    /// only rewritten in Ethernet frames.
+    pub fn apply(&self, linktype: i32, frame: &mut [u8]) {
+        if linktype == LINKTYPE_ETHERNET && frame.len() >= 12 {
+            if let Some(mac) = self.dst_mac {
+                frame[0..6].copy_from_slice(&mac.octets());
+            }
+            if let Some(mac) = self.src_mac {
+                frame[6..12].copy_from_slice(&mac.octets());
+            }
+        }
+        if self.ip_map.is_empty() {
+            return;
+        }
+        if let Some(offset) = network_offset(linktype, frame) {
+            self.rewrite_ip(&mut frame[offset..]);
+        }
+    }
+
+    fn mapped(&self, addr: &[u8]) -> Option<IpAddr> {
+        let addr = match addr.len() {
+            4 => IpAddr::from(<[u8; 4]>::try_from(addr).ok()?),
+            _ => IpAddr::from(<[u8; 16]>::try_from(addr).ok()?),
+        };
+        self.ip_map
+            .iter()
+            .find(|mapping| mapping.from == addr)
+            .map(|mapping| mapping.to)
+    }
+
+    fn rewrite_ip(&self, packet: &mut [u8]) {
+        let (addresses, header_len, protocol, is_v6) = match packet.first().map(|b| b >> 4) {
+            Some(4) if packet.len() >= 20 => {
+                let header_len = (packet[0] & 0x0f) as usize * 4;
+                let fragment_offset = u16::from_be_bytes([packet[6], packet[7]]) & 0x1fff;
+                // Later fragments carry no transport header to fix up
+                let protocol = if fragment_offset == 0 { packet[9] } else { 0 };
+                ([12..16, 16..20], header_len, protocol, false)
+            }
+            Some(6) if packet.len() >= 40 => ([8..24, 24..40], 40, packet[6], true),
+            _ => return,
+        };
+
+        let mut changes = Vec::new();
+        for range in addresses {
+            let Some(to) = self.mapped(&packet[range.clone()]) else {
+                continue;
+            };
+            let new = match to {
+                IpAddr::V4(v4) => v4.octets().to_vec(),
+                IpAddr::V6(v6) => v6.octets().to_vec(),
+            };
+            changes.push((packet[range.clone()].to_vec(), new.clone()));
+            packet[range].copy_from_slice(&new);
+        }
+        if changes.is_empty() {
+            return;
+        }
+
+        if !is_v6 {
+            update_checksum_at(packet, 10, &changes);
+        }
+        // TCP, UDP and ICMPv6 checksums cover the addresses through the pseudo-header
+        let offset = match protocol {
+            6 => 16,
+            17 => 6,
+            58 if is_v6 => 2,
+            _ => return,
+        };
+        let Some(segment) = packet.get_mut(header_len..) else {
+            return;
+        };
+        if protocol == 17 && !is_v6 && segment.get(offset..offset + 2) == Some(&[0, 0]) {
+            // UDP over IPv4 without a checksum
+            return;
+        }
+        update_checksum_at(segment, offset, &changes);
+        if protocol == 17 && segment.get(offset..offset + 2) == Some(&[0, 0]) {
+            segment[offset..offset + 2].copy_from_slice(&[0xff, 0xff]);
+        }
+    }
+}
+
+/// Where the IP header starts in a frame, if it carries IPv4 or IPv6
+fn network_offset(linktype: i32, frame: &[u8]) -> Option<usize> {
+    let is_ip = |ethertype: u16| ethertype == 0x0800 || ethertype == 0x86dd;
+    let ethertype_at = |offset: usize| {
+        frame
+            .get(offset..offset + 2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+    match linktype {
+        LINKTYPE_ETHERNET => {
+            let mut offset = 12;
+            loop {
+                match ethertype_at(offset)? {
+                    0x8100 | 0x88a8 | 0x9100 => offset += 4,
+                    ethertype => return is_ip(ethertype).then_some(offset + 2),
+                }
+            }
+        }
+        LINKTYPE_LINUX_SLL => is_ip(ethertype_at(14)?).then_some(16),
+        LINKTYPE_LINUX_SLL2 => is_ip(ethertype_at(0)?).then_some(20),
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => Some(0),
+        LINKTYPE_NULL | LINKTYPE_LOOP => Some(4),
+        _ => None,
+    }
+}
+
+/// Fold address changes into the ones' complement checksum at `offset`
+/// incrementally (RFC 1624), so truncated frames can be fixed up too
+fn update_checksum_at(data: &mut [u8], offset: usize, changes: &[(Vec<u8>, Vec<u8>)]) {
+    let Some(bytes) = data.get(offset..offset + 2) else {
+        return;
+    };
+    let checksum = u16::from_be_bytes([bytes[0], bytes[1]]);
+
+    let mut sum = !checksum as u32;
+    for (old, new) in changes {
+        for (old, new) in old.chunks(2).zip(new.chunks(2)) {
+            sum += !u16::from_be_bytes([old[0], old[1]]) as u32;
+            sum += u16::from_be_bytes([new[0], new[1]]) as u32;
+        }
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    data[offset..offset + 2].copy_from_slice(&(!(sum as u16)).to_be_bytes());
+}
+
+/// When each frame is due, as an offset from the start of the replay
+#[derive(Debug)]
+struct Schedule {
+    pacing: Pacing,
+    /// Offset at which the current pass over the file started
+    base: Duration,
+    first: Option<Timestamp>,
+    last: Duration,
+    sent: u64,
+}
+
+impl Schedule {
+    fn new(pacing: Pacing) -> Self {
+        Schedule {
+            pacing,
+            base: Duration::ZERO,
+            first: None,
+            last: Duration::ZERO,
+            sent: 0,
+        }
+    }
+
+    /// `None` if the frame is due further ahead than a `Duration` reaches,
+    /// which a tiny multiplier or rate can cause
+    fn due(&mut self, timestamp: Timestamp) -> Option<Duration> {
+        let due = match self.pacing {
+            Pacing::Original { multiplier } => {
+                let first = *self.first.get_or_insert(timestamp);
+                let gap = timestamp.since(first).as_secs_f64() / multiplier;
+                self.base
+                    .checked_add(Duration::try_from_secs_f64(gap).ok()?)?
+            }
+            Pacing::Pps(pps) => Duration::try_from_secs_f64(self.sent as f64 / pps).ok()?,
+            Pacing::TopSpeed => Duration::ZERO,
+        };
+        self.sent += 1;
+        // Timestamps going backwards are sent right away rather than early
+        self.last = self.last.max(due);
+        Some(self.last)
+    }
+
+    /// Start the next pass right after the last frame of this one
+    fn next_loop(&mut self) {
+        self.base = self.last;
+        self.first = None;
+    }
+}
+
+/// How a file is replayed
+#[derive(Debug, Clone)]
+pub struct ReplayOptions {
+    pub pacing: Pacing,
+    /// Passes over the file; 0 repeats until interrupted
+    pub loops: u32,
+    pub rewrite: Rewrite,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        ReplayOptions {
+            pacing: Pacing::default(),
+            loops: 1,
+            rewrite: Rewrite::default(),
+        }
+    }
+}
+
+impl ReplayOptions {
+    pub fn with_pacing(mut self, pacing: Pacing) -> Self {
+        self.pacing = pacing;
+        self
+    }
+
+    pub fn with_loops(mut self, loops: u32) -> Self {
+        self.loops = loops;
+        self
+    }
+
+    pub fn with_rewrite(mut self, rewrite: Rewrite) -> Self {
+        self.rewrite = rewrite;
+        self
+    }
+}
+
+/// What a replay sent
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplayStats {
+    pub packets: u64,
+    pub bytes: u64,
+    pub failed: u64,
+    pub loops: u32,
+    pub elapsed: Duration,
+}
+
+impl ReplayStats {
+    pub fn print(&self) {
+        let secs = self.elapsed.as_secs_f64().max(f64::EPSILON);
+        println!(
+            "📤 Sent {} packets ({} bytes) in {:.2}s over {} loop(s)",
+            self.packets.to_string().bright_green(),
+            self.bytes,
+            self.elapsed.as_secs_f64(),
+            self.loops
+        );
+        println!(
+            "   {:.1} pps, {}/s",
+            self.packets as f64 / secs,
+            format_bits(self.bytes as f64 * 8.0 / secs)
+        );
+        if self.failed > 0 {
+            println!(
+                "{}",
+                format!("   ⚠️  {} packets could not be sent", self.failed).bright_red()
+            );
+        }
+    }
+}
+
+/// Send the frames of `file` out of `interface`
+pub fn replay(
+    file: &str,
+    interface: &str,
+    options: &ReplayOptions,
+    shutdown: &Shutdown,
+) -> Result<ReplayStats> {
+    match options.pacing {
+        Pacing::Original { multiplier } if multiplier.is_nan() || multiplier <= 0.0 => {
+            return Err(anyhow!("Speed multiplier must be positive"));
+        }
+        Pacing::Pps(pps) if pps.is_nan() || pps <= 0.0 => {
+            return Err(anyhow!("Packet rate must be positive"));
+        }
+        _ => {}
+    }
+
+    let mut sender = Capture::from_device(interface)
+        .and_then(|capture| capture.open())
+        .map_err(|e| anyhow!("Cannot open {} for sending: {}", interface, e))?;
+
+    let mut schedule = Schedule::new(options.pacing);
+    let mut stats = ReplayStats::default();
+    let start = Instant::now();
+
+    'passes: while options.loops == 0 || stats.loops < options.loops {
+        let mut capture: Capture<dyn Activated> =
+            Capture::from_file_with_precision(file, Precision::Nano)?.into();
+        let linktype = capture.get_datalink();
+        if stats.loops == 0 {
+            let sending = sender.get_datalink();
+            if linktype != sending {
+                warn!(
+                    "{} holds {} frames but {} uses {}; they may not make sense on the wire",
+                    file,
+                    bpf::linktype_name(linktype),
+                    interface,
+                    bpf::linktype_name(sending)
+                );
+            }
+            let rewrites_mac =
+                options.rewrite.src_mac.is_some() || options.rewrite.dst_mac.is_some();
+            if rewrites_mac && linktype.0 != LINKTYPE_ETHERNET {
+                warn!(
+                    "{} holds {} frames without an Ethernet header; --src-mac and --dst-mac have no effect",
+                    file,
+                    bpf::linktype_name(linktype)
+                );
+            }
+        }
+
+        let mut read = 0;
+        loop {
+            let mut packet = match engine::read_packet(&mut capture, Precision::Nano) {
+                Ok(packet) => packet,
+                Err(pcap::Error::NoMorePackets) => break,
+                Err(e) => return Err(e.into()),
+            };
+            read += 1;
+
+            let deadline = schedule
+                .due(packet.timestamp)
+                .and_then(|due| start.checked_add(due))
+                .ok_or_else(|| {
+                    anyhow!("Frames are due too far in the future; raise the speed or packet rate")
+                })?;
+            if !wait_until(deadline, shutdown) {
+                break 'passes;
+            }
+
+            if !options.rewrite.is_empty() {
+                options.rewrite.apply(linktype.0, &mut packet.data);
+            }
+            match sender.sendpacket(packet.data.as_slice()) {
+                Ok(()) => {
+                    stats.packets += 1;
+                    stats.bytes += packet.data.len() as u64;
+                }
+                Err(e) => {
+                    if stats.failed == 0 {
+                        warn!("Failed to send a packet on {}: {}", interface, e);
+                    } else {
+                        debug!("Failed to send a packet on {}: {}", interface, e);
+                    }
+                    stats.failed += 1;
+                }
+            }
+        }
+
+        if read == 0 {
+            warn!("{} holds no packets", file);
+            break;
+        }
+        stats.loops += 1;
+        info!("Finished pass {} over {}", stats.loops, file);
+        schedule.next_loop();
+    }
+
+    stats.elapsed = start.elapsed();
+    Ok(stats)
+}
+
+/// Sleep until `deadline`, returning false if a signal arrives first
+fn wait_until(deadline: Instant, shutdown: &Shutdown) -> bool {
+    loop {
+        if shutdown.is_triggered() {
+            return false;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        std::thread::sleep((deadline - now).min(WAIT_SLICE));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::Frame;
+    use pnet::packet::Packet;
+    use pnet::packet::ipv4::{self, Ipv4Packet};
+    use pnet::packet::udp::{self, MutableUdpPacket, UdpPacket};
+    use std::net::Ipv4Addr;
+
+    fn udp_frame() -> Vec<u8> {
+        let mut frame = Frame::new("10.0.0.1:40000", "10.0.0.2:53").udp(b"ping");
+
+        // Valid checksums to start from
+        let checksum = ipv4::checksum(&Ipv4Packet::new(&frame[14..]).unwrap());
+        frame[24..26].copy_from_slice(&checksum.to_be_bytes());
+        let checksum = udp_checksum(&frame);
+        frame[40..42].copy_from_slice(&checksum.to_be_bytes());
+        frame
+    }
+
+    fn udp_checksum(frame: &[u8]) -> u16 {
+        let ip = Ipv4Packet::new(&frame[14..]).unwrap();
+        let mut segment = MutableUdpPacket::owned(ip.payload().to_vec()).unwrap();
+        segment.set_checksum(0);
+        udp::ipv4_checksum(
+            &segment.to_immutable(),
+            &ip.get_source(),
+            &ip.get_destination(),
+        )
+    }
+
+    #[test]
+    fn test_rewrite() {
+        let rewrite = Rewrite {
+            src_mac: Some("02:aa:bb:cc:dd:ee".parse().unwrap()),
+            dst_mac: None,
+            ip_map: vec!["10.0.0.2=192.168.50.7".parse().unwrap()],
+        };
+        let mut frame = udp_frame();
+        rewrite.apply(LINKTYPE_ETHERNET, &mut frame);
+
+        assert_eq!(&frame[0..6], &[0x02, 0, 0, 0, 0, 2]);
+        assert_eq!(&frame[6..12], &[0x02, 0xaa, 0xbb, 0xcc, 0xdd, 0xee]);
+        let ip = Ipv4Packet::new(&frame[14..]).unwrap();
+        assert_eq!(ip.get_source(), Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(ip.get_destination(), Ipv4Addr::new(192, 168, 50, 7));
+        assert_eq!(ip.get_checksum(), ipv4::checksum(&ip));
+        assert_eq!(
+            UdpPacket::new(ip.payload()).unwrap().get_checksum(),
+            udp_checksum(&frame)
+        );
+
+        assert!("10.0.0.1=::1".parse::<IpMapping>().is_err());
+        assert!("10.0.0.1".parse::<IpMapping>().is_err());
+    }
+
+    #[test]
+    fn test_schedule() {
+        let at = |millis: u64| Timestamp::from(Duration::from_millis(1_000 + millis));
+
+        let mut schedule = Schedule::new(Pacing::Original { multiplier: 2.0 });
+        assert_eq!(schedule.due(at(0)), Some(Duration::ZERO));
+        assert_eq!(schedule.due(at(100)), Some(Duration::from_millis(50)));
+        // Out-of-order timestamps don't go back in time
+        assert_eq!(schedule.due(at(40)), Some(Duration::from_millis(50)));
+        schedule.next_loop();
+        assert_eq!(schedule.due(at(0)), Some(Duration::from_millis(50)));
+        assert_eq!(schedule.due(at(100)), Some(Duration::from_millis(100)));
+
+        let mut schedule = Schedule::new(Pacing::Pps(4.0));
+        let due: Vec<_> = (0..3).map(|i| schedule.due(at(i * 1000))).collect();
+        assert_eq!(due[2], Some(Duration::from_millis(500)));
+
+        let mut schedule = Schedule::new(Pacing::TopSpeed);
+        assert_eq!(schedule.due(at(5_000)), Some(Duration::ZERO));
+
+        // Rates so low that the schedule leaves the range of a Duration
+        let mut schedule = Schedule::new(Pacing::Pps(1e-300));
+        assert_eq!(schedule.due(at(0)), Some(Duration::ZERO));
+        assert_eq!(schedule.due(at(1)), None);
+        let mut schedule = Schedule::new(Pacing::Original { multiplier: 1e-300 });
+        assert_eq!(schedule.due(at(0)), Some(Duration::ZERO));
+        assert_eq!(schedule.due(at(1)), None);
+    }
+}
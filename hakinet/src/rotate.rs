@@ -0,0 +1,201 @@
+//! tcpdump-style output rotation (`-C`, `-G`, `-W`, `-z`) shared by the
+//! pcap and JSON writers
+
+use anyhow::{Result, anyhow};
+use chrono::format::{Item, StrftimeItems};
+use hakinet_common::Timestamp;
+use log::{debug, info, warn};
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
+
+/// When to roll over to a new file and how many to keep
+#[derive(Debug, Clone, Default)]
+pub struct RotationPolicy {
+    /// Roll over once a file reaches this many bytes
+    pub max_bytes: Option<u64>,
+    /// Roll over once a file spans this much capture time
+    pub interval: Option<Duration>,
+    /// Delete the oldest files beyond this many
+    pub max_files: Option<usize>,
+    /// Command run with the path of each finished file
+    pub post_rotate: Option<String>,
+}
+
+impl RotationPolicy {
+    /// Build a policy from the command-line values; `None` when rotation is off
+    pub fn from_args(
+        file_size_mb: Option<u64>,
+        rotate_seconds: Option<u64>,
+        file_count: Option<usize>,
+        post_rotate: Option<String>,
+    ) -> Result<Option<Self>> {
+        if file_size_mb.is_none() && rotate_seconds.is_none() {
+            if file_count.is_some() || post_rotate.is_some() {
+                return Err(anyhow!(
+                    "--file-count and --post-rotate need --file-size or --rotate-seconds"
+                ));
+            }
+            return Ok(None);
+        }
+        if file_size_mb == Some(0) || rotate_seconds == Some(0) || file_count == Some(0) {
+            return Err(anyhow!("Rotation limits must be greater than zero"));
+        }
+
+        Ok(Some(RotationPolicy {
+            // Millions of bytes, as tcpdump -C counts them
+            max_bytes: file_size_mb.map(|mb| mb * 1_000_000),
+            interval: rotate_seconds.map(Duration::from_secs),
+            max_files: file_count,
+            post_rotate,
+        }))
+    }
+}
+
+/// Names successive files from a strftime pattern and retires old ones
+#[derive(Debug)]
+pub struct Rotation {
+    pattern: String,
+    policy: RotationPolicy,
+    /// Capture time of the first packet in the current file
+    opened_at: Option<Timestamp>,
+    /// Files written so far, oldest first, for pruning
+    files: VecDeque<String>,
+    used: HashSet<String>,
+    sequence: usize,
+}
+
+impl Rotation {
+    pub fn new(pattern: &str, policy: RotationPolicy) -> Result<Self> {
+        if StrftimeItems::new(pattern).any(|item| item == Item::Error) {
+            return Err(anyhow!(
+                "Invalid strftime pattern in file name '{}'",
+                pattern
+            ));
+        }
+
+        Ok(Rotation {
+            pattern: pattern.to_string(),
+            policy,
+            opened_at: None,
+            files: VecDeque::new(),
+            used: HashSet::new(),
+            sequence: 0,
+        })
+    }
+
+    /// Whether a packet captured at `timestamp` belongs in a new file, given
+    /// how many bytes the current one holds
+    pub fn is_due(&self, written: u64, timestamp: Timestamp) -> bool {
+        let Some(opened_at) = self.opened_at else {
+            return false;
+        };
+        self.policy.max_bytes.is_some_and(|max| written >= max)
+            || self
+                .policy
+                .interval
+                .is_some_and(|interval| timestamp.since(opened_at) >= interval)
+    }
+
+    /// Path for a file starting at `timestamp`. The pattern is expanded in
+    /// local time; a name already used in this run gets a sequence number.
+    pub fn next_path(&mut self, timestamp: Timestamp) -> String {
+        let mut path = chrono::DateTime::from_timestamp(timestamp.secs as i64, 0)
+            .map(|dt| {
+                dt.with_timezone(&chrono::Local)
+                    .format(&self.pattern)
+                    .to_string()
+            })
+            .unwrap_or_else(|| self.pattern.clone());
+        if self.used.contains(&path) {
+            path = format!("{}{}", path, self.sequence);
+        }
+
+        self.sequence += 1;
+        self.opened_at = Some(timestamp);
+        self.used.insert(path.clone());
+        self.files.push_back(path.clone());
+        path
+    }
+
+    /// Hand a finished file to the post-rotate hook and delete files beyond
+    /// the limit
+    pub fn retire(&mut self, path: &str) {
+        if let Some(command) = &self.policy.post_rotate {
+            match tokio::process::Command::new(command).arg(path).spawn() {
+                Ok(_) => debug!("Started post-rotate command {} {}", command, path),
+                Err(e) => warn!("Cannot run post-rotate command '{}': {}", command, e),
+            }
+        }
+
+        let Some(max_files) = self.policy.max_files else {
+            return;
+        };
+        while self.files.len() > max_files {
+            if let Some(oldest) = self.files.pop_front() {
+                match std::fs::remove_file(&oldest) {
+                    Ok(()) => info!("Removed rotated file: {}", oldest),
+                    Err(e) => warn!("Cannot remove rotated file {}: {}", oldest, e),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RotationPolicy {
+        RotationPolicy::from_args(Some(1), Some(60), Some(2), None)
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_policy_from_args() {
+        assert!(
+            RotationPolicy::from_args(None, None, None, None)
+                .unwrap()
+                .is_none()
+        );
+        assert!(RotationPolicy::from_args(None, None, Some(3), None).is_err());
+        assert!(RotationPolicy::from_args(Some(0), None, None, None).is_err());
+        assert_eq!(policy().max_bytes, Some(1_000_000));
+    }
+
+    #[test]
+    fn test_due_by_size_and_time() {
+        let mut rotation = Rotation::new("capture.pcap", policy()).unwrap();
+        let start = Timestamp::new(1_700_000_000, 0);
+        assert!(!rotation.is_due(5_000_000, start));
+
+        rotation.next_path(start);
+        assert!(!rotation.is_due(999_999, Timestamp::new(1_700_000_059, 0)));
+        assert!(rotation.is_due(1_000_000, start));
+        assert!(rotation.is_due(0, Timestamp::new(1_700_000_060, 0)));
+    }
+
+    #[test]
+    fn test_names_and_pruning() {
+        let dir = std::env::temp_dir().join(format!("hakinet-rotate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let pattern = dir.join("capture-%Y.pcap").to_string_lossy().into_owned();
+        assert!(Rotation::new("bad-%Q", policy()).is_err());
+
+        let mut rotation = Rotation::new(&pattern, policy()).unwrap();
+        let ts = Timestamp::new(1_700_000_000, 0);
+        let paths: Vec<String> = (0..3).map(|_| rotation.next_path(ts)).collect();
+        assert!(paths[0].ends_with("capture-2023.pcap"));
+        assert!(paths[1].ends_with("capture-2023.pcap1"));
+        assert!(paths[2].ends_with("capture-2023.pcap2"));
+
+        for path in &paths {
+            std::fs::write(path, b"x").unwrap();
+        }
+        rotation.retire(&paths[1]);
+        assert!(!std::path::Path::new(&paths[0]).exists());
+        assert!(std::path::Path::new(&paths[2]).exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
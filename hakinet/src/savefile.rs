@@ -0,0 +1,409 @@
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+use log::info;
+use pcap::Precision;
+use tokio::fs::File;
+use tokio::io::{AsyncWriteExt, BufWriter};
+
+use crate::capture::RawPacket;
+use crate::rotate::{Rotation, RotationPolicy};
+
+/// On-disk format for saved captures
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SaveFormat {
+    /// Classic libpcap format
+    Pcap,
+    /// pcapng with interface metadata and comments
+    Pcapng,
+}
+
+/// Metadata about the interface the frames were captured on
+#[derive(Debug, Clone)]
+pub struct InterfaceMeta {
+    pub name: String,
+    pub description: Option<String>,
+    pub linktype: i32,
+    pub snaplen: u32,
+    pub precision: Precision,
+    pub filter: Option<String>,
+}
+
+const PCAP_MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d_0d0a;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+const PCAPNG_ENHANCED_PACKET: u32 = 0x0000_0006;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+
+const OPT_END: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+const SHB_OS: u16 = 3;
+const SHB_USERAPPL: u16 = 4;
+const IF_NAME: u16 = 2;
+const IF_DESCRIPTION: u16 = 3;
+const IF_TSRESOL: u16 = 9;
+const IF_FILTER: u16 = 11;
+
+/// Writes raw frames to a pcap or pcapng file, optionally rotating through
+/// a series of files
+pub struct SavefileWriter {
+    /// `None` until the first packet when rotating
+    writer: Option<BufWriter<File>>,
+    /// File header, repeated at the start of every rotated file
+    header: Vec<u8>,
+    format: SaveFormat,
+    /// Timestamp resolution by interface index
+    precisions: Vec<Precision>,
+    path: String,
+    rotation: Option<Rotation>,
+    file_bytes: u64,
+    packet_count: usize,
+}
+
+impl SavefileWriter {
+    /// Create the writer for frames from `interfaces`, indexed as in
+    /// `RawPacket::interface`. With a rotation policy, `path` is a strftime
+    /// pattern and files are opened as packets arrive.
+    pub async fn create(
+        path: &str,
+        format: SaveFormat,
+        interfaces: &[InterfaceMeta],
+        comment: Option<&str>,
+        rotation: Option<RotationPolicy>,
+    ) -> Result<Self> {
+        let (header, precisions) = match format {
+            SaveFormat::Pcap => {
+                let interface = merge_for_pcap(interfaces)?;
+                (
+                    pcap_file_header(&interface),
+                    vec![interface.precision; interfaces.len()],
+                )
+            }
+            SaveFormat::Pcapng => {
+                let mut header = pcapng_section_header(comment);
+                for interface in interfaces {
+                    header.extend(pcapng_interface_block(interface));
+                }
+                (header, interfaces.iter().map(|i| i.precision).collect())
+            }
+        };
+
+        let mut writer = SavefileWriter {
+            writer: None,
+            header,
+            format,
+            precisions,
+            path: path.to_string(),
+            rotation: rotation
+                .map(|policy| Rotation::new(path, policy))
+                .transpose()?,
+            file_bytes: 0,
+            packet_count: 0,
+        };
+        if writer.rotation.is_none() {
+            writer.open(path).await?;
+        }
+        Ok(writer)
+    }
+
+    async fn open(&mut self, path: &str) -> Result<()> {
+        let file = File::create(path).await?;
+        let mut writer = BufWriter::new(file);
+        writer.write_all(&self.header).await?;
+
+        info!("Created {:?} file: {}", self.format, path);
+        self.writer = Some(writer);
+        self.path = path.to_string();
+        self.file_bytes = self.header.len() as u64;
+        Ok(())
+    }
+
+    /// Flush the current file and pass it to the rotation hook
+    async fn finish_file(&mut self) -> Result<()> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush().await?;
+            if let Some(rotation) = self.rotation.as_mut() {
+                rotation.retire(&self.path);
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn write_packet(&mut self, packet: &RawPacket) -> Result<()> {
+        let due = match &self.rotation {
+            Some(rotation) => {
+                self.writer.is_none() || rotation.is_due(self.file_bytes, packet.timestamp)
+            }
+            None => false,
+        };
+        if due {
+            self.finish_file().await?;
+            if let Some(rotation) = self.rotation.as_mut() {
+                let path = rotation.next_path(packet.timestamp);
+                self.open(&path).await?;
+            }
+        }
+
+        let precision = self
+            .precisions
+            .get(packet.interface)
+            .copied()
+            .unwrap_or(Precision::Micro);
+        let record = match self.format {
+            SaveFormat::Pcap => pcap_record(packet, precision),
+            SaveFormat::Pcapng => pcapng_packet_block(packet, precision),
+        };
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+        writer.write_all(&record).await?;
+        self.file_bytes += record.len() as u64;
+        self.packet_count += 1;
+
+        if self.packet_count.is_multiple_of(10) {
+            writer.flush().await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn close(&mut self) -> Result<()> {
+        if self.writer.is_none() {
+            return Ok(());
+        }
+        self.finish_file().await?;
+        info!("Saved {} frames to: {}", self.packet_count, self.path);
+        println!("📦 Capture saved to: {}", self.path);
+        Ok(())
+    }
+}
+
+/// A pcap file has one header for all frames, so the interfaces must share
+/// a link type; the header gets the largest snaplen and the coarsest
+/// timestamp resolution
+fn merge_for_pcap(interfaces: &[InterfaceMeta]) -> Result<InterfaceMeta> {
+    let Some(first) = interfaces.first() else {
+        bail!("No interfaces to save");
+    };
+    if interfaces.iter().any(|i| i.linktype != first.linktype) {
+        bail!("The interfaces have different link types; save them with --format pcapng");
+    }
+    let mut merged = first.clone();
+    merged.snaplen = interfaces
+        .iter()
+        .map(|i| i.snaplen)
+        .max()
+        .unwrap_or(first.snaplen);
+    if interfaces.iter().any(|i| i.precision == Precision::Micro) {
+        merged.precision = Precision::Micro;
+    }
+    Ok(merged)
+}
+
+fn pcap_file_header(interface: &InterfaceMeta) -> Vec<u8> {
+    let magic = match interface.precision {
+        Precision::Micro => PCAP_MAGIC_MICROS,
+        Precision::Nano => PCAP_MAGIC_NANOS,
+    };
+
+    let mut header = Vec::with_capacity(24);
+    header.extend_from_slice(&magic.to_le_bytes());
+    header.extend_from_slice(&2u16.to_le_bytes());
+    header.extend_from_slice(&4u16.to_le_bytes());
+    header.extend_from_slice(&0i32.to_le_bytes()); // thiszone
+    header.extend_from_slice(&0u32.to_le_bytes()); // sigfigs
+    header.extend_from_slice(&interface.snaplen.to_le_bytes());
+    header.extend_from_slice(&(interface.linktype as u32).to_le_bytes());
+    header
+}
+
+fn pcap_record(packet: &RawPacket, precision: Precision) -> Vec<u8> {
+    let fraction = match precision {
+        Precision::Micro => packet.timestamp.nanos / 1000,
+        Precision::Nano => packet.timestamp.nanos,
+    };
+
+    let mut record = Vec::with_capacity(16 + packet.data.len());
+    record.extend_from_slice(&(packet.timestamp.secs as u32).to_le_bytes());
+    record.extend_from_slice(&fraction.to_le_bytes());
+    record.extend_from_slice(&(packet.data.len() as u32).to_le_bytes());
+    record.extend_from_slice(&packet.len.to_le_bytes());
+    record.extend_from_slice(&packet.data);
+    record
+}
+
+fn pcapng_section_header(comment: Option<&str>) -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes());
+    body.extend_from_slice(&1u16.to_le_bytes());
+    body.extend_from_slice(&0u16.to_le_bytes());
+    body.extend_from_slice(&(-1i64).to_le_bytes()); // section length unknown
+
+    if let Some(comment) = comment {
+        push_option(&mut body, OPT_COMMENT, comment.as_bytes());
+    }
+    push_option(&mut body, SHB_OS, std::env::consts::OS.as_bytes());
+    push_option(
+        &mut body,
+        SHB_USERAPPL,
+        concat!("hakinet ", env!("CARGO_PKG_VERSION")).as_bytes(),
+    );
+    push_option(&mut body, OPT_END, &[]);
+
+    pcapng_block(PCAPNG_SECTION_HEADER, &body)
+}
+
+fn pcapng_interface_block(interface: &InterfaceMeta) -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&(interface.linktype as u16).to_le_bytes());
+    body.extend_from_slice(&0u16.to_le_bytes());
+    body.extend_from_slice(&interface.snaplen.to_le_bytes());
+
+    push_option(&mut body, IF_NAME, interface.name.as_bytes());
+    if let Some(description) = &interface.description {
+        push_option(&mut body, IF_DESCRIPTION, description.as_bytes());
+    }
+    let tsresol = match interface.precision {
+        Precision::Micro => 6,
+        Precision::Nano => 9,
+    };
+    push_option(&mut body, IF_TSRESOL, &[tsresol]);
+    if let Some(filter) = &interface.filter {
+        // First byte 0 marks a libpcap filter string
+        let mut value = vec![0u8];
+        value.extend_from_slice(filter.as_bytes());
+        push_option(&mut body, IF_FILTER, &value);
+    }
+    push_option(&mut body, OPT_END, &[]);
+
+    pcapng_block(PCAPNG_INTERFACE_DESCRIPTION, &body)
+}
+
+fn pcapng_packet_block(packet: &RawPacket, precision: Precision) -> Vec<u8> {
+    let duration = packet.timestamp.as_duration();
+    let units = match precision {
+        Precision::Micro => duration.as_micros() as u64,
+        Precision::Nano => duration.as_nanos() as u64,
+    };
+
+    let mut body = Vec::with_capacity(20 + packet.data.len() + 3);
+    body.extend_from_slice(&(packet.interface as u32).to_le_bytes());
+    body.extend_from_slice(&((units >> 32) as u32).to_le_bytes());
+    body.extend_from_slice(&(units as u32).to_le_bytes());
+    body.extend_from_slice(&(packet.data.len() as u32).to_le_bytes());
+    body.extend_from_slice(&packet.len.to_le_bytes());
+    body.extend_from_slice(&packet.data);
+    pad_to_word(&mut body);
+
+    pcapng_block(PCAPNG_ENHANCED_PACKET, &body)
+}
+
+fn pcapng_block(block_type: u32, body: &[u8]) -> Vec<u8> {
+    let total_len = (body.len() + 12) as u32;
+    let mut block = Vec::with_capacity(total_len as usize);
+    block.extend_from_slice(&block_type.to_le_bytes());
+    block.extend_from_slice(&total_len.to_le_bytes());
+    block.extend_from_slice(body);
+    block.extend_from_slice(&total_len.to_le_bytes());
+    block
+}
+
+fn push_option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend_from_slice(&code.to_le_bytes());
+    body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    body.extend_from_slice(value);
+    pad_to_word(body);
+}
+
+fn pad_to_word(buf: &mut Vec<u8>) {
+    while !buf.len().is_multiple_of(4) {
+        buf.push(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hakinet_common::Timestamp;
+
+    fn sample_packet() -> RawPacket {
+        RawPacket {
+            timestamp: Timestamp::new(1_700_000_000, 123_456_789),
+            len: 60,
+            data: vec![0xaa; 5],
+            interface: 0,
+        }
+    }
+
+    fn sample_interface() -> InterfaceMeta {
+        InterfaceMeta {
+            name: "eth0".to_string(),
+            description: None,
+            linktype: 1,
+            snaplen: 65535,
+            precision: Precision::Micro,
+            filter: Some("tcp".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_pcap_record_layout() {
+        let header = pcap_file_header(&sample_interface());
+        assert_eq!(header.len(), 24);
+        assert_eq!(&header[..4], &[0xd4, 0xc3, 0xb2, 0xa1]);
+
+        let record = pcap_record(&sample_packet(), Precision::Micro);
+        assert_eq!(record.len(), 16 + 5);
+        assert_eq!(
+            u32::from_le_bytes(record[0..4].try_into().unwrap()),
+            1_700_000_000
+        );
+        assert_eq!(
+            u32::from_le_bytes(record[4..8].try_into().unwrap()),
+            123_456
+        );
+        assert_eq!(u32::from_le_bytes(record[8..12].try_into().unwrap()), 5);
+        assert_eq!(u32::from_le_bytes(record[12..16].try_into().unwrap()), 60);
+
+        let record = pcap_record(&sample_packet(), Precision::Nano);
+        assert_eq!(
+            u32::from_le_bytes(record[4..8].try_into().unwrap()),
+            123_456_789
+        );
+    }
+
+    #[test]
+    fn test_pcapng_blocks_are_aligned() {
+        let blocks = [
+            pcapng_section_header(Some("incident 42")),
+            pcapng_interface_block(&sample_interface()),
+            pcapng_packet_block(&sample_packet(), Precision::Micro),
+        ];
+
+        for block in &blocks {
+            assert!(block.len().is_multiple_of(4));
+            let leading = u32::from_le_bytes(block[4..8].try_into().unwrap());
+            let trailing = u32::from_le_bytes(block[block.len() - 4..].try_into().unwrap());
+            assert_eq!(leading as usize, block.len());
+            assert_eq!(leading, trailing);
+        }
+
+        let epb = &blocks[2];
+        let high = u32::from_le_bytes(epb[12..16].try_into().unwrap()) as u64;
+        let low = u32::from_le_bytes(epb[16..20].try_into().unwrap()) as u64;
+        assert_eq!((high << 32) | low, 1_700_000_000_123_456);
+    }
+
+    #[test]
+    fn test_pcap_needs_one_linktype() {
+        let mut wifi = sample_interface();
+        wifi.name = "wlan0".to_string();
+        wifi.precision = Precision::Nano;
+        let merged = merge_for_pcap(&[sample_interface(), wifi.clone()]).unwrap();
+        assert_eq!(merged.precision, Precision::Micro);
+
+        wifi.linktype = 127;
+        assert!(merge_for_pcap(&[sample_interface(), wifi]).is_err());
+    }
+}
@@ -0,0 +1,186 @@
+//! Live capture statistics: libpcap counters, throughput and drop warnings
+
+use colored::*;
+use log::warn;
+use pcap::Stat;
+use serde::Serialize;
+use std::io::{IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+use crate::summary::format_bits;
+
+/// Counters kept by libpcap and the kernel for a live capture
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CaptureStats {
+    /// Packets that passed the BPF filter
+    pub received: u64,
+    /// Packets lost because the capture buffer was full
+    pub dropped: u64,
+    /// Packets the interface or its driver dropped
+    pub if_dropped: u64,
+}
+
+impl From<Stat> for CaptureStats {
+    fn from(stat: Stat) -> Self {
+        CaptureStats {
+            received: stat.received as u64,
+            dropped: stat.dropped as u64,
+            if_dropped: stat.if_dropped as u64,
+        }
+    }
+}
+
+/// Totals over several interfaces
+impl std::iter::Sum for CaptureStats {
+    fn sum<I: Iterator<Item = CaptureStats>>(iter: I) -> Self {
+        iter.fold(CaptureStats::default(), |total, stats| CaptureStats {
+            received: total.received + stats.received,
+            dropped: total.dropped + stats.dropped,
+            if_dropped: total.if_dropped + stats.if_dropped,
+        })
+    }
+}
+
+impl CaptureStats {
+    /// Counts accumulated since an earlier reading
+    pub fn since(&self, earlier: &CaptureStats) -> CaptureStats {
+        CaptureStats {
+            received: self.received.saturating_sub(earlier.received),
+            dropped: self.dropped.saturating_sub(earlier.dropped),
+            if_dropped: self.if_dropped.saturating_sub(earlier.if_dropped),
+        }
+    }
+
+    /// Share of packets lost, in percent of those seen by the filter
+    pub fn drop_percent(&self) -> f64 {
+        let lost = self.dropped + self.if_dropped;
+        let seen = self.received.max(lost);
+        if seen == 0 {
+            0.0
+        } else {
+            lost as f64 * 100.0 / seen as f64
+        }
+    }
+
+    /// Warn when losses exceed `threshold` percent
+    pub fn warn_if_dropping(&self, threshold: f64, period: &str) {
+        if self.drop_percent() > threshold {
+            warn!(
+                "Dropped {:.1}% of packets {} ({} by the kernel, {} by the interface); \
+                 narrow the filter or enlarge the capture buffer",
+                self.drop_percent(),
+                period,
+                self.dropped,
+                self.if_dropped
+            );
+        }
+    }
+}
+
+/// Periodic throughput report on stderr. On a terminal it is redrawn in
+/// place and cleared before packet lines are printed.
+pub struct StatusLine {
+    interval: Duration,
+    drop_threshold: f64,
+    terminal: bool,
+    shown: bool,
+    last_tick: Instant,
+    last_packets: u64,
+    last_bytes: u64,
+    last_stats: Option<CaptureStats>,
+}
+
+impl StatusLine {
+    pub fn new(interval: Duration, drop_threshold: f64) -> Self {
+        StatusLine {
+            interval,
+            drop_threshold,
+            terminal: std::io::stderr().is_terminal(),
+            shown: false,
+            last_tick: Instant::now(),
+            last_packets: 0,
+            last_bytes: 0,
+            last_stats: None,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Report the rate since the last tick, given running totals of frames
+    /// read, their wire bytes, the latest pcap counters and the queue depth
+    pub fn tick(&mut self, packets: u64, bytes: u64, stats: Option<CaptureStats>, queued: usize) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_tick).as_secs_f64().max(1e-3);
+        let pps = (packets - self.last_packets) as f64 / elapsed;
+        let bps = (bytes - self.last_bytes) as f64 * 8.0 / elapsed;
+        self.last_tick = now;
+        self.last_packets = packets;
+        self.last_bytes = bytes;
+
+        let mut line = format!(
+            "⏱  {:.0} pkt/s  {}/s  total {}",
+            pps,
+            format_bits(bps),
+            packets
+        );
+        if let Some(stats) = stats {
+            line.push_str(&format!(
+                "  drops {}/{}  queue {}",
+                stats.dropped, stats.if_dropped, queued
+            ));
+            if let Some(last) = self.last_stats {
+                let interval = stats.since(&last);
+                if interval.drop_percent() > self.drop_threshold {
+                    self.clear();
+                    interval.warn_if_dropping(self.drop_threshold, "in the last interval");
+                }
+            }
+            self.last_stats = Some(stats);
+        }
+
+        let mut stderr = std::io::stderr().lock();
+        if self.terminal {
+            let _ = write!(stderr, "\r\x1b[K{}", line.bright_black());
+            self.shown = true;
+        } else {
+            let _ = writeln!(stderr, "{}", line);
+        }
+        let _ = stderr.flush();
+    }
+
+    /// Remove the status line so other output starts on a clean line
+    pub fn clear(&mut self) {
+        if self.shown {
+            let mut stderr = std::io::stderr().lock();
+            let _ = write!(stderr, "\r\x1b[K");
+            let _ = stderr.flush();
+            self.shown = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drop_percent() {
+        let start = CaptureStats {
+            received: 1000,
+            dropped: 10,
+            if_dropped: 0,
+        };
+        let now = CaptureStats {
+            received: 2000,
+            dropped: 60,
+            if_dropped: 50,
+        };
+        assert_eq!(start.drop_percent(), 1.0);
+        let interval = now.since(&start);
+        assert_eq!(interval.received, 1000);
+        assert_eq!(interval.drop_percent(), 10.0);
+        assert_eq!(CaptureStats::default().drop_percent(), 0.0);
+    }
+}
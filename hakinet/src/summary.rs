@@ -0,0 +1,429 @@
+//! End-of-capture report: protocol hierarchy, top talkers, sizes and rate
+
+use anyhow::Result;
+use colored::*;
+use hakinet_common::{Layer, PacketInfo, Timestamp, format_bytes, format_duration};
+
+use crate::stats::CaptureStats;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// How many entries the top-N tables keep
+const TOP_N: usize = 10;
+
+/// Packet size buckets, as in Wireshark's Packet Lengths statistics
+const SIZE_BUCKETS: [usize; 10] = [0, 20, 40, 80, 160, 320, 640, 1280, 2560, 5120];
+
+/// Report written at the end of a capture
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptureSummary {
+    pub packets: u64,
+    pub bytes: u64,
+    pub first_seen: Option<Timestamp>,
+    pub last_seen: Option<Timestamp>,
+    /// Seconds between the first and last packet
+    pub duration: f64,
+    pub packets_per_sec: f64,
+    pub bits_per_sec: f64,
+    pub protocols: Vec<ProtocolNode>,
+    pub top_sources: Vec<Endpoint>,
+    pub top_destinations: Vec<Endpoint>,
+    pub top_source_ports: Vec<PortCount>,
+    pub top_destination_ports: Vec<PortCount>,
+    pub sizes: Vec<SizeBucket>,
+    /// libpcap counters of a live capture
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capture_stats: Option<CaptureStats>,
+}
+
+/// One protocol in the hierarchy, counting every packet that contains it
+/// beneath its parent
+#[derive(Debug, Clone, Serialize)]
+pub struct ProtocolNode {
+    pub protocol: String,
+    pub packets: u64,
+    pub bytes: u64,
+    pub children: Vec<ProtocolNode>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Endpoint {
+    pub address: String,
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PortCount {
+    pub protocol: String,
+    pub port: u16,
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SizeBucket {
+    pub min: usize,
+    /// Inclusive upper bound; `None` for the last, open-ended bucket
+    pub max: Option<usize>,
+    pub packets: u64,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Counter {
+    packets: u64,
+    bytes: u64,
+}
+
+impl Counter {
+    fn add(&mut self, bytes: u64) {
+        self.packets += 1;
+        self.bytes += bytes;
+    }
+}
+
+/// Accumulates statistics packet by packet
+#[derive(Default)]
+pub struct SummaryBuilder {
+    total: Counter,
+    first_seen: Option<Timestamp>,
+    last_seen: Option<Timestamp>,
+    protocols: Vec<ProtocolNode>,
+    sources: HashMap<String, Counter>,
+    destinations: HashMap<String, Counter>,
+    source_ports: HashMap<(&'static str, u16), Counter>,
+    destination_ports: HashMap<(&'static str, u16), Counter>,
+    sizes: [u64; SIZE_BUCKETS.len()],
+}
+
+impl SummaryBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, packet: &PacketInfo) {
+        let bytes = packet.length as u64;
+        self.total.add(bytes);
+        self.first_seen = Some(
+            self.first_seen
+                .map_or(packet.timestamp, |t| t.min(packet.timestamp)),
+        );
+        self.last_seen = Some(
+            self.last_seen
+                .map_or(packet.timestamp, |t| t.max(packet.timestamp)),
+        );
+
+        let mut level = &mut self.protocols;
+        for layer in &packet.layers {
+            let name = layer.name();
+            let index = match level.iter().position(|node| node.protocol == name) {
+                Some(index) => index,
+                None => {
+                    level.push(ProtocolNode {
+                        protocol: name.to_string(),
+                        packets: 0,
+                        bytes: 0,
+                        children: Vec::new(),
+                    });
+                    level.len() - 1
+                }
+            };
+            let node = &mut level[index];
+            node.packets += 1;
+            node.bytes += bytes;
+            level = &mut node.children;
+        }
+
+        // Innermost IP header, so tunnelled traffic counts its real endpoints
+        let addresses = packet.layers.iter().rev().find_map(|layer| match layer {
+            Layer::Ipv4(ip) => Some((ip.src.to_string(), ip.dst.to_string())),
+            Layer::Ipv6(ip) => Some((ip.src.to_string(), ip.dst.to_string())),
+            _ => None,
+        });
+        if let Some((src, dst)) = addresses {
+            self.sources.entry(src).or_default().add(bytes);
+            self.destinations.entry(dst).or_default().add(bytes);
+        }
+
+        let ports = packet.layers.iter().find_map(|layer| match layer {
+            Layer::Tcp(tcp) => Some(("TCP", tcp.src_port, tcp.dst_port)),
+            Layer::Udp(udp) => Some(("UDP", udp.src_port, udp.dst_port)),
+            _ => None,
+        });
+        if let Some((protocol, src_port, dst_port)) = ports {
+            self.source_ports
+                .entry((protocol, src_port))
+                .or_default()
+                .add(bytes);
+            self.destination_ports
+                .entry((protocol, dst_port))
+                .or_default()
+                .add(bytes);
+        }
+
+        let bucket = SIZE_BUCKETS
+            .iter()
+            .rposition(|&min| packet.length >= min)
+            .unwrap_or(0);
+        self.sizes[bucket] += 1;
+    }
+
+    pub fn finish(&self) -> CaptureSummary {
+        let elapsed = match (self.first_seen, self.last_seen) {
+            (Some(first), Some(last)) => last.since(first),
+            _ => Duration::ZERO,
+        };
+        let seconds = elapsed.as_secs_f64();
+        let rate = |value: f64| if seconds > 0.0 { value / seconds } else { 0.0 };
+
+        let mut protocols = self.protocols.clone();
+        sort_protocols(&mut protocols);
+
+        CaptureSummary {
+            packets: self.total.packets,
+            bytes: self.total.bytes,
+            first_seen: self.first_seen,
+            last_seen: self.last_seen,
+            duration: seconds,
+            packets_per_sec: rate(self.total.packets as f64),
+            bits_per_sec: rate(self.total.bytes as f64 * 8.0),
+            protocols,
+            top_sources: top_endpoints(&self.sources),
+            top_destinations: top_endpoints(&self.destinations),
+            top_source_ports: top_ports(&self.source_ports),
+            top_destination_ports: top_ports(&self.destination_ports),
+            sizes: SIZE_BUCKETS
+                .iter()
+                .enumerate()
+                .map(|(i, &min)| SizeBucket {
+                    min,
+                    max: SIZE_BUCKETS.get(i + 1).map(|next| next - 1),
+                    packets: self.sizes[i],
+                })
+                .collect(),
+            capture_stats: None,
+        }
+    }
+}
+
+fn sort_protocols(nodes: &mut [ProtocolNode]) {
+    nodes.sort_by(|a, b| b.packets.cmp(&a.packets).then(a.protocol.cmp(&b.protocol)));
+    for node in nodes {
+        sort_protocols(&mut node.children);
+    }
+}
+
+fn top_endpoints(counts: &HashMap<String, Counter>) -> Vec<Endpoint> {
+    let mut endpoints: Vec<Endpoint> = counts
+        .iter()
+        .map(|(address, counter)| Endpoint {
+            address: address.clone(),
+            packets: counter.packets,
+            bytes: counter.bytes,
+        })
+        .collect();
+    endpoints.sort_by(|a, b| b.packets.cmp(&a.packets).then(a.address.cmp(&b.address)));
+    endpoints.truncate(TOP_N);
+    endpoints
+}
+
+fn top_ports(counts: &HashMap<(&'static str, u16), Counter>) -> Vec<PortCount> {
+    let mut ports: Vec<PortCount> = counts
+        .iter()
+        .map(|(&(protocol, port), counter)| PortCount {
+            protocol: protocol.to_string(),
+            port,
+            packets: counter.packets,
+            bytes: counter.bytes,
+        })
+        .collect();
+    ports.sort_by(|a, b| {
+        b.packets
+            .cmp(&a.packets)
+            .then(a.protocol.cmp(&b.protocol))
+            .then(a.port.cmp(&b.port))
+    });
+    ports.truncate(TOP_N);
+    ports
+}
+
+impl CaptureSummary {
+    /// Print the report in human form
+    pub fn print(&self) {
+        println!();
+        println!("{}", "📋 Capture Summary".bright_green().bold());
+        println!(
+            "Packets: {}  Bytes: {}  Duration: {}",
+            self.packets,
+            format_bytes(self.bytes),
+            format_duration(Duration::from_secs_f64(self.duration))
+        );
+        println!(
+            "Rate: {:.1} packets/s, {}/s",
+            self.packets_per_sec,
+            format_bits(self.bits_per_sec)
+        );
+        if let Some(stats) = &self.capture_stats {
+            println!(
+                "Kernel: {} received by filter, {} dropped ({:.2}%), {} dropped by interface",
+                stats.received,
+                stats.dropped,
+                stats.drop_percent(),
+                stats.if_dropped
+            );
+        }
+        if self.packets == 0 {
+            return;
+        }
+
+        println!();
+        println!("{}", "Protocol Hierarchy".bright_blue().bold());
+        self.print_protocols(&self.protocols, 0);
+
+        for (title, endpoints) in [
+            ("Top Sources", &self.top_sources),
+            ("Top Destinations", &self.top_destinations),
+        ] {
+            if endpoints.is_empty() {
+                continue;
+            }
+            println!();
+            println!("{}", title.bright_blue().bold());
+            for endpoint in endpoints {
+                println!(
+                    "  {:<39} {:>8} pkts {:>10}",
+                    endpoint.address,
+                    endpoint.packets,
+                    format_bytes(endpoint.bytes)
+                );
+            }
+        }
+
+        for (title, ports) in [
+            ("Top Source Ports", &self.top_source_ports),
+            ("Top Destination Ports", &self.top_destination_ports),
+        ] {
+            if ports.is_empty() {
+                continue;
+            }
+            println!();
+            println!("{}", title.bright_blue().bold());
+            for port in ports {
+                println!(
+                    "  {:<39} {:>8} pkts {:>10}",
+                    format!("{}/{}", port.protocol, port.port),
+                    port.packets,
+                    format_bytes(port.bytes)
+                );
+            }
+        }
+
+        println!();
+        println!("{}", "Packet Sizes".bright_blue().bold());
+        let widest = self.sizes.iter().map(|b| b.packets).max().unwrap_or(0);
+        for bucket in self.sizes.iter().filter(|b| b.packets > 0) {
+            let range = match bucket.max {
+                Some(max) => format!("{}-{}", bucket.min, max),
+                None => format!("{}+", bucket.min),
+            };
+            let bar = "█".repeat((bucket.packets * 30).div_ceil(widest) as usize);
+            println!(
+                "  {:<10} {:>8} {:>6.1}% {}",
+                range,
+                bucket.packets,
+                bucket.packets as f64 * 100.0 / self.packets as f64,
+                bar.bright_magenta()
+            );
+        }
+    }
+
+    fn print_protocols(&self, nodes: &[ProtocolNode], depth: usize) {
+        for node in nodes {
+            let name = format!("{}{}", "  ".repeat(depth + 1), node.protocol);
+            println!(
+                "{:<30} {:>8} pkts {:>6.1}% {:>10}",
+                name,
+                node.packets,
+                node.packets as f64 * 100.0 / self.packets as f64,
+                format_bytes(node.bytes)
+            );
+            self.print_protocols(&node.children, depth + 1);
+        }
+    }
+
+    /// Write the report as JSON
+    pub async fn save(&self, path: &str) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        tokio::fs::write(path, json).await?;
+        println!("📁 Summary saved to: {}", path);
+        Ok(())
+    }
+}
+
+pub fn format_bits(bits: f64) -> String {
+    const UNITS: [&str; 4] = ["bit", "kbit", "Mbit", "Gbit"];
+    let mut value = bits;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::parse_packet;
+    use crate::dissect::{Dissector, LINKTYPE_ETHERNET};
+    use crate::testutil::Frame;
+
+    fn udp_frame(src: &str, dst: &str, payload: usize) -> Vec<u8> {
+        Frame::new(src, dst).udp(&vec![0; payload])
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut dissector = Dissector::new(LINKTYPE_ETHERNET);
+        let mut builder = SummaryBuilder::new();
+        let frames = [
+            udp_frame("10.0.0.1:40000", "10.0.0.2:9000", 10),
+            udp_frame("10.0.0.1:40000", "10.0.0.3:9000", 100),
+            udp_frame("10.0.0.4:40000", "10.0.0.2:123", 1000),
+        ];
+        for (i, frame) in frames.iter().enumerate() {
+            let packet = parse_packet(&mut dissector, frame, Timestamp::new(10 + i as u64, 0));
+            builder.update(&packet);
+        }
+
+        let summary = builder.finish();
+        assert_eq!(summary.packets, 3);
+        assert_eq!(summary.bytes, 52 + 142 + 1042);
+        assert_eq!(summary.duration, 2.0);
+        assert_eq!(summary.packets_per_sec, 1.5);
+
+        let ethernet = &summary.protocols[0];
+        assert_eq!(
+            (ethernet.protocol.as_str(), ethernet.packets),
+            ("Ethernet", 3)
+        );
+        let udp = &ethernet.children[0].children[0];
+        assert_eq!((udp.protocol.as_str(), udp.bytes), ("UDP", summary.bytes));
+
+        assert_eq!(summary.top_sources[0].address, "10.0.0.1");
+        assert_eq!(summary.top_sources[0].packets, 2);
+        assert_eq!(summary.top_destinations[0].address, "10.0.0.2");
+        assert_eq!(
+            (
+                summary.top_destination_ports[0].port,
+                summary.top_destination_ports[0].packets
+            ),
+            (9000, 2)
+        );
+
+        let counts: Vec<u64> = summary.sizes.iter().map(|b| b.packets).collect();
+        assert_eq!(counts, [0, 0, 1, 1, 0, 0, 1, 0, 0, 0]);
+        assert_eq!(summary.sizes[9].max, None);
+    }
+}
@@ -0,0 +1,78 @@
+//! Hand-built frames shared by the unit tests
+
+use std::net::SocketAddrV4;
+
+/// An Ethernet frame carrying IPv4 from 02:00:00:00:00:01 to
+/// 02:00:00:00:00:02, with checksums left at zero
+pub struct Frame {
+    src: SocketAddrV4,
+    dst: SocketAddrV4,
+    vlan: Option<u16>,
+    ip_options: Vec<u8>,
+}
+
+impl Frame {
+    /// Endpoints as `address:port`, e.g. `"10.0.0.1:40000"`
+    pub fn new(src: &str, dst: &str) -> Self {
+        Frame {
+            src: src.parse().unwrap(),
+            dst: dst.parse().unwrap(),
+            vlan: None,
+            ip_options: Vec::new(),
+        }
+    }
+
+    /// Tag the frame with an 802.1Q header
+    pub fn vlan(mut self, id: u16) -> Self {
+        self.vlan = Some(id);
+        self
+    }
+
+    /// Options appended to the IPv4 header, a multiple of four bytes long
+    pub fn ip_options(mut self, options: &[u8]) -> Self {
+        self.ip_options = options.to_vec();
+        self
+    }
+
+    pub fn tcp(&self, seq: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+        let mut segment = self.ports();
+        segment.extend_from_slice(&seq.to_be_bytes());
+        segment.extend_from_slice(&[0, 0, 0, 0, 0x50, flags, 0xff, 0xff, 0, 0, 0, 0]);
+        segment.extend_from_slice(payload);
+        self.ipv4(6, &segment)
+    }
+
+    pub fn udp(&self, payload: &[u8]) -> Vec<u8> {
+        let mut datagram = self.ports();
+        datagram.extend_from_slice(&((8 + payload.len()) as u16).to_be_bytes());
+        datagram.extend_from_slice(&[0, 0]);
+        datagram.extend_from_slice(payload);
+        self.ipv4(17, &datagram)
+    }
+
+    fn ports(&self) -> Vec<u8> {
+        let mut ports = self.src.port().to_be_bytes().to_vec();
+        ports.extend_from_slice(&self.dst.port().to_be_bytes());
+        ports
+    }
+
+    fn ipv4(&self, protocol: u8, transport: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x02, 0, 0, 0, 0, 2, 0x02, 0, 0, 0, 0, 1];
+        if let Some(id) = self.vlan {
+            frame.extend_from_slice(&[0x81, 0x00]);
+            frame.extend_from_slice(&id.to_be_bytes());
+        }
+        frame.extend_from_slice(&[0x08, 0x00]);
+
+        let header_len = 20 + self.ip_options.len();
+        frame.push(0x40 | (header_len / 4) as u8);
+        frame.push(0);
+        frame.extend_from_slice(&((header_len + transport.len()) as u16).to_be_bytes());
+        frame.extend_from_slice(&[0, 0, 0x40, 0, 64, protocol, 0, 0]);
+        frame.extend_from_slice(&self.src.ip().octets());
+        frame.extend_from_slice(&self.dst.ip().octets());
+        frame.extend_from_slice(&self.ip_options);
+        frame.extend_from_slice(transport);
+        frame
+    }
+}
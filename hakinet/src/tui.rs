@@ -0,0 +1,885 @@
+//! Interactive packet browser: a live packet list with the decoded layers
+//! and a hex dump of the selected packet, for terminals without a GUI
+
+use anyhow::{Result, anyhow};
+use pcap::Precision;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::capture::{PacketCapture, PacketDecoder, RawPacket, TimeFormat, format_time};
+use crate::display::{self, HexDump, Region};
+use crate::engine::CaptureEngine;
+use crate::filter::PacketFilter;
+use crate::stats::CaptureStats;
+use hakinet_common::{PacketInfo, Shutdown};
+
+/// Default number of packets kept in memory; older ones are discarded
+pub const DEFAULT_MAX_PACKETS: usize = 100_000;
+
+/// How often the screen is redrawn while packets arrive
+const REDRAW_PERIOD: Duration = Duration::from_millis(100);
+
+/// How long the input thread waits for a key before checking for shutdown
+const INPUT_POLL: Duration = Duration::from_millis(100);
+
+/// Frames taken from the queue per wakeup, so a burst costs one redraw
+const BATCH: usize = 1024;
+
+/// Settings for the packet browser
+#[derive(Debug, Clone)]
+pub struct TuiSettings {
+    pub display_filter: Option<String>,
+    pub time_format: TimeFormat,
+    pub max_packets: usize,
+}
+
+/// Browse packets from `capture` until the user quits or a signal arrives
+pub async fn run(
+    mut capture: PacketCapture,
+    settings: TuiSettings,
+    shutdown: &Shutdown,
+) -> Result<()> {
+    let mut app = App::new(
+        &capture.source_name(),
+        !capture.is_offline(),
+        capture.precision(),
+        settings,
+    )?;
+    let mut decoder = capture.decoder();
+    let mut engine = capture.spawn_engine()?;
+
+    let (sender, mut events) = mpsc::channel(64);
+    spawn_input(sender)?;
+
+    let mut terminal = ratatui::init();
+    let result = run_loop(
+        &mut terminal,
+        &mut app,
+        &mut engine,
+        &mut decoder,
+        &mut events,
+        shutdown,
+    )
+    .await;
+    ratatui::restore();
+    // Drop the key events first so the input thread winds down as well
+    drop(events);
+    engine.stop().await?;
+    result?;
+
+    println!(
+        "Browsed {} packets from {}",
+        app.next_number - 1,
+        app.source
+    );
+    Ok(())
+}
+
+async fn run_loop(
+    terminal: &mut DefaultTerminal,
+    app: &mut App,
+    engine: &mut CaptureEngine,
+    decoder: &mut PacketDecoder,
+    events: &mut mpsc::Receiver<Event>,
+    shutdown: &Shutdown,
+) -> Result<()> {
+    let mut redraw = tokio::time::interval(REDRAW_PERIOD);
+    redraw.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    let mut dirty = true;
+
+    loop {
+        if dirty {
+            terminal.draw(|frame| app.draw(frame))?;
+            dirty = false;
+        }
+
+        tokio::select! {
+            event = events.recv() => match event {
+                Some(event) => {
+                    if app.handle_event(event) {
+                        break;
+                    }
+                    dirty = true;
+                }
+                None => break,
+            },
+            packet = engine.next_packet(), if !app.finished => match packet {
+                Some(raw) => {
+                    let packet = decoder.decode(&raw);
+                    app.push(raw, packet);
+                    for _ in 0..BATCH {
+                        let Some(raw) = engine.try_next_packet() else {
+                            break;
+                        };
+                        let packet = decoder.decode(&raw);
+                        app.push(raw, packet);
+                    }
+                }
+                None => {
+                    app.finished = true;
+                    dirty = true;
+                }
+            },
+            _ = redraw.tick() => {
+                let stats = engine.stats();
+                dirty |= app.changed || stats != app.stats;
+                app.changed = false;
+                app.stats = stats;
+            }
+            _ = shutdown.wait() => break,
+        }
+    }
+    Ok(())
+}
+
+/// Read terminal events on their own thread, since crossterm blocks
+fn spawn_input(sender: mpsc::Sender<Event>) -> Result<()> {
+    std::thread::Builder::new()
+        .name("input".to_string())
+        .spawn(move || {
+            while !sender.is_closed() {
+                match event::poll(INPUT_POLL) {
+                    Ok(true) => match event::read() {
+                        Ok(event) => {
+                            if sender.blocking_send(event).is_err() {
+                                break;
+                            }
+                        }
+                        Err(_) => break,
+                    },
+                    Ok(false) => {}
+                    Err(_) => break,
+                }
+            }
+        })
+        .map_err(|e| anyhow!("Cannot start input thread: {}", e))?;
+    Ok(())
+}
+
+/// A packet kept for browsing
+struct Entry {
+    number: u64,
+    raw: RawPacket,
+    packet: PacketInfo,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pane {
+    List,
+    Detail,
+    Hex,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Prompt {
+    Filter,
+    Jump,
+}
+
+/// Text being typed into the prompt on the bottom line
+struct Input {
+    prompt: Prompt,
+    text: String,
+}
+
+struct App {
+    source: String,
+    live: bool,
+    precision: Precision,
+    time_format: TimeFormat,
+    max_packets: usize,
+    packets: VecDeque<Entry>,
+    next_number: u64,
+    filter: Option<PacketFilter>,
+    /// Numbers of the stored packets that pass the filter, ascending
+    visible: VecDeque<u64>,
+    /// Index into `visible`
+    selected: Option<usize>,
+    /// Keep the newest packet selected as packets arrive
+    follow: bool,
+    paused: bool,
+    /// Packets that arrived while paused, shown on resume
+    held: VecDeque<Entry>,
+    focus: Pane,
+    list_offset: usize,
+    list_height: usize,
+    detail_scroll: u16,
+    hex_scroll: u16,
+    input: Option<Input>,
+    /// Feedback on the bottom line and whether it is an error
+    message: Option<(String, bool)>,
+    stats: Option<CaptureStats>,
+    /// The source has no more packets
+    finished: bool,
+    /// Packets arrived since the last redraw
+    changed: bool,
+}
+
+impl App {
+    fn new(source: &str, live: bool, precision: Precision, settings: TuiSettings) -> Result<Self> {
+        let filter = settings
+            .display_filter
+            .as_deref()
+            .map(PacketFilter::new)
+            .transpose()?;
+        Ok(App {
+            source: source.to_string(),
+            live,
+            precision,
+            time_format: settings.time_format,
+            max_packets: settings.max_packets.max(1),
+            packets: VecDeque::new(),
+            next_number: 1,
+            filter,
+            visible: VecDeque::new(),
+            selected: None,
+            follow: true,
+            paused: false,
+            held: VecDeque::new(),
+            focus: Pane::List,
+            list_offset: 0,
+            list_height: 1,
+            detail_scroll: 0,
+            hex_scroll: 0,
+            input: None,
+            message: None,
+            stats: None,
+            finished: false,
+            changed: false,
+        })
+    }
+
+    /// Take in a decoded frame
+    fn push(&mut self, raw: RawPacket, packet: PacketInfo) {
+        let entry = Entry {
+            number: self.next_number,
+            raw,
+            packet,
+        };
+        self.next_number += 1;
+        self.changed = true;
+
+        if self.paused {
+            self.held.push_back(entry);
+            if self.held.len() > self.max_packets {
+                self.held.pop_front();
+            }
+        } else {
+            self.store(entry);
+        }
+    }
+
+    fn store(&mut self, entry: Entry) {
+        if self
+            .filter
+            .as_ref()
+            .is_none_or(|filter| filter.matches(&entry.packet))
+        {
+            self.visible.push_back(entry.number);
+            if self.follow {
+                self.select(Some(self.visible.len() - 1));
+            }
+        }
+        self.packets.push_back(entry);
+
+        if self.packets.len() > self.max_packets
+            && let Some(evicted) = self.packets.pop_front()
+            && self.visible.front() == Some(&evicted.number)
+        {
+            self.visible.pop_front();
+            self.list_offset = self.list_offset.saturating_sub(1);
+            if self.visible.is_empty() {
+                self.select(None);
+            } else if let Some(selected) = self.selected {
+                if selected == 0 {
+                    // The selected packet itself is gone
+                    self.select(Some(0));
+                } else {
+                    self.selected = Some(selected - 1);
+                }
+            }
+        }
+    }
+
+    fn entry(&self, number: u64) -> Option<&Entry> {
+        let first = self.packets.front()?.number;
+        self.packets.get(number.checked_sub(first)? as usize)
+    }
+
+    fn selected_entry(&self) -> Option<&Entry> {
+        self.entry(*self.visible.get(self.selected?)?)
+    }
+
+    fn select(&mut self, index: Option<usize>) {
+        if index != self.selected {
+            self.detail_scroll = 0;
+            self.hex_scroll = 0;
+        }
+        self.selected = index;
+    }
+
+    /// Move the selection by `delta` rows, following new packets again once
+    /// the last row is reached
+    fn move_selection(&mut self, delta: isize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let last = self.visible.len() - 1;
+        let current = self.selected.unwrap_or(0);
+        let index = current.saturating_add_signed(delta).min(last);
+        self.select(Some(index));
+        self.follow = index == last;
+    }
+
+    fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        if !self.paused {
+            while let Some(entry) = self.held.pop_front() {
+                self.store(entry);
+            }
+        }
+    }
+
+    fn apply_filter(&mut self, expression: &str) {
+        let filter = if expression.trim().is_empty() {
+            None
+        } else {
+            match PacketFilter::new(expression) {
+                Ok(filter) => Some(filter),
+                Err(e) => {
+                    self.message = Some((e.to_string(), true));
+                    return;
+                }
+            }
+        };
+        let selected_number = self
+            .selected
+            .and_then(|index| self.visible.get(index).copied());
+
+        self.filter = filter;
+        self.visible = self
+            .packets
+            .iter()
+            .filter(|entry| {
+                self.filter
+                    .as_ref()
+                    .is_none_or(|filter| filter.matches(&entry.packet))
+            })
+            .map(|entry| entry.number)
+            .collect();
+        self.message = Some((format!("{} packets match", self.visible.len()), false));
+
+        // Stay on the same packet, or the nearest one after it
+        let index = match selected_number {
+            Some(number) => match self.visible.binary_search(&number) {
+                Ok(index) | Err(index) => index,
+            },
+            None => 0,
+        };
+        self.list_offset = 0;
+        if self.visible.is_empty() {
+            self.select(None);
+        } else if self.follow {
+            self.select(Some(self.visible.len() - 1));
+        } else {
+            self.select(Some(index.min(self.visible.len() - 1)));
+        }
+    }
+
+    fn jump(&mut self, text: &str) {
+        let Ok(number) = text.trim().parse::<u64>() else {
+            self.message = Some((format!("Not a packet number: {}", text.trim()), true));
+            return;
+        };
+        match self.visible.binary_search(&number) {
+            Ok(index) => {
+                self.select(Some(index));
+                self.follow = index + 1 == self.visible.len();
+            }
+            Err(_) if self.entry(number).is_some() => {
+                self.message = Some((
+                    format!("Packet {} is hidden by the display filter", number),
+                    true,
+                ));
+            }
+            Err(_) => {
+                self.message = Some((format!("Packet {} is not in the buffer", number), true));
+            }
+        }
+    }
+
+    /// React to a terminal event; true means quit
+    fn handle_event(&mut self, event: Event) -> bool {
+        match event {
+            Event::Key(key) if key.kind == KeyEventKind::Press => {
+                if self.input.is_some() {
+                    self.handle_input_key(key);
+                    false
+                } else {
+                    self.handle_key(key)
+                }
+            }
+            _ => false,
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return true;
+        }
+        self.message = None;
+        let page = self.list_height.max(1) as isize;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return true,
+            KeyCode::Char(' ') | KeyCode::Char('p') => self.toggle_pause(),
+            KeyCode::Char('/') => {
+                self.input = Some(Input {
+                    prompt: Prompt::Filter,
+                    text: self
+                        .filter
+                        .as_ref()
+                        .map(|filter| filter.expression().to_string())
+                        .unwrap_or_default(),
+                });
+            }
+            KeyCode::Char(':') | KeyCode::Char('g') => {
+                self.input = Some(Input {
+                    prompt: Prompt::Jump,
+                    text: String::new(),
+                });
+            }
+            KeyCode::Tab => {
+                self.focus = match self.focus {
+                    Pane::List => Pane::Detail,
+                    Pane::Detail => Pane::Hex,
+                    Pane::Hex => Pane::List,
+                }
+            }
+            KeyCode::BackTab => {
+                self.focus = match self.focus {
+                    Pane::List => Pane::Hex,
+                    Pane::Detail => Pane::List,
+                    Pane::Hex => Pane::Detail,
+                }
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.scroll(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.scroll(1),
+            KeyCode::PageUp => self.scroll(-page),
+            KeyCode::PageDown => self.scroll(page),
+            KeyCode::Home => self.move_selection(isize::MIN),
+            KeyCode::End | KeyCode::Char('G') => self.move_selection(isize::MAX),
+            _ => {}
+        }
+        false
+    }
+
+    /// Arrow keys move the selection in the list and scroll the other panes
+    fn scroll(&mut self, delta: isize) {
+        let scroll = |offset: u16| (offset as isize + delta).clamp(0, u16::MAX as isize) as u16;
+        match self.focus {
+            Pane::List => self.move_selection(delta),
+            Pane::Detail => self.detail_scroll = scroll(self.detail_scroll),
+            Pane::Hex => self.hex_scroll = scroll(self.hex_scroll),
+        }
+    }
+
+    fn handle_input_key(&mut self, key: KeyEvent) {
+        let Some(input) = self.input.as_mut() else {
+            return;
+        };
+        match key.code {
+            KeyCode::Esc => self.input = None,
+            KeyCode::Enter => {
+                if let Some(input) = self.input.take() {
+                    match input.prompt {
+                        Prompt::Filter => self.apply_filter(&input.text),
+                        Prompt::Jump => self.jump(&input.text),
+                    }
+                }
+            }
+            KeyCode::Backspace => {
+                input.text.pop();
+            }
+            KeyCode::Char(c) => input.text.push(c),
+            _ => {}
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [list, detail, hex, status] = Layout::vertical([
+            Constraint::Percentage(45),
+            Constraint::Percentage(35),
+            Constraint::Min(4),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        self.draw_list(frame, list);
+        self.draw_detail(frame, detail);
+        self.draw_hex(frame, hex);
+        self.draw_status(frame, status);
+    }
+
+    fn block(&self, pane: Pane, title: String) -> Block<'static> {
+        let style = if self.focus == pane {
+            Style::new().fg(Color::Cyan)
+        } else {
+            Style::new().fg(Color::DarkGray)
+        };
+        Block::bordered().border_style(style).title(title)
+    }
+
+    fn draw_list(&mut self, frame: &mut Frame, area: Rect) {
+        // Borders and the header row
+        self.list_height = area.height.saturating_sub(3).max(1) as usize;
+        if let Some(selected) = self.selected {
+            if selected < self.list_offset {
+                self.list_offset = selected;
+            } else if selected >= self.list_offset + self.list_height {
+                self.list_offset = selected + 1 - self.list_height;
+            }
+        }
+
+        // Packets name their interface when several are captured at once
+        let interfaces = self
+            .packets
+            .front()
+            .is_some_and(|entry| entry.packet.interface.is_some());
+        let rows: Vec<Row> = self
+            .visible
+            .iter()
+            .skip(self.list_offset)
+            .take(self.list_height)
+            .filter_map(|number| self.entry(*number))
+            .map(|entry| self.row(entry, interfaces))
+            .collect();
+
+        let time_width = match self.time_format {
+            TimeFormat::Absolute => 18,
+            TimeFormat::Date => 29,
+            TimeFormat::Epoch => 20,
+            TimeFormat::Delta | TimeFormat::Relative => 14,
+        };
+        let mut header = vec!["No.", "Time"];
+        let mut widths = vec![Constraint::Length(7), Constraint::Length(time_width)];
+        if interfaces {
+            header.push("Interface");
+            widths.push(Constraint::Length(10));
+        }
+        header.extend(["Source", "Destination", "Protocol", "Length", "Info"]);
+        widths.extend([
+            Constraint::Length(22),
+            Constraint::Length(22),
+            Constraint::Length(8),
+            Constraint::Length(6),
+            Constraint::Min(10),
+        ]);
+        let header = Row::new(header).style(Style::new().add_modifier(Modifier::BOLD));
+        let title = format!(
+            " 🐱 Hakinet — {} ({}) ",
+            self.source,
+            if self.live { "live" } else { "file" }
+        );
+        let table = Table::new(rows, widths)
+            .header(header)
+            .block(self.block(Pane::List, title))
+            .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+
+        let mut state = TableState::default()
+            .with_selected(self.selected.map(|selected| selected - self.list_offset));
+        frame.render_stateful_widget(table, area, &mut state);
+    }
+
+    fn row(&self, entry: &Entry, interfaces: bool) -> Row<'static> {
+        let packet = &entry.packet;
+        let endpoint = |addr: &Option<String>, port: Option<u16>| match (addr, port) {
+            (Some(addr), Some(port)) => format!("{}:{}", addr, port),
+            (Some(addr), None) => addr.clone(),
+            (None, _) => String::new(),
+        };
+        let mut info = packet.info.clone().unwrap_or_default();
+        if !packet.vlan_ids.is_empty() {
+            let ids: Vec<String> = packet.vlan_ids.iter().map(|id| id.to_string()).collect();
+            info = format!("[VLAN {}] {}", ids.join("/"), info);
+        }
+
+        let mut cells = vec![
+            Span::raw(entry.number.to_string()),
+            Span::raw(format_time(packet, self.time_format, self.precision)),
+        ];
+        if interfaces {
+            cells.push(Span::styled(
+                packet.interface.clone().unwrap_or_default(),
+                Style::new().fg(Color::Blue),
+            ));
+        }
+        cells.extend([
+            Span::raw(endpoint(&packet.src_addr, packet.src_port)),
+            Span::raw(endpoint(&packet.dst_addr, packet.dst_port)),
+            Span::styled(
+                packet.protocol.clone(),
+                Style::new().fg(protocol_color(&packet.protocol)),
+            ),
+            Span::raw(packet.length.to_string()),
+            Span::raw(info),
+        ]);
+        Row::new(cells)
+    }
+
+    fn draw_detail(&self, frame: &mut Frame, area: Rect) {
+        let Some(entry) = self.selected_entry() else {
+            frame.render_widget(self.block(Pane::Detail, " Layers ".to_string()), area);
+            return;
+        };
+
+        let lines: Vec<Line> = display::layer_tree(&entry.packet, 2)
+            .into_iter()
+            .map(|line| {
+                if line.depth == 0 {
+                    Line::from(vec![
+                        Span::styled(
+                            line.name,
+                            Style::new()
+                                .fg(Color::LightCyan)
+                                .add_modifier(Modifier::BOLD),
+                        ),
+                        Span::raw(" "),
+                        Span::styled(line.value, Style::new().fg(Color::DarkGray)),
+                    ])
+                } else {
+                    let separator = if line.value.is_empty() { ":" } else { ": " };
+                    Line::from(vec![
+                        Span::raw("  ".repeat(line.depth)),
+                        Span::styled(line.name, Style::new().fg(Color::LightBlue)),
+                        Span::raw(separator),
+                        Span::raw(line.value),
+                    ])
+                }
+            })
+            .collect();
+
+        let title = format!(
+            " Packet {}: {} bytes on wire, {} captured ",
+            entry.number,
+            entry.raw.len,
+            entry.raw.data.len()
+        );
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(self.block(Pane::Detail, title))
+                .scroll((self.detail_scroll, 0)),
+            area,
+        );
+    }
+
+    fn draw_hex(&self, frame: &mut Frame, area: Rect) {
+        let Some(entry) = self.selected_entry() else {
+            frame.render_widget(self.block(Pane::Hex, " Bytes ".to_string()), area);
+            return;
+        };
+
+        let lines: Vec<Line> =
+            display::hex_rows(&entry.raw.data, &entry.packet.layers, HexDump::Frame)
+                .into_iter()
+                .map(|row| {
+                    let mut spans = vec![Span::styled(
+                        format!("0x{:04x}:", row.offset),
+                        Style::new().fg(Color::DarkGray),
+                    )];
+                    for (i, (byte, region)) in row.bytes.iter().enumerate() {
+                        if i % 2 == 0 {
+                            spans.push(Span::raw(" "));
+                        }
+                        spans.push(Span::styled(format!("{:02x}", byte), region_style(*region)));
+                    }
+                    spans.push(Span::raw(" ".repeat(row.padding() + 2)));
+                    for (byte, region) in &row.bytes {
+                        spans.push(Span::styled(
+                            display::printable(*byte).to_string(),
+                            region_style(*region),
+                        ));
+                    }
+                    Line::from(spans)
+                })
+                .collect();
+
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(self.block(Pane::Hex, " Bytes ".to_string()))
+                .scroll((self.hex_scroll, 0)),
+            area,
+        );
+    }
+
+    fn draw_status(&self, frame: &mut Frame, area: Rect) {
+        if let Some(input) = &self.input {
+            let label = match input.prompt {
+                Prompt::Filter => "Display filter: ",
+                Prompt::Jump => "Go to packet: ",
+            };
+            let line = Line::from(vec![
+                Span::styled(label, Style::new().fg(Color::Yellow)),
+                Span::raw(input.text.clone()),
+                Span::styled("█", Style::new().fg(Color::Yellow)),
+            ]);
+            frame.render_widget(Paragraph::new(line), area);
+            return;
+        }
+
+        let state = if self.paused {
+            Span::styled(
+                format!(" ⏸ PAUSED (+{}) ", self.held.len()),
+                Style::new().fg(Color::Black).bg(Color::Yellow),
+            )
+        } else if self.finished {
+            Span::styled(" ■ DONE ", Style::new().fg(Color::Black).bg(Color::Blue))
+        } else if self.live {
+            Span::styled(" ▶ LIVE ", Style::new().fg(Color::Black).bg(Color::Green))
+        } else {
+            Span::styled(
+                " ▶ READING ",
+                Style::new().fg(Color::Black).bg(Color::Green),
+            )
+        };
+        let mut spans = vec![
+            state,
+            Span::raw(format!(
+                " {} shown / {} packets",
+                self.visible.len(),
+                self.next_number - 1
+            )),
+        ];
+        if let Some(stats) = self.stats {
+            spans.push(Span::raw(format!(
+                "  drops {}/{}",
+                stats.dropped, stats.if_dropped
+            )));
+        }
+        if let Some(filter) = &self.filter {
+            spans.push(Span::styled(
+                format!("  filter: {}", filter.expression()),
+                Style::new().fg(Color::Cyan),
+            ));
+        }
+        match &self.message {
+            Some((message, true)) => spans.push(Span::styled(
+                format!("  {}", message),
+                Style::new().fg(Color::Red),
+            )),
+            Some((message, false)) => spans.push(Span::raw(format!("  {}", message))),
+            None => spans.push(Span::styled(
+                "  q quit  space pause  / filter  g go to  tab pane",
+                Style::new().fg(Color::DarkGray),
+            )),
+        }
+        frame.render_widget(Paragraph::new(Line::from(spans)), area);
+    }
+}
+
+/// The console colors of the packet list, as in `PacketCapture`
+fn protocol_color(protocol: &str) -> Color {
+    match protocol {
+        "TCP" => Color::LightRed,
+        "UDP" => Color::LightBlue,
+        "ICMP" => Color::LightYellow,
+        "ARP" => Color::LightGreen,
+        "DNS" => Color::LightCyan,
+        "HTTP" => Color::Green,
+        "TLS" => Color::Magenta,
+        _ => Color::Reset,
+    }
+}
+
+fn region_style(region: Region) -> Style {
+    match region {
+        Region::Link => Style::new().fg(Color::LightBlue),
+        Region::Network => Style::new().fg(Color::LightGreen),
+        Region::Transport => Style::new().fg(Color::LightYellow),
+        Region::Payload => Style::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::read_packet;
+    use pcap::Capture;
+
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/sample.pcap");
+
+    fn app(max_packets: usize) -> App {
+        let settings = TuiSettings {
+            display_filter: None,
+            time_format: TimeFormat::Relative,
+            max_packets,
+        };
+        let mut app = App::new("sample.pcap", false, Precision::Nano, settings).unwrap();
+
+        // TCP, DNS over UDP, ARP
+        let mut capture: Capture<dyn pcap::Activated> =
+            Capture::from_file_with_precision(FIXTURE, Precision::Nano)
+                .unwrap()
+                .into();
+        let mut decoder = PacketDecoder::new(capture.get_datalink());
+        while let Ok(raw) = read_packet(&mut capture, Precision::Nano) {
+            let packet = decoder.decode(&raw);
+            app.push(raw, packet);
+        }
+        app
+    }
+
+    #[test]
+    fn test_filter_and_jump() {
+        let mut app = app(DEFAULT_MAX_PACKETS);
+        assert_eq!(app.visible, [1, 2, 3]);
+        assert_eq!(app.selected, Some(2));
+
+        app.move_selection(-1);
+        assert!(!app.follow);
+        app.apply_filter("udp or arp");
+        assert_eq!(app.visible, [2, 3]);
+        assert_eq!(app.selected_entry().unwrap().number, 2);
+
+        app.jump("1");
+        assert!(app.message.as_ref().unwrap().0.contains("hidden"));
+        app.jump("3");
+        assert_eq!(app.selected_entry().unwrap().number, 3);
+
+        app.apply_filter("tcp.port ==");
+        assert!(app.message.as_ref().unwrap().1);
+        assert_eq!(app.visible, [2, 3]);
+    }
+
+    #[test]
+    fn test_pause_and_eviction() {
+        let mut app = app(2);
+        assert_eq!(app.visible, [2, 3]);
+        assert_eq!(app.selected_entry().unwrap().number, 3);
+        app.jump("1");
+        assert!(
+            app.message
+                .as_ref()
+                .unwrap()
+                .0
+                .contains("not in the buffer")
+        );
+
+        app.toggle_pause();
+        let raw = app.packets[0].raw.clone();
+        let packet = app.packets[0].packet.clone();
+        app.push(raw, packet);
+        assert_eq!(app.visible, [2, 3]);
+        assert_eq!(app.held.len(), 1);
+
+        app.toggle_pause();
+        assert_eq!(app.visible, [3, 4]);
+        assert_eq!(app.selected_entry().unwrap().number, 4);
+    }
+}